@@ -12,11 +12,18 @@ use std::str::FromStr;
 use std::{io, fs};
 use std::path::Path;
 
+use color_eyre::eyre::eyre;
 use structopt::StructOpt;
 
 use abogado_common::{Docx, get::{self, SourceFromFileError, SourceFromGoogleDocsError}};
+use abogado_lex::Literate;
+use abogado_parse::ast::{tree, Module};
+use abogado_parse::pretty::{self, PrettyOptions};
+use abogado_passes::pipeline::{PassPipeline, PipelineError, Severity};
 use avocadocx_interpreter;
 
+mod loader;
+
 // TODO: maybe have a driver crate?
 
 #[derive(Debug)]
@@ -44,7 +51,7 @@ impl AvocadoxInput {
 
     async fn from_google_docs(id: &str) -> Result<Self, SourceFromGoogleDocsError> {
         get::from_google_docs(id).await.map(|doc| AvocadoxInput::DocxSource {
-            name: "<main module>".to_string(), // TODO: fetch the document name!
+            name: id.to_string(),
             doc,
         })
     }
@@ -71,12 +78,19 @@ impl FromStr for Input {
     fn from_str(s: &str) -> Result<Self, String> {
         use Input::*;
 
+        const LINK: &str = "docs.google.com/document/d/";
+
         let res = match s {
             _ if s.ends_with(".cado") => CadoFile(s.to_string()),
             _ if s.ends_with(".docx") => DocxFile(s.to_string()),
             id if id.len() == 44 => GoogleDocId(id.to_string()),
+            // i.e. `https://docs.google.com/document/d/<id>/edit`
+            link if link.contains(LINK) => {
+                let id = link.split(LINK).nth(1).unwrap().split('/').next().unwrap();
+                GoogleDocId(id.to_string())
+            },
             _ => return Err(
-                format!("invalid input source: {}; must be a Google Sheet Id or a file path", s)
+                format!("invalid input source: {}; must be a Google Doc Id or link or a file path", s)
             )
         };
 
@@ -92,12 +106,226 @@ enum Args {
         ///
         /// Can be a file (.cado or .docx) or a Google Doc Id.
         input: Input,
+
+        /// Section (heading) to use as the entry point.
+        ///
+        /// Either a section's title or, if titles repeat, a path of titles
+        /// through the table of contents: `Setup/Helpers`. Everything before
+        /// the section runs first, as a prelude; sections after it don't run.
+        #[structopt(long)]
+        section: Option<String>,
+
+        /// Print the program instead of running it: `tokens`, `ast`,
+        /// `tokens-json` or `ast-json`.
+        ///
+        /// `ast` starts with the types inferred for the program's procedures
+        /// and top-level variables.
+        ///
+        /// Only the input itself is printed, not the modules it imports.
+        #[structopt(long)]
+        emit: Option<Emit>,
+
+        #[structopt(flatten)]
+        code: CodeOptions,
+
+        #[structopt(flatten)]
+        passes: PassOptions,
     },
     Compile { },
     Fetch { },
-    Translate { },
+    /// Print an `avocadocx` program as `.cado` source or as a `.docx`
+    /// document.
+    Translate {
+        /// Input program file.
+        ///
+        /// Can be a file (.cado or .docx) or a Google Doc Id.
+        input: Input,
+
+        /// Where to write the program; stdout if not given.
+        ///
+        /// Required for `.docx` output.
+        #[structopt(long, short)]
+        output: Option<String>,
+
+        /// What to write: `cado` or `docx`.
+        ///
+        /// `.docx` output keeps the program's styles.
+        #[structopt(long, default_value = "cado")]
+        to: Format,
+
+        /// Spaces per level of indentation.
+        #[structopt(long, default_value = "4")]
+        indent: usize,
+
+        /// Column to wrap blocks at.
+        #[structopt(long, default_value = "80")]
+        width: usize,
+
+        #[structopt(flatten)]
+        code: CodeOptions,
+    },
+}
+
+#[derive(Debug)]
+enum Emit {
+    Tokens,
+    Ast,
+    TokensJson,
+    AstJson,
+}
+
+impl FromStr for Emit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "tokens" => Ok(Emit::Tokens),
+            "ast" => Ok(Emit::Ast),
+            "tokens-json" => Ok(Emit::TokensJson),
+            "ast-json" => Ok(Emit::AstJson),
+            _ => Err(format!(
+                "invalid output: {}; must be `tokens`, `ast`, `tokens-json` or `ast-json`",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Format {
+    Cado,
+    Docx,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "cado" => Ok(Format::Cado),
+            "docx" => Ok(Format::Docx),
+            _ => Err(format!("invalid format: {}; must be `cado` or `docx`", s)),
+        }
+    }
+}
+
+#[derive(Debug, StructOpt)]
+struct CodeOptions {
+    /// Only use the code in the document, not the prose around it.
+    ///
+    /// Code is text in the `Code` paragraph style unless `--code-style`
+    /// or `--code-font` say otherwise. Documents can also turn this on
+    /// with the `avocadocx.literate` custom property.
+    #[structopt(long)]
+    literate: bool,
+
+    /// Paragraph style that code is written in (implies `--literate`).
+    #[structopt(long)]
+    code_style: Option<String>,
+
+    /// Font that code is written in (implies `--literate`).
+    ///
+    /// Character styles can't be used to pick out code (their runs don't
+    /// carry the style's name); use the character style's font here.
+    #[structopt(long)]
+    code_font: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+struct PassOptions {
+    /// Turn off a check: `ambiguity`, `namespaces`, `resolve`, `types`,
+    /// `privacy`, `matches` or `exports` (or `names`, `regions` or `lower`,
+    /// which the checks use).
+    #[structopt(long = "disable-pass", number_of_values = 1)]
+    disable: Vec<String>,
+
+    /// Print how long each pass took.
+    #[structopt(long)]
+    time_passes: bool,
+}
+
+impl PassOptions {
+    fn configure(&self, pipeline: &mut PassPipeline<'_>) -> Result<(), PipelineError> {
+        for name in &self.disable {
+            pipeline.set_enabled(name, false)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl CodeOptions {
+    fn literate(self) -> Option<Literate> {
+        let CodeOptions { literate, code_style, code_font } = self;
+        if literate || code_style.is_some() || code_font.is_some() {
+            let mut lit = Literate::default();
+            if code_style.is_some() || code_font.is_some() {
+                lit.paragraph_style = code_style;
+                lit.font = code_font;
+            }
+
+            Some(lit)
+        } else {
+            None
+        }
+    }
+}
+
+/// Finds `title` in `module`'s table of contents and picks out what to run
+/// for it.
+fn entry_point(module: &Module, title: &str) -> color_eyre::Result<Module> {
+    let tree = module.tree();
+    match tree.find(title) {
+        Some(node) => Ok(tree::entry_point(module, node)),
+        None => Err(eyre!("there's no section named `{}`; the sections are:\n{}", title, tree)),
+    }
+}
+
+async fn translate(
+    input: Input,
+    output: Option<String>,
+    format: Format,
+    options: PrettyOptions,
+    literate: Option<Literate>,
+) -> color_eyre::Result<()> {
+    let loader = loader::Loader { literate };
+    let module = loader.module(input).await?.module;
+
+    match (format, output) {
+        (Format::Cado, Some(path)) => fs::write(path, pretty::module(&module, &options))?,
+        (Format::Cado, None) => print!("{}", pretty::module(&module, &options)),
+        (Format::Docx, Some(path)) => {
+            let file = fs::File::create(path)?;
+            pretty::docx(&module, &options).build().pack(file)?;
+        }
+        (Format::Docx, None) => return Err(eyre!("`--to docx` needs an `--output` file")),
+    }
+
+    Ok(())
 }
 
+async fn emit(
+    input: Input,
+    section: Option<String>,
+    what: Emit,
+    literate: Option<Literate>,
+) -> color_eyre::Result<()> {
+    let loader = loader::Loader { literate };
+    let loader::Source { tokens, module } = loader.module(input).await?;
+    let module = match section {
+        Some(title) => entry_point(&module, &title)?,
+        None => module,
+    };
+
+    match what {
+        Emit::Tokens => println!("{:#?}", tokens),
+        Emit::Ast => print!("{}", abogado_passes::types::annotated(&module)),
+        Emit::TokensJson => println!("{}", serde_json::to_string(&tokens)?),
+        Emit::AstJson => println!("{}", serde_json::to_string(&module)?),
+    }
+
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() -> color_eyre::Result<()> {
@@ -105,42 +333,68 @@ async fn main() -> color_eyre::Result<()> {
 
     let args = Args::from_args();
 
-    let source = match args {
-        Args::Run  { input } => input,
+    let (source, section, literate, passes) = match args {
+        Args::Run { input, section, emit: Some(what), code, .. } => {
+            return emit(input, section, what, code.literate()).await;
+        },
+        Args::Run { input, section, emit: None, code, passes } => {
+            (input, section, code.literate(), passes)
+        },
+        Args::Translate { input, output, to, indent, width, code } => {
+            let options = PrettyOptions { indent, width };
+            return translate(input, output, to, options, code.literate()).await;
+        },
         _ => todo!(),
     };
 
-    let inp = match source {
-        Input::CadoFile(f) => AvocadoxInput::from_cado_file(f)?,
-        Input::DocxFile(f) => AvocadoxInput::from_docx_file(f)?,
-        Input::GoogleDocId(id) => AvocadoxInput::from_google_docs(&id).await?,
+    let loader = loader::Loader { literate };
+    let loader::Loaded { main, modules, cycles } = loader.load(source).await?;
+    let name = modules[&main].name.clone();
 
+    // The main module stays in `modules` too, for the modules that import it.
+    let program = match section {
+        Some(title) => entry_point(&modules[&main], &title)?,
+        None => modules[&main].clone(),
     };
 
-    let (name, (tokens, string)) = match inp {
-        AvocadoxInput::DocxSource {
-            name, doc
-        } => (name, abogado_lex::lex_docx(&doc)),
-        AvocadoxInput::CadoSource { fname, contents } => (fname, abogado_lex::lex_cado(contents)),
-    };
-
-    let tokens = tokens?;
-
-    // let tokens = chumsky::stream::SpannedIterStream::<_, abogado_lex::Span>::new(tokens.iter().map(|t| (t, t.span)));
-    let tokens = tokens.into_iter().map(|t| { let s = t.span.clone(); (t, s) }).collect::<Vec<_>>();
+    // Cycles are fine (only procedures are imported) but worth knowing about.
+    for cycle in cycles {
+        let names = cycle.iter().map(|k| modules[k].name.as_str()).collect::<Vec<_>>();
+        eprintln!("note: these modules import each other: {}", names.join(" -> "));
+    }
 
-    println!("{:#?}", tokens.iter().map(|(t, _s)| t).collect::<Vec<_>>());
-    // println!("{:#?}", tokens.iter().map(|(t, s)| format!("{:#?}", t)).collect::<Vec<_>>());
+    let mut failed = false;
+    {
+        let mut pipeline = abogado_passes::checks(&modules);
+        passes.configure(&mut pipeline)?;
 
-    // let exp = abogado_parse::parser::expr();
-    use abogado_parse::Parser as _;
-    let program = abogado_parse::statement().repeated().parse(tokens).unwrap();
+        let imported = modules.iter().filter(|(k, _)| **k != main).map(|(_, m)| m);
+        for module in Some(&program).into_iter().chain(imported) {
+            let report = pipeline.run(module)?;
+            for diagnostic in &report.diagnostics {
+                let severity = match diagnostic.severity {
+                    Severity::Error => "error",
+                    Severity::Warning => "warning",
+                };
+                eprintln!("{}: {}", severity, diagnostic.message);
+                for (span, note) in &diagnostic.labels {
+                    eprintln!("  at {:?} in `{}`: {}", span.inner, module.name, note);
+                }
+            }
+            failed |= report.has_errors();
 
-    for statement in program.clone() {
-        println!("{}", statement.inner);
+            if passes.time_passes {
+                for (pass, time) in report.timings() {
+                    eprintln!("time: {:>10.2?} {} in `{}`", time, pass, module.name);
+                }
+            }
+        }
+    }
+    if failed {
+        return Err(eyre!("failed to check `{}`", name));
     }
 
-    avocadocx_interpreter::run_program(program.iter().map(|n| n.inner.clone()).collect());
+    avocadocx_interpreter::run_modules(program, modules)?;
 
     Ok(())
 }
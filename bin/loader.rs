@@ -0,0 +1,301 @@
+//! Loads a program and every module it imports.
+//!
+//! Modules are cached by their source (a canonical path or a Google Doc ID)
+//! so each is only fetched, lexed and parsed once; that includes the main
+//! module. Imports are allowed to form cycles; they're found so the driver can
+//! mention them.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use color_eyre::eyre::{eyre, WrapErr};
+
+use abogado_lex::{spanned::S, LexOptions, Literate, Token};
+use abogado_parse::ast::Module;
+
+use super::{AvocadoxInput, Input};
+
+/// A lexed and parsed module, along with its tokens.
+pub struct Source {
+    pub tokens: Vec<S<Token>>,
+    pub module: Module,
+}
+
+pub struct Loaded {
+    /// The key the main module is under.
+    pub main: String,
+    /// Every module, the main one included, keyed by source; the `import`
+    /// statements in every module are rewritten to use these keys.
+    pub modules: HashMap<String, Module>,
+    /// Each cycle is a list of module keys where each imports the next (and
+    /// the last imports the first).
+    pub cycles: Vec<Vec<String>>,
+}
+
+pub struct Loader {
+    /// From the command line; wins over what documents ask for.
+    pub literate: Option<Literate>,
+}
+
+impl Loader {
+    /// Loads `input` and everything it imports, each once.
+    ///
+    /// The main module is keyed like every other module (by its canonical
+    /// path or doc ID) so a module that imports it gets the same one back.
+    pub async fn load(&self, input: Input) -> color_eyre::Result<Loaded> {
+        let input = input.relative_to(None)?;
+        let key = input.key();
+        let dir = input.dir();
+        let main = self.module(input).await?.module;
+
+        let mut modules = HashMap::<String, Module>::new();
+        let mut edges = HashMap::<String, Vec<String>>::new();
+
+        // (key, the directory its relative imports are in, the module)
+        let mut queue = vec![(key.clone(), dir, main)];
+        while let Some((key, dir, mut module)) = queue.pop() {
+            let mut imports = vec![];
+            for import in module.imports_mut() {
+                let source = &mut import.source;
+                let input = source
+                    .inner
+                    .parse::<Input>()
+                    .map_err(|e| eyre!(e))
+                    .and_then(|i| i.relative_to(dir.as_deref()))
+                    .wrap_err_with(|| format!("couldn't resolve import `{}`", source.inner))?;
+
+                let k = input.key();
+                source.inner = k.clone();
+                imports.push(k.clone());
+
+                let seen = k == key
+                    || modules.contains_key(&k)
+                    || queue.iter().any(|(q, _, _)| *q == k);
+                if !seen {
+                    let dir = input.dir();
+                    let module = self.module(input).await?.module;
+                    queue.push((k, dir, module));
+                }
+            }
+            module.reindex();
+
+            edges.insert(key.clone(), imports);
+            modules.insert(key, module);
+        }
+
+        Ok(Loaded {
+            cycles: cycles(&key, &edges),
+            main: key,
+            modules,
+        })
+    }
+
+    /// Loads just `input`, without what it imports.
+    pub async fn module(&self, input: Input) -> color_eyre::Result<Source> {
+        let inp = match input {
+            Input::CadoFile(f) => AvocadoxInput::from_cado_file(f)?,
+            Input::DocxFile(f) => AvocadoxInput::from_docx_file(f)?,
+            Input::GoogleDocId(id) => AvocadoxInput::from_google_docs(&id).await?,
+        };
+
+        let (name, metadata, docx, (tokens, source)) = match inp {
+            AvocadoxInput::DocxSource { name, doc } => {
+                // Flags win over the document's own settings.
+                let mut options = LexOptions::from_docx(&doc);
+                if self.literate.is_some() {
+                    options.literate = self.literate.clone();
+                }
+
+                let metadata = doc
+                    .doc_props
+                    .custom
+                    .properties
+                    .iter()
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect();
+
+                (name, metadata, true, abogado_lex::lex_docx_with(&doc, &options))
+            }
+            AvocadoxInput::CadoSource { fname, contents } => {
+                (fname, Default::default(), false, abogado_lex::lex_cado(contents))
+            }
+        };
+
+        let tokens = tokens?;
+        let (mut module, errors) = if docx {
+            abogado_parse::parse_docx(name, tokens.clone(), &source)
+        } else {
+            abogado_parse::parse(name, tokens.clone(), &source)
+        };
+        if !errors.is_empty() {
+            let mut message = format!("failed to parse `{}`:", module.name);
+            for err in errors {
+                message.push_str(&format!("\n  error: {}", err));
+                for (span, note) in err.labels() {
+                    message.push_str(&format!("\n    at {:?}: {}", span.inner, note));
+                }
+            }
+            return Err(eyre!(message));
+        }
+        module.metadata = metadata;
+
+        Ok(Source { tokens, module })
+    }
+}
+
+/// Finds the import cycles in `edges` that can be reached from `main`, each
+/// starting from its first module.
+fn cycles(main: &str, edges: &HashMap<String, Vec<String>>) -> Vec<Vec<String>> {
+    fn visit(
+        node: &str,
+        edges: &HashMap<String, Vec<String>>,
+        stack: &mut Vec<String>,
+        finished: &mut HashSet<String>,
+        found: &mut Vec<Vec<String>>,
+    ) {
+        if let Some(start) = stack.iter().position(|n| n == node) {
+            found.push(stack[start..].to_vec());
+            return;
+        }
+        if finished.contains(node) {
+            return;
+        }
+
+        stack.push(node.to_string());
+        for next in edges.get(node).into_iter().flatten() {
+            visit(next, edges, stack, finished, found);
+        }
+        stack.pop();
+        finished.insert(node.to_string());
+    }
+
+    let mut found = vec![];
+    visit(main, edges, &mut vec![], &mut HashSet::new(), &mut found);
+    found
+}
+
+impl Input {
+    /// Makes file inputs relative to `dir` (the importing module's directory).
+    fn relative_to(self, dir: Option<&Path>) -> color_eyre::Result<Self> {
+        let resolve = |f: String| -> color_eyre::Result<String> {
+            let path = match dir {
+                Some(dir) if Path::new(&f).is_relative() => dir.join(&f),
+                _ => PathBuf::from(&f),
+            };
+
+            Ok(path
+                .canonicalize()
+                .wrap_err_with(|| format!("couldn't find `{}`", path.display()))?
+                .to_string_lossy()
+                .into_owned())
+        };
+
+        Ok(match self {
+            Input::CadoFile(f) => Input::CadoFile(resolve(f)?),
+            Input::DocxFile(f) => Input::DocxFile(resolve(f)?),
+            Input::GoogleDocId(id) => Input::GoogleDocId(id),
+        })
+    }
+
+    /// What the module is cached under.
+    fn key(&self) -> String {
+        match self {
+            Input::CadoFile(f) | Input::DocxFile(f) | Input::GoogleDocId(f) => f.clone(),
+        }
+    }
+
+    /// Where relative imports in this module are found.
+    fn dir(&self) -> Option<PathBuf> {
+        match self {
+            Input::CadoFile(f) | Input::DocxFile(f) => {
+                Path::new(f).parent().map(Path::to_path_buf)
+            }
+            // Imports in Google Docs are relative to the working directory.
+            Input::GoogleDocId(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    /// Writes `files` to a new directory and loads the first one.
+    async fn load(test: &str, files: &[(&str, &str)]) -> (PathBuf, Loaded) {
+        let dir = std::env::temp_dir().join(format!("avocadocx-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+
+        let main = dir.join(files[0].0).to_string_lossy().into_owned();
+        let loaded = Loader { literate: None }
+            .load(Input::CadoFile(main))
+            .await
+            .unwrap();
+        (dir.canonicalize().unwrap(), loaded)
+    }
+
+    fn key(dir: &Path, name: &str) -> String {
+        dir.join(name).to_string_lossy().into_owned()
+    }
+
+    fn sources(module: &Module) -> Vec<String> {
+        module.imports.iter().map(|i| i.source.inner.clone()).collect()
+    }
+
+    #[tokio::test]
+    async fn modules_are_loaded_once() {
+        let (dir, loaded) = load(
+            "diamond",
+            &[
+                ("main.cado", "import \"b.cado\".\nimport \"c.cado\"."),
+                ("b.cado", "import \"d.cado\"."),
+                ("c.cado", "import \"./d.cado\"."),
+                ("d.cado", "procedure f takes does 1."),
+            ],
+        )
+        .await;
+
+        assert_eq!(loaded.main, key(&dir, "main.cado"));
+        assert_eq!(loaded.modules.len(), 4);
+        // Both spellings of `d.cado` are the same module.
+        assert_eq!(sources(&loaded.modules[&key(&dir, "b.cado")]), [key(&dir, "d.cado")]);
+        assert_eq!(sources(&loaded.modules[&key(&dir, "c.cado")]), [key(&dir, "d.cado")]);
+        assert!(loaded.cycles.is_empty());
+    }
+
+    #[tokio::test]
+    async fn cycles_through_the_main_module() {
+        let (dir, loaded) = load(
+            "cycles",
+            &[
+                ("main.cado", "import \"b.cado\"."),
+                ("b.cado", "import \"main.cado\".\nimport \"c.cado\"."),
+                ("c.cado", "import \"b.cado\"."),
+            ],
+        )
+        .await;
+
+        // `b.cado` gets the main module back instead of loading it again.
+        assert_eq!(loaded.modules.len(), 3);
+        assert_eq!(
+            sources(&loaded.modules[&key(&dir, "b.cado")]),
+            [key(&dir, "main.cado"), key(&dir, "c.cado")],
+        );
+
+        let (main, b, c) = (key(&dir, "main.cado"), key(&dir, "b.cado"), key(&dir, "c.cado"));
+        assert_eq!(loaded.cycles, vec![vec![main, b.clone()], vec![b, c]]);
+    }
+
+    #[tokio::test]
+    async fn a_module_can_import_itself() {
+        let (dir, loaded) = load("self", &[("main.cado", "import \"main.cado\".")]).await;
+
+        assert_eq!(loaded.modules.len(), 1);
+        assert_eq!(loaded.cycles, vec![vec![key(&dir, "main.cado")]]);
+    }
+}
@@ -41,9 +41,6 @@ pub async fn run(id: String) -> Result<String, JsValue> {
     let (tokens, file) = abogado_lex::lex_docx(&file);
 
     let tokens = tokens.unwrap();
-    let tokens = tokens.into_iter().map(|t| { let s = t.span.clone(); (t, s) }).collect::<Vec<_>>();
-
-    use abogado_parse::Parser as _;
 
     term.focus();
 
@@ -51,11 +48,14 @@ pub async fn run(id: String) -> Result<String, JsValue> {
     term.write(String::from("\x1B[1;3;31mxterm.js\x1B[0m with 🦀\n$ "));
 
 
-    debug!("{:#?}", tokens.iter().map(|(t, _s)| t).collect::<Vec<_>>());
+    debug!("{:#?}", tokens);
 
-    let program = abogado_parse::statement().repeated().parse(tokens).unwrap();
+    let (program, errors) = abogado_parse::parse_docx(&*id, tokens, &file);
+    for err in errors {
+        term.write(format!("\x1B[1;31merror:\x1B[0m {}\n", err));
+    }
 
-    for statement in program {
+    for statement in program.body {
         debug!("{}", statement.inner);
         term.write(format!("{}\n", statement.inner));
     }
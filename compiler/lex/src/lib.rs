@@ -8,19 +8,25 @@
     html_root_url = "https://docs.rs/abogado-lex/0.0.0", // remember to bump!
 )]
 
+pub mod literate;
 pub mod span;
 pub mod spanned;
 pub mod style;
 pub mod token;
 
-use std::{convert::TryInto, num::ParseFloatError};
+use std::{
+    convert::{TryFrom, TryInto},
+    iter::Peekable,
+    num::ParseFloatError,
+};
 
 use docx_rs::{DocumentChild, Docx, ParagraphChild, RunChild};
 use thiserror::Error;
 
+pub use literate::{LexOptions, Literate};
 pub use span::Span;
 pub use style::Style;
-pub use token::{Keyword, Op, Sigil, Token};
+pub use token::{Heading, Interpolation, Keyword, Op, Sigil, Token};
 
 type S = spanned::S<Token>;
 
@@ -67,6 +73,10 @@ fn splat_docx(doc: &Docx) -> Splatted {
                     }
                 }
             }
+
+            // Paragraphs are separate lines; without this the last word of
+            // one paragraph would run into the first word of the next.
+            tagged_chars.push(('\n', styles.len() - 1));
         }
     }
 
@@ -92,106 +102,145 @@ fn collate(
         tagged_chars,
         mut styles,
     }: Splatted,
+    options: &LexOptions,
 ) -> Result<Vec<S>, LexError> {
-    let mut tokens = vec![];
-
-    let char_iter = tagged_chars.iter().scan(0, |byte_offset, (c, tag)| {
+    // In literate mode, prose is lexed as if it were whitespace; this way the
+    // offsets of the code that's around it don't change.
+    //
+    // Headings still mark sections, even though they aren't code.
+    let is_code: Vec<bool> = styles
+        .iter()
+        .map(|s| {
+            options
+                .literate
+                .as_ref()
+                .map(|l| l.is_code(s) || s.heading_level().is_some())
+                .unwrap_or(true)
+        })
+        .collect();
+
+    let char_iter = tagged_chars.iter().scan(0, move |byte_offset, (c, tag)| {
         let starting_offset = *byte_offset;
         *byte_offset += c.len_utf16();
         let ending_offset = *byte_offset;
 
-        Some(((starting_offset, ending_offset), *c, *tag))
+        let c = if is_code[*tag] { *c } else { ' ' };
+        Some(((starting_offset, ending_offset), c, *tag))
     });
 
-    let mut char_iter = char_iter.peekable();
+    lex_chars(char_iter, &mut styles)
+}
+
+/// A character with its (utf16) offsets and the index of its style.
+type Char = ((usize, usize), char, usize);
+
+const OPEN_QUOTE: char = '“';
+const CLOSE_QUOTE: char = '”';
+const WHITESPACE: &str = " \n\r\t";
+const SECTION_SIGN: char = '§';
+
+fn fold_style(styles: &mut Vec<Style>, existing: &mut usize, added: usize) {
+    if *existing == added {
+        // we're good; the styles match
+    } else if styles[*existing] == styles[added] {
+        // we're good; the styles match
+        //
+        // we'll update the index since it's what the next tokens may have
+        *existing = added;
+    } else {
+        // if the styles are different, find their intersection:
+        let new = styles[*existing].intersect(&styles[added]);
+        styles.push(new);
+        *existing = styles.len() - 1;
+    }
+}
+
+/// Lexes a run of characters; this is also used for the expressions that are
+/// embedded in strings, which is why it works on [`Char`]s (that carry their
+/// own offsets) instead of on a whole document.
+fn lex_chars(
+    chars: impl Iterator<Item = Char>,
+    styles: &mut Vec<Style>,
+) -> Result<Vec<S>, LexError> {
+    let mut tokens = vec![];
+
+    let mut char_iter = chars.peekable();
     // states: ready, in_num, in_str, in_word
     while let Some(((start_ofs, end_ofs), c, style_id)) = char_iter.next() {
-        const OPEN_QUOTE: char = '“';
-        const CLOSE_QUOTE: char = '”';
-        const WHITESPACE: &str = " \n\r\t";
-
-        let fold_style = |styles: &mut Vec<Style>, existing: &mut usize, added: usize| {
-            if *existing == added {
-                // we're good; the styles match
-            } else if styles[*existing] == styles[added] {
-                // we're good; the styles match
-                //
-                // we'll update the index since it's what the next tokens may have
-                *existing = added;
-            } else {
-                // if the styles are different, find their intersection:
-                let new = styles[*existing].intersect(&styles[added]);
-                styles.push(new);
-                *existing = styles.len() - 1;
-            }
-        };
-
         match c {
-            '"' => {
-                // Just eat chars until we see another `"`:
-                let start_offset = start_ofs;
+            c if !WHITESPACE.contains(c) && styles[style_id].heading_level().is_some() => {
+                // Headings mark the start of a section; the whole paragraph is
+                // the section's title.
+                let level = styles[style_id].heading_level().unwrap();
+                let mut title = String::from(c);
+                let mut end = end_ofs;
                 let mut token_style_id = style_id;
-                let mut string = String::new();
-                let end_offset = loop {
-                    let ((_, end_ofs), c, style_id) =
-                        char_iter.next().ok_or(LexError::UnexpectedEof {
-                            looking_for: Some(String::from('"')),
-                        })?;
-
-                    fold_style(&mut styles, &mut token_style_id, style_id);
 
-                    if c == '"' {
-                        break end_ofs;
+                while let Some(((_, end_ofs), c, style_id)) = char_iter.peek() {
+                    if *c == '\n' {
+                        break;
                     }
 
-                    string.push(c);
-                };
+                    end = *end_ofs;
+                    fold_style(styles, &mut token_style_id, *style_id);
+                    title.push(*c);
+
+                    let _ = char_iter.next();
+                }
 
                 tokens.push(S {
-                    inner: Token::StringConst(string),
+                    inner: Token::Heading(Heading {
+                        level,
+                        title: title.trim().to_string(),
+                    }),
                     span: Span {
-                        inner: start_offset..end_offset,
+                        inner: start_ofs..end,
                     },
                     style: styles[token_style_id].clone(),
                 })
             }
-            OPEN_QUOTE => {
-                // these can be nested so we keep track of our depth:
-                let mut quote_depth = 1;
-                let start_offset = start_ofs;
+            SECTION_SIGN => {
+                // The plain text equivalent of a heading: `§ title` (or
+                // `§§ title` for a subsection) up to the end of the line.
+                let mut level = 1;
+                let mut title = String::new();
+                let mut end = end_ofs;
                 let mut token_style_id = style_id;
-                let mut string = String::new();
-                let end_offset = loop {
-                    let ((_, end_ofs), c, style_id) =
-                        char_iter.next().ok_or(LexError::UnexpectedEof {
-                            looking_for: Some(String::from(CLOSE_QUOTE)),
-                        })?;
-
-                    match c {
-                        OPEN_QUOTE => quote_depth += 1,
-                        CLOSE_QUOTE => quote_depth -= 1,
-                        _ => {}
-                    }
 
-                    fold_style(&mut styles, &mut token_style_id, style_id);
+                while let Some(((_, end_ofs), c, style_id)) = char_iter.peek() {
+                    if *c == '\n' {
+                        break;
+                    }
 
-                    if quote_depth == 0 {
-                        break end_ofs;
+                    if *c == SECTION_SIGN && title.trim().is_empty() {
+                        level += 1;
+                    } else {
+                        title.push(*c);
                     }
 
-                    // Note that we do this last; we don't push the closing
-                    // quote to the string.
-                    string.push(c);
-                };
+                    end = *end_ofs;
+                    fold_style(styles, &mut token_style_id, *style_id);
+
+                    let _ = char_iter.next();
+                }
 
                 tokens.push(S {
-                    inner: Token::StringConst(string),
+                    inner: Token::Heading(Heading {
+                        level,
+                        title: title.trim().to_string(),
+                    }),
                     span: Span {
-                        inner: start_offset..end_offset,
+                        inner: start_ofs..end,
                     },
                     style: styles[token_style_id].clone(),
                 })
             }
+            // Curly quotes can be nested; straight quotes can't.
+            '"' | OPEN_QUOTE => {
+                let close = if c == '"' { '"' } else { CLOSE_QUOTE };
+                let open = ((start_ofs, end_ofs), c, style_id);
+                tokens.extend(string_literal(open, close, &mut char_iter, styles)?);
+            }
             c if WHITESPACE.contains(c) => {
                 // do nothing for white space
             }
@@ -250,7 +299,7 @@ fn collate(
                     num.push(c);
                     ending_ofs = end_pos;
 
-                    fold_style(&mut styles, &mut token_style_id, style_id);
+                    fold_style(styles, &mut token_style_id, style_id);
                 }
 
                 let num = num.parse()?;
@@ -299,7 +348,7 @@ fn collate(
 
                     end = *end_ofs;
 
-                    fold_style(&mut styles, &mut token_style_id, *style_id);
+                    fold_style(styles, &mut token_style_id, *style_id);
                     word.push(*c);
 
                     // But once we know it's not a terminator we can advance the
@@ -307,7 +356,7 @@ fn collate(
                     let _ = char_iter.next();
                 }
 
-                use {crate::Keyword::*, crate::Sigil::*, Op::*, Token::*};
+                use {crate::Sigil::*, Op::*, Token::*};
                 let inner = match &*word {
                     "+" => Operator(Add),
                     "-" => Operator(Sub),
@@ -324,29 +373,10 @@ fn collate(
                     "." => Sigil(Dot),
                     ";" => Sigil(Semicolon),
 
-                    "set" => Keyword(Set),
-                    "to" => Keyword(To),
-                    "is" => Keyword(Is),
-                    "do" => Keyword(Do),
-                    "using" => Keyword(Using),
-                    "get" => Keyword(Get),
-                    "also" => Keyword(Also),
-                    "and" => Keyword(And),
-                    "not" => Keyword(Not),
-                    "while" => Keyword(While),
-                    "keep" => Keyword(Keep),
-                    "until" => Keyword(Until),
-                    "run" => Keyword(Run),
-                    "for" => Keyword(For),
-                    "in" => Keyword(In),
-                    "procedure" => Keyword(Procedure),
-                    "takes" => Keyword(Takes),
-                    "does" => Keyword(Does),
-                    "otherwise" | "else" => Keyword(Else),
-                    "emit" => Keyword(Emit),
-                    "from" => Keyword(From),
-
-                    _ => Ident(word),
+                    w => match crate::Keyword::try_from(w) {
+                        Ok(kw) => Keyword(kw),
+                        Err(()) => Ident(w.to_string()),
+                    },
                 };
 
                 tokens.push(S {
@@ -363,11 +393,166 @@ fn collate(
     Ok(tokens)
 }
 
+/// Lexes the rest of a string literal, after its `open`ing quote.
+///
+/// Strings without `{...}` in them become a single [`Token::StringConst`].
+/// Strings with them are split into [`Interpolation`] tokens: the text parts
+/// and the tokens of the embedded expressions, between
+/// [`Interpolation::Start`] and [`Interpolation::End`] tokens for the quotes.
+///
+/// `{{` and `}}` are a literal `{` and `}`.
+fn string_literal(
+    open: Char,
+    close: char,
+    chars: &mut Peekable<impl Iterator<Item = Char>>,
+    styles: &mut Vec<Style>,
+) -> Result<Vec<S>, LexError> {
+    let ((start_offset, open_end), open_c, open_style) = open;
+    let nests = open_c != close;
+
+    let mut quote_depth = 1;
+    let mut token_style_id = open_style;
+    let mut parts = vec![];
+    let mut interpolated = false;
+
+    // The text part we're in the middle of: (start, end, style, text).
+    let mut text: Option<(usize, usize, usize, String)> = None;
+    let flush = |text: &mut Option<(usize, usize, usize, String)>,
+                 parts: &mut Vec<S>,
+                 styles: &[Style]| {
+        if let Some((start, end, style, t)) = text.take() {
+            parts.push(S {
+                inner: Token::Interpolation(Interpolation::Text(t)),
+                span: Span { inner: start..end },
+                style: styles[style].clone(),
+            });
+        }
+    };
+
+    let (close_start, end_offset, close_style) = loop {
+        let ((start, end), c, style_id) = chars.next().ok_or(LexError::UnexpectedEof {
+            looking_for: Some(String::from(close)),
+        })?;
+
+        fold_style(styles, &mut token_style_id, style_id);
+
+        if nests && c == open_c {
+            quote_depth += 1;
+        } else if c == close {
+            quote_depth -= 1;
+            if quote_depth == 0 {
+                break (start, end, style_id);
+            }
+        }
+
+        let escaped = matches!(c, '{' | '}') && matches!(chars.peek(), Some((_, n, _)) if *n == c);
+        if c == '{' && !escaped {
+            interpolated = true;
+            flush(&mut text, &mut parts, styles);
+            parts.push(S {
+                inner: Token::Interpolation(Interpolation::OpenExpr),
+                span: Span { inner: start..end },
+                style: styles[style_id].clone(),
+            });
+
+            // Grab everything up to the matching `}` and lex it on its own;
+            // braces in strings (of either kind of quote) don't count:
+            let mut inner = vec![];
+            let mut braces = 1;
+            let mut straight = false;
+            let mut curly = 0usize;
+            let closing = loop {
+                let ch = chars.next().ok_or(LexError::UnexpectedEof {
+                    looking_for: Some(String::from('}')),
+                })?;
+                fold_style(styles, &mut token_style_id, ch.2);
+
+                let in_string = straight || curly > 0;
+                match ch.1 {
+                    '"' if curly == 0 => straight = !straight,
+                    '“' if !straight => curly += 1,
+                    '”' if !straight => curly = curly.saturating_sub(1),
+                    '{' if !in_string => braces += 1,
+                    '}' if !in_string => {
+                        braces -= 1;
+                        if braces == 0 {
+                            break ch;
+                        }
+                    }
+                    _ => {}
+                }
+
+                inner.push(ch);
+            };
+
+            parts.extend(lex_chars(inner.into_iter(), styles)?);
+            let ((start, end), _, style_id) = closing;
+            parts.push(S {
+                inner: Token::Interpolation(Interpolation::CloseExpr),
+                span: Span { inner: start..end },
+                style: styles[style_id].clone(),
+            });
+
+            continue;
+        }
+
+        let mut end = end;
+        if escaped {
+            let ((_, e), _, s) = chars.next().unwrap();
+            fold_style(styles, &mut token_style_id, s);
+            end = e;
+        }
+
+        // Note that the closing quote never makes it here; we don't push it
+        // to the string.
+        let (_, text_end, text_style, t) =
+            text.get_or_insert_with(|| (start, end, style_id, String::new()));
+        *text_end = end;
+        fold_style(styles, text_style, style_id);
+        t.push(c);
+    };
+
+    if !interpolated {
+        let string = text.map(|(_, _, _, t)| t).unwrap_or_default();
+        return Ok(vec![S {
+            inner: Token::StringConst(string),
+            span: Span {
+                inner: start_offset..end_offset,
+            },
+            style: styles[token_style_id].clone(),
+        }]);
+    }
+
+    flush(&mut text, &mut parts, styles);
+
+    let mut tokens = vec![S {
+        inner: Token::Interpolation(Interpolation::Start),
+        span: Span {
+            inner: start_offset..open_end,
+        },
+        style: styles[open_style].clone(),
+    }];
+    tokens.extend(parts);
+    tokens.push(S {
+        inner: Token::Interpolation(Interpolation::End),
+        span: Span {
+            inner: close_start..end_offset,
+        },
+        style: styles[close_style].clone(),
+    });
+
+    Ok(tokens)
+}
+
 pub fn lex_docx(doc: &Docx) -> (Result<Vec<S>, LexError>, String) {
+    lex_docx_with(doc, &LexOptions::from_docx(doc))
+}
+
+pub fn lex_docx_with(doc: &Docx, options: &LexOptions) -> (Result<Vec<S>, LexError>, String) {
     let splatted = splat_docx(doc);
     let full_string = splatted.tagged_chars.iter().map(|p| p.0).collect();
 
-    (collate(splatted), full_string)
+    (collate(splatted, options), full_string)
 }
 
 pub fn lex_cado(inp: String) -> (Result<Vec<S>, LexError>, String) {
@@ -376,5 +561,196 @@ pub fn lex_cado(inp: String) -> (Result<Vec<S>, LexError>, String) {
         styles: vec![Default::default()],
     };
 
-    (collate(splatted), inp)
+    // Plain text has no styles to pick code out with so it's never literate.
+    (collate(splatted, &LexOptions::default()), inp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use docx_rs::{Paragraph, Run, RunFonts, RunProperty};
+    use std::ops::Range;
+
+    fn tokens(src: &str) -> Vec<(Token, Range<usize>)> {
+        lex_cado(src.to_string())
+            .0
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.inner, t.span.inner))
+            .collect()
+    }
+
+    fn interp(i: Interpolation) -> Token {
+        Token::Interpolation(i)
+    }
+
+    fn text(t: &str) -> Token {
+        interp(Interpolation::Text(t.to_string()))
+    }
+
+    fn ident(i: &str) -> Token {
+        Token::Ident(i.to_string())
+    }
+
+    #[test]
+    fn strings_without_holes_are_constants() {
+        assert_eq!(
+            tokens("“a {{b}}”"),
+            vec![(Token::StringConst("a {b}".to_string()), 0..9)],
+        );
+    }
+
+    #[test]
+    fn holes_are_split_out() {
+        use Interpolation::*;
+
+        assert_eq!(
+            tokens("\"a{x}b\""),
+            vec![
+                (interp(Start), 0..1),
+                (text("a"), 1..2),
+                (interp(OpenExpr), 2..3),
+                (ident("x"), 3..4),
+                (interp(CloseExpr), 4..5),
+                (text("b"), 5..6),
+                (interp(End), 6..7),
+            ],
+        );
+    }
+
+    #[test]
+    fn quotes_in_holes_hide_braces() {
+        use Interpolation::*;
+
+        for (open, close) in [('"', '"'), ('“', '”')] {
+            for (inner_open, inner_close) in [('"', '"'), ('“', '”')] {
+                let src = format!("{}{{f {}}}{}}}{}", open, inner_open, inner_close, close);
+                assert_eq!(
+                    tokens(&src),
+                    vec![
+                        (interp(Start), 0..1),
+                        (interp(OpenExpr), 1..2),
+                        (ident("f"), 2..3),
+                        (Token::StringConst("}".to_string()), 4..7),
+                        (interp(CloseExpr), 7..8),
+                        (interp(End), 8..9),
+                    ],
+                    "{}",
+                    src,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hole_spans_are_utf16_offsets() {
+        use Interpolation::*;
+
+        // `😀` is two UTF-16 code units (and four bytes).
+        assert_eq!(
+            tokens("“😀{x}é”"),
+            vec![
+                (interp(Start), 0..1),
+                (text("😀"), 1..3),
+                (interp(OpenExpr), 3..4),
+                (ident("x"), 4..5),
+                (interp(CloseExpr), 5..6),
+                (text("é"), 6..7),
+                (interp(End), 7..8),
+            ],
+        );
+    }
+
+    fn paragraph(style: Option<&str>, runs: &[(&str, RunProperty)]) -> Paragraph {
+        let p = style.map_or_else(Paragraph::new, |s| Paragraph::new().style(s));
+        runs.iter().fold(p, |p, (text, prop)| {
+            let mut run = Run::new().add_text(*text);
+            run.run_property = prop.clone();
+            p.add_run(run)
+        })
+    }
+
+    fn code_tokens(doc: &Docx, literate: Literate) -> (Vec<(Token, Range<usize>)>, String) {
+        let options = LexOptions {
+            literate: Some(literate),
+        };
+        let (tokens, source) = lex_docx_with(doc, &options);
+        let tokens = tokens
+            .unwrap()
+            .into_iter()
+            .map(|t| (t.inner, t.span.inner))
+            .collect();
+        (tokens, source)
+    }
+
+    fn statement(offset: usize) -> Vec<(Token, Range<usize>)> {
+        vec![
+            (Token::Keyword(Keyword::Set), offset..offset + 3),
+            (ident("x"), offset + 4..offset + 5),
+            (Token::Keyword(Keyword::To), offset + 6..offset + 8),
+            (ident("y"), offset + 9..offset + 10),
+            (Token::Sigil(Sigil::Dot), offset + 10..offset + 11),
+        ]
+    }
+
+    #[test]
+    fn literate_documents_skip_prose_by_paragraph_style() {
+        // The quotes in the prose don't start a string and the emoji is two
+        // UTF-16 code units, so the code starts at 12.
+        let doc = Docx::new()
+            .add_paragraph(paragraph(None, &[("say “hi” 😀", RunProperty::new())]))
+            .add_paragraph(paragraph(Some("SourceCode"), &[("set x to y.", RunProperty::new())]))
+            .add_paragraph(paragraph(None, &[("set z to y.", RunProperty::new())]));
+
+        let literate = Literate {
+            paragraph_style: Some("Source Code".to_string()),
+            font: None,
+        };
+        let (tokens, source) = code_tokens(&doc, literate);
+        assert_eq!(tokens, statement(12));
+        assert!(source.starts_with("say “hi” 😀\nset x to y.\n"));
+
+        // Without literate mode the prose is code too:
+        let (tokens, _) = lex_docx(&doc);
+        assert_eq!(tokens.unwrap()[0].inner, ident("say"));
+    }
+
+    #[test]
+    fn literate_documents_skip_prose_by_font() {
+        let coded = RunProperty {
+            fonts: Some(RunFonts::new().ascii("Courier New")),
+            ..RunProperty::new()
+        };
+        let doc = Docx::new()
+            .add_paragraph(paragraph(
+                Some("Code"),
+                &[
+                    ("we ", RunProperty::new()),
+                    ("set x to y.", coded.clone()),
+                    (" set z", RunProperty::new()),
+                ],
+            ))
+            .add_paragraph(paragraph(None, &[("set x to y.", coded)]));
+
+        let literate = Literate {
+            paragraph_style: None,
+            font: Some("courier new".to_string()),
+        };
+        let (tokens, source) = code_tokens(&doc, literate);
+
+        let mut expected = statement(3);
+        expected.extend(statement(3 + 11 + 6 + 1));
+        assert_eq!(tokens, expected);
+        assert_eq!(&source[3..14], "set x to y.");
+        assert_eq!(&source[21..32], "set x to y.");
+    }
+
+    #[test]
+    fn unclosed_holes_are_errors() {
+        assert!(matches!(
+            lex_cado("“a{x”".to_string()).0,
+            Err(LexError::UnexpectedEof { .. }),
+        ));
+    }
 }
@@ -0,0 +1,117 @@
+//! Options for lexing; mostly for literate documents, where code is mixed in
+//! with prose.
+
+use docx_rs::Docx;
+
+use super::style::Style;
+
+/// The custom document property that turns on literate mode. Its value is the
+/// name of the paragraph style that code is written in (or empty, for the
+/// default).
+pub const LITERATE_PROPERTY: &str = "avocadocx.literate";
+/// The custom document property that picks out code by its font in literate
+/// mode.
+pub const LITERATE_FONT_PROPERTY: &str = "avocadocx.literate-font";
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LexOptions {
+    /// When set, only the text picked out by [`Literate`] is lexed; everything
+    /// else is prose.
+    pub literate: Option<Literate>,
+}
+
+impl LexOptions {
+    /// Grabs options from a document's settings (its custom properties).
+    pub fn from_docx(doc: &Docx) -> LexOptions {
+        let props = &doc.doc_props.custom.properties;
+
+        let literate = match (props.get(LITERATE_PROPERTY), props.get(LITERATE_FONT_PROPERTY)) {
+            (None, None) => None,
+            (style, font) => Some(Literate {
+                paragraph_style: match style {
+                    Some(s) if !s.trim().is_empty() => Some(s.trim().to_string()),
+                    _ => Literate::default().paragraph_style,
+                },
+                font: font.map(|f| f.trim().to_string()),
+            }),
+        };
+
+        LexOptions { literate }
+    }
+}
+
+/// What code looks like in a literate document.
+///
+/// Text that's in the paragraph style *or* the font given here is code.
+///
+/// Character styles aren't supported: `docx-rs` doesn't read a run's style
+/// (`w:rStyle`), only the properties set on the run itself, so there's no
+/// style name to match. Code that's marked with a character style can be
+/// picked out by the style's font instead.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Literate {
+    pub paragraph_style: Option<String>,
+    pub font: Option<String>,
+}
+
+impl Default for Literate {
+    fn default() -> Self {
+        Literate {
+            paragraph_style: Some("Code".to_string()),
+            font: None,
+        }
+    }
+}
+
+impl Literate {
+    pub fn is_code(&self, style: &Style) -> bool {
+        // Style ids don't have spaces in them (i.e. "Source Code" is
+        // `SourceCode`) so we drop them before comparing.
+        fn normalize(name: &str) -> String {
+            name.chars()
+                .filter(|c| !c.is_whitespace())
+                .flat_map(char::to_lowercase)
+                .collect()
+        }
+
+        let paragraph_style_matches = match (&self.paragraph_style, &style.paragraph_style) {
+            (Some(want), Some(got)) => normalize(want) == normalize(&got.val),
+            _ => false,
+        };
+
+        let font_matches = match (&self.font, style.font()) {
+            (Some(want), Some(got)) => normalize(want) == normalize(got),
+            _ => false,
+        };
+
+        paragraph_style_matches || font_matches
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(props: &[(&str, &str)]) -> LexOptions {
+        let mut doc = Docx::new();
+        for (k, v) in props {
+            doc.doc_props.custom.properties.insert(k.to_string(), v.to_string());
+        }
+
+        LexOptions::from_docx(&doc)
+    }
+
+    #[test]
+    fn documents_turn_literate_mode_on() {
+        assert_eq!(options(&[]).literate, None);
+        assert_eq!(options(&[(LITERATE_PROPERTY, " ")]).literate, Some(Literate::default()));
+        assert_eq!(
+            options(&[(LITERATE_PROPERTY, "Listing"), (LITERATE_FONT_PROPERTY, "Consolas")])
+                .literate,
+            Some(Literate {
+                paragraph_style: Some("Listing".to_string()),
+                font: Some("Consolas".to_string()),
+            }),
+        );
+    }
+}
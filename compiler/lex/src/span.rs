@@ -1,6 +1,7 @@
 use std::{fmt::Display , ops::{BitOr, Range}};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize), serde(transparent))]
 pub struct Span {
     pub inner: Range<usize>,
     // TODO: add page number? (optional; for docx inputs)
@@ -25,6 +26,10 @@ impl From<Range<usize>> for Span {
 }
 
 impl Span {
+    pub fn contains(&self, other: &Span) -> bool {
+        self.inner.start <= other.inner.start && other.inner.end <= self.inner.end
+    }
+
     pub fn union(
         &self,
         Span {
@@ -5,6 +5,7 @@ use super::span::Span;
 use super::style::Style;
 
 #[derive(Clone, PartialEq)] // TODO: Eq, PartialOrd, Ord, Hash
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct SpannedAndStyled<T> {
     pub inner: T,
     pub span: Span,
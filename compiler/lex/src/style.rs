@@ -9,6 +9,30 @@ pub struct Style {
 }
 
 impl Style {
+    /// The heading level (i.e. `1` for `Heading1`) of the paragraph this text
+    /// came from, if it's a heading.
+    pub fn heading_level(&self) -> Option<usize> {
+        self.paragraph_style
+            .as_ref()?
+            .val
+            .strip_prefix("Heading")?
+            .parse()
+            .ok()
+    }
+
+    /// The name of the font this text is in, if it has one.
+    pub fn font(&self) -> Option<&str> {
+        self.prop.fonts.as_ref()?.ascii.as_deref()
+    }
+
+    pub fn is_bordered(&self) -> bool {
+        self.prop.text_border.is_some()
+    }
+
+    pub fn is_underlined(&self) -> bool {
+        matches!(&self.prop.underline, Some(u) if u.val != "none")
+    }
+
     pub fn intersect(&self, other: &Style) -> Style {
         fn same_or_none<T: PartialEq + Clone>(a: &Option<T>, b: &Option<T>) -> Option<T> {
             if a == b {
@@ -70,3 +94,48 @@ impl BitAnd for Style {
         self.intersect(&rhs)
     }
 }
+
+/// Only what's set is written out, by its `docx` name (i.e. `b` for bold);
+/// most text has next to no formatting.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Style {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeMap;
+
+        let mut map = serializer.serialize_map(None)?;
+        if let Some(style) = &self.paragraph_style {
+            map.serialize_entry("pStyle", &style.val)?;
+        }
+        if let Some(alignment) = &self.alignment {
+            map.serialize_entry("jc", alignment)?;
+        }
+
+        macro_rules! props {
+            ($($field:ident => $name:literal),* $(,)?) => {$(
+                if let Some(p) = &self.prop.$field {
+                    map.serialize_entry($name, p)?;
+                }
+            )*};
+        }
+        props! {
+            sz => "sz",
+            sz_cs => "szCs",
+            color => "color",
+            highlight => "highlight",
+            vert_align => "vertAlign",
+            underline => "u",
+            bold => "b",
+            bold_cs => "bCs",
+            italic => "i",
+            italic_cs => "iCs",
+            vanish => "vanish",
+            spacing => "spacing",
+            fonts => "rFonts",
+            text_border => "bdr",
+            del => "del",
+            ins => "ins",
+        }
+
+        map.end()
+    }
+}
@@ -1,6 +1,7 @@
 use std::{convert::TryFrom, fmt::{self, Display, } };
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Token {
     StringConst(String),
     Num(f64),
@@ -8,9 +9,57 @@ pub enum Token {
     Keyword(Keyword),
     Sigil(Sigil),
     Operator(Op),
+    Heading(Heading),
+    Interpolation(Interpolation),
+}
+
+/// Tokens as they'd be written; used in error messages.
+impl Display for Token {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::StringConst(s) => write!(fmt, "“{}”", s),
+            Token::Num(n) => write!(fmt, "{}", n),
+            Token::Ident(i) => write!(fmt, "{}", i),
+            Token::Keyword(k) => write!(fmt, "{}", k),
+            Token::Sigil(s) => write!(fmt, "{}", s),
+            Token::Operator(o) => write!(fmt, "{}", o),
+            Token::Heading(h) => write!(fmt, "§ {}", h.title),
+            Token::Interpolation(i) => match i {
+                Interpolation::Start => write!(fmt, "“"),
+                Interpolation::Text(t) => write!(fmt, "{}", t),
+                Interpolation::OpenExpr => write!(fmt, "{{"),
+                Interpolation::CloseExpr => write!(fmt, "}}"),
+                Interpolation::End => write!(fmt, "”"),
+            },
+        }
+    }
+}
+
+/// The pieces of a string literal with `{...}`s in it; the tokens of each
+/// embedded expression go between an `OpenExpr` and a `CloseExpr`.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Interpolation {
+    /// The opening quote.
+    Start,
+    Text(String),
+    OpenExpr,
+    CloseExpr,
+    /// The closing quote.
+    End,
+}
+
+/// A heading paragraph (or a `§` line in plain text sources); these start a
+/// new section.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Heading {
+    pub level: usize,
+    pub title: String,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Keyword {
     Set,
     To,
@@ -33,9 +82,72 @@ pub enum Keyword {
     Else,
     Emit,
     From,
+    Both,
+    Either,
+    Or,
+    Import,
+    Ledger,
+    Where,
+    When,
+    Give,
+    Stop,
+    Skip,
+}
+
+impl TryFrom<&str> for Keyword {
+    type Error = ();
+
+    fn try_from(word: &str) -> Result<Self, ()> {
+        use Keyword::*;
+
+        Ok(match word {
+            "set" => Set,
+            "to" => To,
+            "is" => Is,
+            "do" => Do,
+            "using" => Using,
+            "get" => Get,
+            "also" => Also,
+            "and" => And,
+            "not" => Not,
+            "while" => While,
+            "keep" => Keep,
+            "until" => Until,
+            "run" => Run,
+            "for" => For,
+            "in" => In,
+            "procedure" => Procedure,
+            "takes" => Takes,
+            "does" => Does,
+            "otherwise" | "else" => Else,
+            "emit" => Emit,
+            "from" => From,
+            "both" => Both,
+            "either" => Either,
+            "or" => Or,
+            "import" => Import,
+            "ledger" => Ledger,
+            "where" => Where,
+            "when" => When,
+            "give" => Give,
+            "stop" => Stop,
+            "skip" => Skip,
+            _ => return Err(()),
+        })
+    }
+}
+
+impl Display for Keyword {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Keyword::Else => write!(fmt, "otherwise"),
+            kw => write!(fmt, "{}", format!("{:?}", kw).to_lowercase()),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Sigil {
     StartList = 0,
     EndList = 1,
@@ -46,10 +158,15 @@ pub enum Sigil {
     Exclamation = 6,
     Dot = 7,
     Semicolon = 8,
+    /// A line break that ends a statement.
+    ///
+    /// The lexer doesn't produce these (line breaks are whitespace to it);
+    /// the parser puts them in where a line break can end a statement.
+    Newline = 9,
 }
 
 impl Sigil {
-    pub const SIGILS: &'static str = "[](),?!.;";
+    pub const SIGILS: &'static str = "[](),?!.;\n";
 }
 
 impl Display for Sigil {
@@ -74,12 +191,14 @@ impl TryFrom<&str> for Sigil {
             "!" => Exclamation,
             "." => Dot,
             ";" => Semicolon,
+            "\n" => Newline,
             _ => return Err(()),
         })
     }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Op {
     Add = 0,
     Sub = 1,
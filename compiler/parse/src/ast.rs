@@ -1,14 +1,143 @@
+use std::collections::BTreeMap;
 use std::fmt::{self, Display};
 
 use abogado_lex::{spanned::S, Op};
 
+use crate::interface::Interface;
+
+pub mod arena;
+pub mod tree;
+
+pub use tree::ModuleTree;
+
+/// A whole document (a program or one of the modules it imports); the root of
+/// the AST.
+///
+/// Its imports, exports and procedures are picked out of `body` when it's
+/// made; anything that changes `body` afterwards (like the loader rewriting
+/// import sources) should [`reindex`](Module::reindex) it.
+#[derive(Debug, Clone, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Module {
+    /// Where it came from: a file name or a Google Doc ID.
+    pub name: String,
+    /// The document's custom properties; plain text sources don't have any.
+    pub metadata: BTreeMap<String, String>,
+    /// Every import, including the ones in sections.
+    pub imports: Vec<Import>,
+    /// The names other modules can import from this one.
+    pub exports: Vec<S<Ident>>,
+    /// The procedures defined at the top level (or in sections).
+    pub procedures: Vec<Procedure>,
+    pub body: Vec<S<Statement>>,
+}
+
+impl Module {
+    pub fn new(name: impl Into<String>, body: Vec<S<Statement>>) -> Module {
+        let mut module = Module {
+            name: name.into(),
+            metadata: BTreeMap::new(),
+            imports: vec![],
+            exports: vec![],
+            procedures: vec![],
+            body,
+        };
+        module.reindex();
+        module
+    }
+
+    /// Picks the imports, exports and procedures out of `body` again.
+    pub fn reindex(&mut self) {
+        let statements = self.statements();
+        let imports = statements
+            .iter()
+            .filter_map(|s| match &s.inner {
+                Statement::Import(i) => Some(i.clone()),
+                _ => None,
+            })
+            .collect();
+        let procedures = statements
+            .iter()
+            .filter_map(|s| match &s.inner {
+                Statement::Procedure(p) => Some(p.clone()),
+                _ => None,
+            })
+            .collect();
+        let exports = self.interface().exports().map(|d| d.name.clone()).collect();
+
+        self.imports = imports;
+        self.procedures = procedures;
+        self.exports = exports;
+    }
+
+    /// The statements at the top level, with the ones in sections pulled out
+    /// of them (in order).
+    pub fn statements(&self) -> Vec<&S<Statement>> {
+        fn collect<'m>(body: &'m [S<Statement>], out: &mut Vec<&'m S<Statement>>) {
+            for s in body {
+                match &s.inner {
+                    Statement::Section(sec) => collect(&sec.body, out),
+                    _ => out.push(s),
+                }
+            }
+        }
+
+        let mut out = vec![];
+        collect(&self.body, &mut out);
+        out
+    }
+
+    /// The imports in `body`, to be changed in place; [`reindex`](Module::reindex)
+    /// afterwards.
+    pub fn imports_mut(&mut self) -> Vec<&mut Import> {
+        fn collect<'m>(body: &'m mut [S<Statement>], out: &mut Vec<&'m mut Import>) {
+            for s in body {
+                match &mut s.inner {
+                    Statement::Import(i) => out.push(i),
+                    Statement::Section(sec) => collect(&mut sec.body, out),
+                    _ => {}
+                }
+            }
+        }
+
+        let mut out = vec![];
+        collect(&mut self.body, &mut out);
+        out
+    }
+
+    /// The document's table of contents.
+    pub fn tree(&self) -> ModuleTree {
+        ModuleTree::of(self)
+    }
+
+    pub fn interface(&self) -> Interface {
+        Interface::of(self)
+    }
+}
+
+impl Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in self.body.iter() {
+            writeln!(f, "{}", s.inner)?;
+        }
+
+        Ok(())
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Statement {
     Expr(S<Expr>),
     While(While),
-    // Until(Expr, Expr),
+    Until(Until),
     For(For),
     Procedure(Procedure),
+    Section(Section),
+    Return(Return),
+    Import(Import),
+    Break,
+    Continue,
 }
 
 impl Display for Statement {
@@ -18,8 +147,14 @@ impl Display for Statement {
         match self {
             Expr(e) => write!(fmt, "{}", e.inner),
             While(w) => write!(fmt, "{}", w),
+            Until(u) => write!(fmt, "{}", u),
             For(f) => write!(fmt, "{}", f),
             Procedure(p) => write!(fmt, "{}", p),
+            Section(s) => write!(fmt, "{}", s),
+            Return(r) => write!(fmt, "{}", r),
+            Import(i) => write!(fmt, "{}", i),
+            Break => write!(fmt, "break"),
+            Continue => write!(fmt, "continue"),
         }?;
 
         write!(fmt, ";")
@@ -27,9 +162,12 @@ impl Display for Statement {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Expr {
     If(If),
+    When(When),
     BinOp(BinOp),
+    Logic(Logic),
 
     UnOp(UnOp),
     Call(Call),
@@ -39,10 +177,14 @@ pub enum Expr {
     Ident(Ident),
     List(List),
     Assign(Assign),
+    Update(Update),
+    Ledger(Vec<Entry>),
 
-    Print(S<Box<Expr>>),
+    Print(Box<S<Expr>>),
     Get(Get),
     Block(Block),
+    Lambda(Lambda),
+    Interpolated(Vec<Fragment>),
     //TODO: is op
 }
 
@@ -52,22 +194,175 @@ impl Display for Expr {
 
         match self {
             Assign(a) => write!(fmt, "{}", a),
+            Update(u) => write!(fmt, "{}", u),
+            Lambda(l) => write!(fmt, "{}", l),
+            Ledger(entries) => write!(
+                fmt,
+                "{{ {} }}",
+                entries
+                    .iter()
+                    .map(|e| format!("{:?}: {}", e.key.inner, e.value.inner))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             Block(b) => write!(fmt, "{}", b),
             Print(p) => write!(fmt, "({})!", p.inner),
             If(i) => write!(fmt, "{}", i),
+            When(w) => write!(fmt, "{}", w),
             Call(c) => write!(fmt, "{}", c),
             BinOp(b) => write!(fmt, "{}", b),
+            Logic(l) => write!(fmt, "{}", l),
             UnOp(u) => write!(fmt, "{}", u),
             Num(n) => write!(fmt, "{}", n),
             String(s) => write!(fmt, "\"{}\"", s),
             Ident(i) => write!(fmt, "{}", i),
             List(l) => write!(fmt, "{}", l),
             Get(g) => write!(fmt, "{}", g),
+            Interpolated(fragments) => {
+                write!(fmt, "`")?;
+                for f in fragments {
+                    match f {
+                        Fragment::Text(t) => write!(fmt, "{}", t.inner)?,
+                        Fragment::Expr(e) => write!(fmt, "${{{}}}", e.inner)?,
+                    }
+                }
+                write!(fmt, "`")
+            }
         }
     }
 }
 
+/// `when <subject> is <pattern> then <expr>, is ... otherwise <expr>`
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct When {
+    pub subject: Box<S<Expr>>,
+    pub arms: Vec<S<Arm>>,
+    pub otherwise: Option<Box<S<Expr>>>,
+}
+impl Display for When {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "match ({}) {{ ", self.subject.inner)?;
+        for arm in &self.arms {
+            write!(f, "{}, ", arm.inner)?;
+        }
+        if let Some(o) = &self.otherwise {
+            write!(f, "_ => {} ", o.inner)?;
+        }
+        write!(f, "}}")
+    }
+}
+
+/// `is <pattern> (where <guard>)? then <body>`
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Arm {
+    pub pattern: S<Pattern>,
+    pub guard: Option<S<Expr>>,
+    pub body: S<Expr>,
+}
+impl Display for Arm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.pattern.inner)?;
+        if let Some(g) = &self.guard {
+            write!(f, " if {}", g.inner)?;
+        }
+        write!(f, " => {}", self.body.inner)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Pattern {
+    Num(f64),
+    String(String),
+    /// `an empty list`
+    EmptyList,
+    /// `a list starting with <head> (followed by <rest>)?`
+    ListStartingWith {
+        head: S<Ident>,
+        rest: Option<S<Ident>>,
+    },
+    /// A name matches anything and binds it.
+    Bind(S<Ident>),
+    /// `anything`
+    Anything,
+}
+impl Pattern {
+    /// Whether this matches every value.
+    pub fn is_catch_all(&self) -> bool {
+        matches!(self, Pattern::Bind(_) | Pattern::Anything)
+    }
+}
+impl Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Num(n) => write!(f, "{}", n),
+            Pattern::String(s) => write!(f, "\"{}\"", s),
+            Pattern::EmptyList => write!(f, "[]"),
+            Pattern::ListStartingWith { head, rest: Some(rest) } => {
+                write!(f, "[{}, ...{}]", head.inner, rest.inner)
+            }
+            Pattern::ListStartingWith { head, rest: None } => write!(f, "[{}, ..]", head.inner),
+            Pattern::Bind(name) => write!(f, "{}", name.inner),
+            Pattern::Anything => write!(f, "_"),
+        }
+    }
+}
+
+/// An anonymous procedure: `a procedure taking x that does (...)`.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Lambda {
+    pub args: Vec<S<Ident>>,
+    pub body: Box<S<Expr>>,
+}
+impl Display for Lambda {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "(({}) => {})",
+            self.args.iter().map(|a| a.inner.as_str()).collect::<Vec<_>>().join(", "),
+            self.body.inner
+        )
+    }
+}
+
+/// `<key> is <value>` in a ledger literal.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Entry {
+    pub key: S<String>,
+    pub value: S<Expr>,
+}
+
+/// `set <key> in <target> to <value>`; `target` is a ledger or a list.
+///
+/// As in [`Get`], a bare name for the key is the key itself unless there's a
+/// variable with that name.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Update {
+    pub key: Box<S<Expr>>,
+    pub target: S<Ident>,
+    pub to: Box<S<Expr>>,
+}
+impl Display for Update {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}] = {}", self.target.inner, self.key.inner, self.to.inner)
+    }
+}
+
+/// A part of an interpolated string: “total is {total}”.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Fragment {
+    Text(S<String>),
+    Expr(S<Expr>),
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct List(pub Vec<S<Expr>>);
 impl Display for List {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -86,6 +381,7 @@ impl Display for List {
 pub type Ident = String;
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct While {
     pub cond: Box<S<Expr>>,
     pub body: Box<S<Expr>>,
@@ -95,7 +391,20 @@ impl Display for While {
         write!(f, "while {} do {}", self.cond.inner, self.body.inner)
     }
 }
+/// A loop that runs its body at least once: `keep doing <body> until <cond>`.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Until {
+    pub body: Box<S<Expr>>,
+    pub cond: Box<S<Expr>>,
+}
+impl Display for Until {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "do {} until {}", self.body.inner, self.cond.inner)
+    }
+}
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct For {
     pub name: S<Ident>,
     pub list: Box<S<Expr>>,
@@ -111,6 +420,7 @@ impl Display for For {
     }
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Procedure {
     pub name: S<Ident>,
     pub args: Vec<S<Ident>>,
@@ -131,7 +441,83 @@ impl Display for Procedure {
         )
     }
 }
+/// `give back <value>`; returns from the procedure it's in.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Return {
+    pub value: Box<S<Expr>>,
+}
+impl Display for Return {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "return {}", self.value.inner)
+    }
+}
+
+/// `import "<source>".` or `import <names> from "<source>".`
+///
+/// The source is a `.cado` path, a `.docx` path, or a Google Doc ID or link;
+/// once the driver has loaded it, it's replaced with the key the module was
+/// loaded under.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Import {
+    /// `None` imports everything the module exports.
+    pub names: Option<Vec<S<Ident>>>,
+    pub source: S<String>,
+}
+impl Display for Import {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.names {
+            Some(names) => write!(
+                f,
+                "import {{ {} }} from {:?}",
+                names.iter().map(|n| n.inner.as_str()).collect::<Vec<_>>().join(", "),
+                self.source.inner
+            ),
+            None => write!(f, "import {:?}", self.source.inner),
+        }
+    }
+}
+
+/// A heading and everything under it, up to the next heading of the same (or
+/// a higher) level.
+///
+/// Subsections are [`Statement::Section`]s in `body`; the sections in a
+/// program form a [`ModuleTree`] that mirrors the document's table of
+/// contents.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Section {
+    pub title: S<String>,
+    pub level: usize,
+    pub body: Vec<S<Statement>>,
+}
+impl Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "section \"{}\" {{", self.title.inner)?;
+        for s in self.body.iter() {
+            for line in s.inner.to_string().lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// Picks out the statements to run when the section `title` (a title or a
+/// path of titles; see [`ModuleTree::find`]) is used as the entry point of
+/// `module`: everything that comes before the section, as a prelude, and the
+/// section itself.
+///
+/// Returns `None` if there's no such section.
+pub fn entry_point(module: &Module, title: &str) -> Option<Module> {
+    let tree = module.tree();
+    tree.find(title).map(|node| tree::entry_point(module, node))
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Assign {
     pub name: S<Ident>,
     pub to: Box<S<Expr>>,
@@ -142,7 +528,12 @@ impl Display for Assign {
     }
 }
 
+/// `get <index> from <from>`; `from` is a list, a string or a ledger.
+///
+/// For ledgers, a bare name for the index is the key itself (`get apples from
+/// prices`) unless there's a variable with that name.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Get {
     pub index: Box<S<Expr>>,
     pub from: Box<S<Expr>>,
@@ -155,7 +546,10 @@ impl Display for Get {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Block {
+    /// Expressions, control flow and loops; never procedures, imports or
+    /// sections.
     pub body: Vec<S<Statement>>,
     pub end: Option<Box<S<Expr>>>,
 }
@@ -178,6 +572,7 @@ impl Display for Block {
     }
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct If {
     pub cond: Box<S<Expr>>,
     pub then: Box<S<Expr>>,
@@ -193,6 +588,7 @@ impl Display for If {
     }
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Call {
     pub name: S<Ident>,
     pub args: Vec<S<Expr>>,
@@ -212,6 +608,7 @@ impl Display for Call {
     }
 }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct BinOp {
     pub lhs: Box<S<Expr>>,
     pub op: S<Op>,
@@ -227,7 +624,44 @@ impl Display for BinOp {
     }
 }
 
+/// Logical connectives; these short-circuit (except for `Xor`, which can't).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum LogicOp {
+    And,
+    Or,
+    Xor,
+}
+
+impl Display for LogicOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogicOp::And => write!(f, "&&"),
+            LogicOp::Or => write!(f, "||"),
+            LogicOp::Xor => write!(f, "^^"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Logic {
+    pub lhs: Box<S<Expr>>,
+    pub op: S<LogicOp>,
+    pub rhs: Box<S<Expr>>,
+}
+impl Display for Logic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "({} {} {})",
+            self.lhs.inner, self.op.inner, self.rhs.inner
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum UnaryOperator {
     Neg,
     Not,
@@ -243,6 +677,7 @@ impl Display for UnaryOperator {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct UnOp {
     pub op: S<UnaryOperator>,
     pub expr: Box<S<Expr>>,
@@ -0,0 +1,737 @@
+//! The AST, flattened into an [`Arena`].
+//!
+//! The parser makes a tree (see [`ast`](crate::ast)), and the tree is still
+//! the AST: it's what the parser, the pretty printer and most passes work on.
+//! The arena is a lowering of it for the code that walks a program over and
+//! over or wants to attach results to its nodes (the interpreter and type
+//! inference).
+//!
+//! [`Arena::lower`] moves a tree into an arena where statements and
+//! expressions refer to their children by [`Id`] ([`Arena::tree`] goes back).
+//! Ids are small and `Copy`, so walking a program doesn't need to clone any of
+//! it, and they're stable: passes can key what they find by them (see
+//! [`SideTable`]).
+//!
+//! Spans and styles of statements and expressions live in a side table next
+//! to the nodes ([`Arena::span`], [`Arena::style`]); the smaller things that
+//! aren't nodes of their own (names, patterns, operators) keep theirs inline.
+
+use std::fmt::{self, Debug};
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::ops::Index;
+
+use abogado_lex::{spanned::S, Op, Span, Style};
+
+use super::{Ident, Import, LogicOp, Pattern, UnaryOperator};
+use crate::ast;
+
+/// Refers to a node of type `T` in an [`Arena`].
+pub struct Id<T> {
+    index: u32,
+    node: PhantomData<fn() -> T>,
+}
+
+pub type ExprId = Id<Expr>;
+pub type StatementId = Id<Statement>;
+
+impl<T> Id<T> {
+    fn new(index: usize) -> Self {
+        Id {
+            index: index as u32,
+            node: PhantomData,
+        }
+    }
+
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+// These are by hand so that they don't need `T` to implement them too.
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state)
+    }
+}
+
+impl<T> Debug for Id<T> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "#{}", self.index)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement {
+    Expr(ExprId),
+    While { cond: ExprId, body: ExprId },
+    Until { body: ExprId, cond: ExprId },
+    For {
+        name: S<Ident>,
+        list: ExprId,
+        body: ExprId,
+    },
+    Procedure {
+        name: S<Ident>,
+        args: Vec<S<Ident>>,
+        body: ExprId,
+    },
+    Section {
+        title: S<String>,
+        level: usize,
+        body: Vec<StatementId>,
+    },
+    Return { value: ExprId },
+    Import(Import),
+    Break,
+    Continue,
+}
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    If {
+        cond: ExprId,
+        then: ExprId,
+        otherwise: Option<ExprId>,
+    },
+    When {
+        subject: ExprId,
+        arms: Vec<S<Arm>>,
+        otherwise: Option<ExprId>,
+    },
+    BinOp {
+        lhs: ExprId,
+        op: S<Op>,
+        rhs: ExprId,
+    },
+    Logic {
+        lhs: ExprId,
+        op: S<LogicOp>,
+        rhs: ExprId,
+    },
+    UnOp { op: S<UnaryOperator>, expr: ExprId },
+    Call { name: S<Ident>, args: Vec<ExprId> },
+    Num(f64),
+    String(String),
+    Ident(Ident),
+    List(Vec<ExprId>),
+    Assign { name: S<Ident>, to: ExprId },
+    Update {
+        key: ExprId,
+        target: S<Ident>,
+        to: ExprId,
+    },
+    Ledger(Vec<(S<String>, ExprId)>),
+    Print(ExprId),
+    Get { index: ExprId, from: ExprId },
+    Block {
+        body: Vec<StatementId>,
+        end: Option<ExprId>,
+    },
+    Lambda { args: Vec<S<Ident>>, body: ExprId },
+    Interpolated(Vec<Fragment>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: S<Pattern>,
+    pub guard: Option<ExprId>,
+    pub body: ExprId,
+}
+
+#[derive(Debug, Clone)]
+pub enum Fragment {
+    Text(S<String>),
+    Expr(ExprId),
+}
+
+/// Where a node came from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Meta {
+    pub span: Span,
+    pub style: Style,
+}
+
+/// Statements and expressions, each with a [`Meta`].
+#[derive(Debug, Clone, Default)]
+pub struct Arena {
+    statements: Vec<Statement>,
+    statement_meta: Vec<Meta>,
+    exprs: Vec<Expr>,
+    expr_meta: Vec<Meta>,
+}
+
+/// The kinds of nodes that are in an [`Arena`].
+pub trait Node: Sized {
+    fn nodes(arena: &Arena) -> &[Self];
+    fn meta(arena: &Arena) -> &[Meta];
+    fn push(arena: &mut Arena, node: Self, meta: Meta);
+}
+
+impl Node for Statement {
+    fn nodes(arena: &Arena) -> &[Self] {
+        &arena.statements
+    }
+
+    fn meta(arena: &Arena) -> &[Meta] {
+        &arena.statement_meta
+    }
+
+    fn push(arena: &mut Arena, node: Self, meta: Meta) {
+        arena.statements.push(node);
+        arena.statement_meta.push(meta);
+    }
+}
+
+impl Node for Expr {
+    fn nodes(arena: &Arena) -> &[Self] {
+        &arena.exprs
+    }
+
+    fn meta(arena: &Arena) -> &[Meta] {
+        &arena.expr_meta
+    }
+
+    fn push(arena: &mut Arena, node: Self, meta: Meta) {
+        arena.exprs.push(node);
+        arena.expr_meta.push(meta);
+    }
+}
+
+impl<T: Node> Index<Id<T>> for Arena {
+    type Output = T;
+
+    fn index(&self, id: Id<T>) -> &T {
+        &T::nodes(self)[id.index()]
+    }
+}
+
+impl Arena {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc<T: Node>(&mut self, node: T, span: Span, style: Style) -> Id<T> {
+        let id = Id::new(T::nodes(self).len());
+        T::push(self, node, Meta { span, style });
+        id
+    }
+
+    pub fn meta<T: Node>(&self, id: Id<T>) -> &Meta {
+        &T::meta(self)[id.index()]
+    }
+
+    pub fn span<T: Node>(&self, id: Id<T>) -> &Span {
+        &self.meta(id).span
+    }
+
+    pub fn style<T: Node>(&self, id: Id<T>) -> &Style {
+        &self.meta(id).style
+    }
+
+    /// How many nodes of type `T` there are; ids are `0..len`.
+    pub fn len<T: Node>(&self) -> usize {
+        T::nodes(self).len()
+    }
+
+    /// The statements at the top level of `program`, with the ones in sections
+    /// pulled out of them (in order); like [`Module::statements`].
+    ///
+    /// [`Module::statements`]: ast::Module::statements
+    pub fn statements(&self, program: &[StatementId]) -> Vec<StatementId> {
+        fn collect(arena: &Arena, body: &[StatementId], out: &mut Vec<StatementId>) {
+            for &s in body {
+                match &arena[s] {
+                    Statement::Section { body, .. } => collect(arena, body, out),
+                    _ => out.push(s),
+                }
+            }
+        }
+
+        let mut out = vec![];
+        collect(self, program, &mut out);
+        out
+    }
+
+    /// Moves a program into the arena, giving back the ids of its top-level
+    /// statements.
+    ///
+    /// Lowering more than one program into the same arena is fine (i.e. for
+    /// modules); each keeps its own ids.
+    pub fn lower(&mut self, program: Vec<S<ast::Statement>>) -> Vec<StatementId> {
+        program.into_iter().map(|s| self.lower_statement(s)).collect()
+    }
+
+    fn lower_boxed(&mut self, e: Box<S<ast::Expr>>) -> ExprId {
+        self.lower_expr(*e)
+    }
+
+    fn lower_statement(&mut self, s: S<ast::Statement>) -> StatementId {
+        use ast::Statement as T;
+
+        let S { inner, span, style } = s;
+        let node = match inner {
+            T::Expr(e) => Statement::Expr(self.lower_expr(e)),
+            T::While(ast::While { cond, body }) => Statement::While {
+                cond: self.lower_boxed(cond),
+                body: self.lower_boxed(body),
+            },
+            T::Until(ast::Until { body, cond }) => Statement::Until {
+                body: self.lower_boxed(body),
+                cond: self.lower_boxed(cond),
+            },
+            T::For(ast::For { name, list, body }) => Statement::For {
+                name,
+                list: self.lower_boxed(list),
+                body: self.lower_boxed(body),
+            },
+            T::Procedure(ast::Procedure { name, args, body }) => Statement::Procedure {
+                name,
+                args,
+                body: self.lower_boxed(body),
+            },
+            T::Section(ast::Section { title, level, body }) => Statement::Section {
+                title,
+                level,
+                body: self.lower(body),
+            },
+            T::Return(ast::Return { value }) => Statement::Return {
+                value: self.lower_boxed(value),
+            },
+            T::Import(i) => Statement::Import(i),
+            T::Break => Statement::Break,
+            T::Continue => Statement::Continue,
+        };
+
+        self.alloc(node, span, style)
+    }
+
+    fn lower_expr(&mut self, e: S<ast::Expr>) -> ExprId {
+        use ast::Expr as T;
+
+        let S { inner, span, style } = e;
+        let node = match inner {
+            T::If(ast::If {
+                cond,
+                then,
+                otherwise,
+            }) => Expr::If {
+                cond: self.lower_boxed(cond),
+                then: self.lower_boxed(then),
+                otherwise: otherwise.map(|o| self.lower_boxed(o)),
+            },
+            T::When(ast::When {
+                subject,
+                arms,
+                otherwise,
+            }) => Expr::When {
+                subject: self.lower_boxed(subject),
+                arms: arms
+                    .into_iter()
+                    .map(|arm| {
+                        arm.map(|ast::Arm { pattern, guard, body }| Arm {
+                            pattern,
+                            guard: guard.map(|g| self.lower_expr(g)),
+                            body: self.lower_expr(body),
+                        })
+                    })
+                    .collect(),
+                otherwise: otherwise.map(|o| self.lower_boxed(o)),
+            },
+            T::BinOp(ast::BinOp { lhs, op, rhs }) => Expr::BinOp {
+                lhs: self.lower_boxed(lhs),
+                op,
+                rhs: self.lower_boxed(rhs),
+            },
+            T::Logic(ast::Logic { lhs, op, rhs }) => Expr::Logic {
+                lhs: self.lower_boxed(lhs),
+                op,
+                rhs: self.lower_boxed(rhs),
+            },
+            T::UnOp(ast::UnOp { op, expr }) => Expr::UnOp {
+                op,
+                expr: self.lower_boxed(expr),
+            },
+            T::Call(ast::Call { name, args }) => Expr::Call {
+                name,
+                args: args.into_iter().map(|a| self.lower_expr(a)).collect(),
+            },
+            T::Num(n) => Expr::Num(n),
+            T::String(s) => Expr::String(s),
+            T::Ident(i) => Expr::Ident(i),
+            T::List(ast::List(items)) => {
+                Expr::List(items.into_iter().map(|i| self.lower_expr(i)).collect())
+            }
+            T::Assign(ast::Assign { name, to }) => Expr::Assign {
+                name,
+                to: self.lower_boxed(to),
+            },
+            T::Update(ast::Update { key, target, to }) => Expr::Update {
+                key: self.lower_boxed(key),
+                target,
+                to: self.lower_boxed(to),
+            },
+            T::Ledger(entries) => Expr::Ledger(
+                entries
+                    .into_iter()
+                    .map(|ast::Entry { key, value }| (key, self.lower_expr(value)))
+                    .collect(),
+            ),
+            T::Print(p) => Expr::Print(self.lower_boxed(p)),
+            T::Get(ast::Get { index, from }) => Expr::Get {
+                index: self.lower_boxed(index),
+                from: self.lower_boxed(from),
+            },
+            T::Block(ast::Block { body, end }) => Expr::Block {
+                body: self.lower(body),
+                end: end.map(|e| self.lower_boxed(e)),
+            },
+            T::Lambda(ast::Lambda { args, body }) => Expr::Lambda {
+                args,
+                body: self.lower_boxed(body),
+            },
+            T::Interpolated(fragments) => Expr::Interpolated(
+                fragments
+                    .into_iter()
+                    .map(|f| match f {
+                        ast::Fragment::Text(t) => Fragment::Text(t),
+                        ast::Fragment::Expr(e) => Fragment::Expr(self.lower_expr(e)),
+                    })
+                    .collect(),
+            ),
+        };
+
+        self.alloc(node, span, style)
+    }
+}
+
+impl Arena {
+    /// Rebuilds the tree for `program`; the other way from [`Arena::lower`].
+    pub fn tree(&self, program: &[StatementId]) -> Vec<S<ast::Statement>> {
+        program.iter().map(|&s| self.statement_tree(s)).collect()
+    }
+
+    fn boxed_tree(&self, e: ExprId) -> Box<S<ast::Expr>> {
+        Box::new(self.expr_tree(e))
+    }
+
+    fn wrap<T: Node, N>(&self, id: Id<T>, inner: N) -> S<N> {
+        let Meta { span, style } = self.meta(id).clone();
+        S { inner, span, style }
+    }
+
+    pub fn statement_tree(&self, id: StatementId) -> S<ast::Statement> {
+        use ast::Statement as T;
+
+        let node = match &self[id] {
+            Statement::Expr(e) => T::Expr(self.expr_tree(*e)),
+            Statement::While { cond, body } => T::While(ast::While {
+                cond: self.boxed_tree(*cond),
+                body: self.boxed_tree(*body),
+            }),
+            Statement::Until { body, cond } => T::Until(ast::Until {
+                body: self.boxed_tree(*body),
+                cond: self.boxed_tree(*cond),
+            }),
+            Statement::For { name, list, body } => T::For(ast::For {
+                name: name.clone(),
+                list: self.boxed_tree(*list),
+                body: self.boxed_tree(*body),
+            }),
+            Statement::Procedure { name, args, body } => T::Procedure(ast::Procedure {
+                name: name.clone(),
+                args: args.clone(),
+                body: self.boxed_tree(*body),
+            }),
+            Statement::Section { title, level, body } => T::Section(ast::Section {
+                title: title.clone(),
+                level: *level,
+                body: self.tree(body),
+            }),
+            Statement::Return { value } => T::Return(ast::Return {
+                value: self.boxed_tree(*value),
+            }),
+            Statement::Import(i) => T::Import(i.clone()),
+            Statement::Break => T::Break,
+            Statement::Continue => T::Continue,
+        };
+
+        self.wrap(id, node)
+    }
+
+    pub fn expr_tree(&self, id: ExprId) -> S<ast::Expr> {
+        use ast::Expr as T;
+
+        let node = match &self[id] {
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => T::If(ast::If {
+                cond: self.boxed_tree(*cond),
+                then: self.boxed_tree(*then),
+                otherwise: otherwise.map(|o| self.boxed_tree(o)),
+            }),
+            Expr::When {
+                subject,
+                arms,
+                otherwise,
+            } => T::When(ast::When {
+                subject: self.boxed_tree(*subject),
+                arms: arms
+                    .iter()
+                    .map(|arm| {
+                        arm.clone().map(|Arm { pattern, guard, body }| ast::Arm {
+                            pattern,
+                            guard: guard.map(|g| self.expr_tree(g)),
+                            body: self.expr_tree(body),
+                        })
+                    })
+                    .collect(),
+                otherwise: otherwise.map(|o| self.boxed_tree(o)),
+            }),
+            Expr::BinOp { lhs, op, rhs } => T::BinOp(ast::BinOp {
+                lhs: self.boxed_tree(*lhs),
+                op: op.clone(),
+                rhs: self.boxed_tree(*rhs),
+            }),
+            Expr::Logic { lhs, op, rhs } => T::Logic(ast::Logic {
+                lhs: self.boxed_tree(*lhs),
+                op: op.clone(),
+                rhs: self.boxed_tree(*rhs),
+            }),
+            Expr::UnOp { op, expr } => T::UnOp(ast::UnOp {
+                op: op.clone(),
+                expr: self.boxed_tree(*expr),
+            }),
+            Expr::Call { name, args } => T::Call(ast::Call {
+                name: name.clone(),
+                args: args.iter().map(|&a| self.expr_tree(a)).collect(),
+            }),
+            Expr::Num(n) => T::Num(*n),
+            Expr::String(s) => T::String(s.clone()),
+            Expr::Ident(i) => T::Ident(i.clone()),
+            Expr::List(items) => T::List(ast::List(
+                items.iter().map(|&i| self.expr_tree(i)).collect(),
+            )),
+            Expr::Assign { name, to } => T::Assign(ast::Assign {
+                name: name.clone(),
+                to: self.boxed_tree(*to),
+            }),
+            Expr::Update { key, target, to } => T::Update(ast::Update {
+                key: self.boxed_tree(*key),
+                target: target.clone(),
+                to: self.boxed_tree(*to),
+            }),
+            Expr::Ledger(entries) => T::Ledger(
+                entries
+                    .iter()
+                    .map(|(key, value)| ast::Entry {
+                        key: key.clone(),
+                        value: self.expr_tree(*value),
+                    })
+                    .collect(),
+            ),
+            Expr::Print(p) => T::Print(self.boxed_tree(*p)),
+            Expr::Get { index, from } => T::Get(ast::Get {
+                index: self.boxed_tree(*index),
+                from: self.boxed_tree(*from),
+            }),
+            Expr::Block { body, end } => T::Block(ast::Block {
+                body: self.tree(body),
+                end: end.map(|e| self.boxed_tree(e)),
+            }),
+            Expr::Lambda { args, body } => T::Lambda(ast::Lambda {
+                args: args.clone(),
+                body: self.boxed_tree(*body),
+            }),
+            Expr::Interpolated(fragments) => T::Interpolated(
+                fragments
+                    .iter()
+                    .map(|f| match f {
+                        Fragment::Text(t) => ast::Fragment::Text(t.clone()),
+                        Fragment::Expr(e) => ast::Fragment::Expr(self.expr_tree(*e)),
+                    })
+                    .collect(),
+            ),
+        };
+
+        self.wrap(id, node)
+    }
+}
+
+/// A module's program, in an arena of its own.
+#[derive(Debug, Clone, Default)]
+pub struct Lowered {
+    pub arena: Arena,
+    /// The ids of the top-level statements.
+    pub program: Vec<StatementId>,
+}
+
+impl Lowered {
+    pub fn of(module: &ast::Module) -> Lowered {
+        let mut arena = Arena::new();
+        let program = arena.lower(module.body.clone());
+        Lowered { arena, program }
+    }
+}
+
+/// Something a pass found out about each node of type `T` (or some of them).
+#[derive(Debug, Clone)]
+pub struct SideTable<T, V> {
+    values: Vec<Option<V>>,
+    node: PhantomData<fn() -> T>,
+}
+
+impl<T, V> Default for SideTable<T, V> {
+    fn default() -> Self {
+        SideTable {
+            values: vec![],
+            node: PhantomData,
+        }
+    }
+}
+
+impl<T, V> SideTable<T, V> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, id: Id<T>, value: V) -> Option<V> {
+        if self.values.len() <= id.index() {
+            self.values.resize_with(id.index() + 1, || None);
+        }
+
+        self.values[id.index()].replace(value)
+    }
+
+    pub fn get(&self, id: Id<T>) -> Option<&V> {
+        self.values.get(id.index())?.as_ref()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Id<T>, &V)> + '_ {
+        self.values
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| Some((Id::new(i), v.as_ref()?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_ok;
+
+    /// Every kind of statement and expression.
+    const PROGRAM: &str = "\
+import f and g from “lib.cado”.
+set x to 1.5.
+set greeting to “hi {x + 1}!”.
+set l to [1, -x, not x, “a”].
+set k to a ledger where apples is 3.
+set apples in k to get 0 from l.
+emit both x and l!
+set y to is x > 1 ? x * 2 otherwise (x - 1) / 2.
+procedure p takes a and b does (
+    while a > 0 run (set a to a - 1. skip ahead).
+    run (emit n!) for n in l.
+    keep doing (set b to b + 1) until b > 10.
+    give back b
+)
+set h to a procedure taking n that does do p using n, 2.
+emit when l is a list starting with first followed by rest where first > 1 then rest, \
+otherwise 4!
+set last to (set x to 1. x.)
+§ Section
+stop.
+";
+
+    #[test]
+    fn lowering_round_trips() {
+        let module = parse_ok(PROGRAM);
+        let mut arena = Arena::new();
+        let program = arena.lower(module.body.clone());
+
+        // Without `#`, `Debug` shows every span and style too.
+        assert_eq!(format!("{:?}", arena.tree(&program)), format!("{:?}", module.body));
+    }
+
+    #[test]
+    fn nodes_keep_their_spans_and_styles() {
+        let module = parse_ok("set x to 1 + 2.\n§ Section\nemit x!");
+        let mut arena = Arena::new();
+        let program = arena.lower(module.body.clone());
+
+        // Children are lowered before their parents.
+        assert_eq!(arena.len::<Statement>(), 3);
+        assert_eq!(arena.len::<Expr>(), 6);
+
+        for (id, original) in program.iter().zip(&module.body) {
+            assert_eq!(arena.span(*id), &original.span);
+            assert_eq!(arena.style(*id), &original.style);
+        }
+
+        let assign = match &arena[program[0]] {
+            Statement::Expr(e) => *e,
+            other => panic!("expected an expression, got {:?}", other),
+        };
+        match &arena[assign] {
+            Expr::Assign { name, to } => {
+                assert_eq!(name.inner, "x");
+                assert!(matches!(arena[*to], Expr::BinOp { .. }));
+            }
+            other => panic!("expected an assignment, got {:?}", other),
+        }
+
+        // Sections are flattened, like `Module::statements`.
+        let flat = arena.statements(&program);
+        assert_eq!(flat.len(), 2);
+        assert!(matches!(
+            &arena[flat[1]],
+            Statement::Expr(e) if matches!(arena[*e], Expr::Print(_))
+        ));
+    }
+
+    #[test]
+    fn lowering_more_than_one_program_keeps_their_ids_apart() {
+        let mut arena = Arena::new();
+        let first = arena.lower(parse_ok("emit 1!").body);
+        let second = arena.lower(parse_ok("emit 2!").body);
+
+        assert_ne!(first, second);
+        assert_eq!(
+            format!("{:?}", arena.tree(&second)),
+            format!("{:?}", parse_ok("emit 2!").body)
+        );
+    }
+
+    #[test]
+    fn side_tables_are_keyed_by_id() {
+        let mut arena = Arena::new();
+        let program = arena.lower(parse_ok("emit 1!\nemit 2!").body);
+
+        let mut table = SideTable::new();
+        assert_eq!(table.insert(program[1], "second"), None);
+        assert_eq!(table.insert(program[1], "again"), Some("second"));
+
+        assert_eq!(table.get(program[0]), None);
+        assert_eq!(table.get(program[1]), Some(&"again"));
+        assert_eq!(table.iter().collect::<Vec<_>>(), vec![(program[1], &"again")]);
+    }
+}
@@ -0,0 +1,233 @@
+//! A module's table of contents, as a tree of its sections.
+//!
+//! Every heading in a document starts a section, and the sections under it
+//! (the ones with lower-level headings) are its children. Sections are found
+//! by their title or, when a title is used more than once, by a path of
+//! titles from the top: `Setup/Helpers`.
+
+use std::fmt::{self, Display};
+
+use abogado_lex::spanned::S;
+
+use super::{Module, Section, Statement};
+
+/// A module and the sections in it.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ModuleTree {
+    /// The module's name.
+    pub name: String,
+    pub sections: Vec<Node>,
+}
+
+/// A section in a [`ModuleTree`].
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Node {
+    pub title: S<String>,
+    pub level: usize,
+    /// Where the section is: the index of each enclosing section's statement
+    /// in its parent's body, ending with this section's.
+    pub path: Vec<usize>,
+    pub children: Vec<Node>,
+}
+
+impl ModuleTree {
+    pub fn of(module: &Module) -> ModuleTree {
+        fn nodes(body: &[S<Statement>], parent: &[usize]) -> Vec<Node> {
+            body.iter()
+                .enumerate()
+                .filter_map(|(i, s)| match &s.inner {
+                    Statement::Section(Section { title, level, body }) => {
+                        let mut path = parent.to_vec();
+                        path.push(i);
+
+                        Some(Node {
+                            title: title.clone(),
+                            level: *level,
+                            children: nodes(body, &path),
+                            path,
+                        })
+                    }
+                    _ => None,
+                })
+                .collect()
+        }
+
+        ModuleTree {
+            name: module.name.clone(),
+            sections: nodes(&module.body, &[]),
+        }
+    }
+
+    /// Finds a section by its title or by a `/`-separated path of titles.
+    ///
+    /// A title on its own can name a section anywhere in the tree (the first
+    /// one, in document order); the parts of a path each name a child of the
+    /// section before.
+    pub fn find(&self, query: &str) -> Option<&Node> {
+        fn anywhere<'t>(nodes: &'t [Node], title: &str) -> Option<&'t Node> {
+            nodes.iter().find_map(|n| {
+                if n.title.inner.trim() == title {
+                    Some(n)
+                } else {
+                    anywhere(&n.children, title)
+                }
+            })
+        }
+
+        let query = query.trim();
+        if let Some(n) = anywhere(&self.sections, query) {
+            return Some(n);
+        }
+
+        let mut parts = query.split('/').map(str::trim);
+        let first = parts.next()?;
+        let mut node = self.sections.iter().find(|n| n.title.inner.trim() == first)?;
+        for part in parts {
+            node = node.children.iter().find(|n| n.title.inner.trim() == part)?;
+        }
+
+        Some(node)
+    }
+
+    /// Every section, in document order.
+    pub fn iter(&self) -> impl Iterator<Item = &Node> + '_ {
+        fn collect<'t>(nodes: &'t [Node], out: &mut Vec<&'t Node>) {
+            for n in nodes {
+                out.push(n);
+                collect(&n.children, out);
+            }
+        }
+
+        let mut out = vec![];
+        collect(&self.sections, &mut out);
+        out.into_iter()
+    }
+}
+
+/// Shows the tree like a table of contents: one section per line, indented
+/// by its depth.
+impl Display for ModuleTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.name)?;
+        for n in self.iter() {
+            writeln!(f, "{}{}", "  ".repeat(n.path.len()), n.title.inner.trim())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Picks out the statements to run when `node` is used as the entry point of
+/// `module`: everything that comes before the section (including earlier
+/// sections, as a prelude) and the section itself.
+///
+/// `node` has to come from `module`'s [`ModuleTree`].
+pub fn entry_point(module: &Module, node: &Node) -> Module {
+    fn up_to(body: &[S<Statement>], path: &[usize]) -> Vec<S<Statement>> {
+        let (&here, rest) = match path.split_first() {
+            Some(p) => p,
+            None => return vec![],
+        };
+
+        let mut prelude = body[..here].to_vec();
+        let s = &body[here];
+        match (&s.inner, rest.is_empty()) {
+            (_, true) => prelude.push(s.clone()),
+            (Statement::Section(sec), false) => prelude.push(S {
+                inner: Statement::Section(Section {
+                    body: up_to(&sec.body, rest),
+                    ..sec.clone()
+                }),
+                ..s.clone()
+            }),
+            (_, false) => unreachable!("section paths only go through sections"),
+        }
+
+        prelude
+    }
+
+    Module {
+        metadata: module.metadata.clone(),
+        ..Module::new(module.name.clone(), up_to(&module.body, &node.path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn module(src: &str) -> Module {
+        let (tokens, source) = abogado_lex::lex_cado(src.to_string());
+        let (module, errors) = crate::parse("test.cado", tokens.unwrap(), &source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        module
+    }
+
+    const DOC: &str = "\
+set a to 1.
+§ Setup
+set b to 2.
+§§ Helpers
+set c to 3.
+§ Main
+set d to 4.
+§§ Helpers
+set e to 5.
+§ Later
+set f to 6.
+";
+
+    #[test]
+    fn headings_make_a_tree() {
+        let tree = module(DOC).tree();
+        let titles = |nodes: &[Node]| {
+            nodes.iter().map(|n| n.title.inner.clone()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(titles(&tree.sections), ["Setup", "Main", "Later"]);
+        assert_eq!(titles(&tree.sections[0].children), ["Helpers"]);
+        assert_eq!(titles(&tree.sections[1].children), ["Helpers"]);
+        assert!(tree.sections[2].children.is_empty());
+        assert_eq!(
+            tree.to_string(),
+            "test.cado\n  Setup\n    Helpers\n  Main\n    Helpers\n  Later\n",
+        );
+    }
+
+    #[test]
+    fn sections_are_found_by_title_or_path() {
+        let tree = module(DOC).tree();
+
+        assert_eq!(tree.find("Main").unwrap().path, [2]);
+        // Repeated titles find the first one unless there's a path.
+        assert_eq!(tree.find("Helpers").unwrap().path, [1, 1]);
+        assert_eq!(tree.find("Main/Helpers").unwrap().path, [2, 1]);
+        assert_eq!(tree.find(" Main / Helpers ").unwrap().path, [2, 1]);
+        assert!(tree.find("Later/Helpers").is_none());
+        assert!(tree.find("Nowhere").is_none());
+    }
+
+    #[test]
+    fn entry_points_keep_the_prelude() {
+        let module = module(DOC);
+        let tree = module.tree();
+
+        let main = entry_point(&module, tree.find("Main/Helpers").unwrap());
+        let assigned = main
+            .statements()
+            .into_iter()
+            .map(|s| match &s.inner {
+                Statement::Expr(e) => match &e.inner {
+                    crate::ast::Expr::Assign(a) => a.name.inner.clone(),
+                    _ => unreachable!(),
+                },
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(assigned, ["a", "b", "c", "d", "e"]);
+        assert_eq!(main.tree().iter().count(), 4);
+    }
+}
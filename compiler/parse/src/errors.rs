@@ -0,0 +1,193 @@
+//! Turns the parser's errors into messages in plain English.
+//!
+//! Chumsky reports what it expected as a set of tokens (or as the labels of
+//! the parsers that failed); these are spelled out the way you'd say them:
+//! `expected the word ‘to’ after ‘set total’`.
+
+use std::convert::TryFrom;
+
+use chumsky::error::Simple;
+use thiserror::Error;
+
+use abogado_lex::{Keyword, Sigil, Span, Token};
+
+use super::Tok;
+
+#[derive(Debug, Error, Clone, PartialEq)]
+#[error("{message}")]
+pub struct SyntaxError {
+    pub message: String,
+    /// Where the parser gave up.
+    pub span: Span,
+    /// What was there instead, as it'd be said in a message (`None` if the
+    /// statement just stopped).
+    pub found: Option<String>,
+    /// Everything that was skipped to get past the error.
+    pub skipped: Span,
+}
+
+impl SyntaxError {
+    /// The spans this error points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        let here = match self.found {
+            Some(_) => "not this",
+            None => "the statement ends here",
+        };
+
+        vec![(&self.span, here), (&self.skipped, "this was skipped")]
+    }
+
+    /// `tokens` are the ones that were skipped; `error` is what chumsky said
+    /// when it tried to parse them as a statement (if it could).
+    pub(crate) fn new(tokens: &[Tok], skipped: Span, error: Option<&Simple<Tok, Span>>) -> Self {
+        let error = match error {
+            Some(error) => error,
+            None => {
+                return SyntaxError {
+                    message: format!("couldn't make sense of ‘{}’", words(tokens)),
+                    span: skipped.clone(),
+                    found: None,
+                    skipped,
+                }
+            }
+        };
+
+        // Chumsky doesn't give the end of the input a useful span; the end of
+        // the last token is better.
+        let span = match error.found() {
+            Some(found) => found.span.clone(),
+            None => {
+                let end = tokens.last().map(|t| t.span.inner.end).unwrap_or(0);
+                Span::from(end..end)
+            }
+        };
+
+        let before = tokens
+            .iter()
+            .take_while(|t| t.span.inner.end <= span.inner.start)
+            .collect::<Vec<_>>();
+        let after = match before.len() {
+            0 => String::new(),
+            n if n > CONTEXT => {
+                format!(" after ‘…{}’", words(before[n - CONTEXT..].iter().copied()))
+            }
+            _ => format!(" after ‘{}’", words(before)),
+        };
+
+        let found = error.found().map(|t| match t.inner {
+            Token::Sigil(Sigil::Newline) => String::from("the end of the line"),
+            ref other => format!("‘{}’", other),
+        });
+        let message = match (expected(error), &found) {
+            (Some(what), _) => format!("expected {}{}", what, after),
+            (None, Some(found)) => format!("didn't expect {}{}", found, after),
+            (None, None) => format!("this statement isn't finished{}", after),
+        };
+
+        SyntaxError { message, span, found, skipped }
+    }
+}
+
+/// How many of the tokens before an error are quoted in its message.
+const CONTEXT: usize = 4;
+
+fn words<'t>(tokens: impl IntoIterator<Item = &'t Tok>) -> String {
+    tokens
+        .into_iter()
+        .filter(|t| t.inner != Token::Sigil(Sigil::Newline))
+        .map(|t| t.inner.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// What `error` wanted, in English: its label if it has one and otherwise
+/// the tokens it would have taken.
+fn expected(error: &Simple<Tok, Span>) -> Option<String> {
+    if let Some(label) = error.label() {
+        return Some(label_name(label));
+    }
+
+    let mut options = error
+        .expected()
+        .map(|t| match t {
+            Some(t) => token_name(&t.inner),
+            None => String::from("the end of the statement"),
+        })
+        .collect::<Vec<_>>();
+    options.sort();
+    options.dedup();
+
+    match options.len() {
+        0 => None,
+        1 => options.pop(),
+        n => {
+            let last = options.pop().unwrap();
+            let sep = if n == 2 { " " } else { ", " };
+            Some(format!("{}{}or {}", options.join(", "), sep, last))
+        }
+    }
+}
+
+fn token_name(token: &Token) -> String {
+    match token {
+        Token::Keyword(kw) => format!("the word ‘{}’", kw),
+        Token::Ident(w) => format!("the word ‘{}’", w),
+        Token::Num(_) => String::from("a number"),
+        Token::StringConst(_) | Token::Interpolation(_) => String::from("a string"),
+        Token::Heading(_) => String::from("a heading"),
+        Token::Sigil(Sigil::Newline) => String::from("the end of the line"),
+        other => format!("‘{}’", other),
+    }
+}
+
+/// Labels are the names given to parsers with `.labelled(...)`: keywords use
+/// their variant's name, sigils their filter's name and plain words
+/// themselves.
+fn label_name(label: &str) -> String {
+    let sigil = |s: Sigil| format!("‘{}’", s);
+
+    match label {
+        "start_list" => sigil(Sigil::StartList),
+        "end_list" => sigil(Sigil::EndList),
+        "start_block" => sigil(Sigil::StartBlock),
+        "end_block" => sigil(Sigil::EndBlock),
+        "comma" => sigil(Sigil::Comma),
+        "question" => sigil(Sigil::Question),
+        "exclamation" => sigil(Sigil::Exclamation),
+        "dot" => sigil(Sigil::Dot),
+        "semicolon" => sigil(Sigil::Semicolon),
+        "newline" => String::from("the end of the line"),
+        "terminator" => String::from("a ‘.’ or the end of the line"),
+
+        "heading" => String::from("a heading"),
+        "items" => String::from("some items"),
+        "list" => String::from("a list"),
+        "num" => String::from("a number"),
+        "string" | "interpolated string" => String::from("a string"),
+        "ledger" => String::from("a ledger"),
+        "unary op" | "atom" | "product" | "sum" | "compare" | "connective" | "list get" => {
+            String::from("a value")
+        }
+        "print" => String::from("an ‘emit’"),
+        "block" => String::from("a block in parentheses"),
+        "call" => String::from("a procedure call"),
+        "assignment" => String::from("an assignment"),
+        "update" => String::from("an update"),
+        "conditional" => String::from("a conditional"),
+        "pattern" => String::from("a pattern"),
+        "arm" => String::from("a case"),
+        "when" => String::from("a ‘when’"),
+        "anonymous procedure" => String::from("a procedure"),
+        "control flow" => String::from("‘give back’, ‘stop’ or ‘skip ahead’"),
+        "import source" => String::from("the file or document to import from"),
+        "import" => String::from("an import"),
+
+        kw if kw.starts_with(char::is_uppercase) => {
+            match Keyword::try_from(kw.to_lowercase().as_str()) {
+                Ok(kw) => format!("the word ‘{}’", kw),
+                Err(()) => kw.to_lowercase(),
+            }
+        }
+        word => format!("the word ‘{}’", word),
+    }
+}
@@ -0,0 +1,123 @@
+//! What a module exposes to the modules that import it.
+//!
+//! Underlining a procedure's name (or the target of a top-level `set`) exports
+//! it. Only underlined names are exported: a document that doesn't underline
+//! any of its definitions doesn't export anything (and neither do `.cado`
+//! files, which can't underline).
+//!
+//! Definitions inside a border are private to it (see `abogado_passes::privacy`)
+//! so they're never exported, underlined or not.
+
+use abogado_lex::{spanned::S, Span, Style};
+
+use crate::ast::{Expr, Ident, Module, Statement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionKind {
+    Procedure,
+    Binding,
+}
+
+/// A top-level definition in a module.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: S<Ident>,
+    pub kind: DefinitionKind,
+    pub underlined: bool,
+    /// The border the definition is private to, if it's in one.
+    pub border: Option<Span>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Interface {
+    pub definitions: Vec<Definition>,
+}
+
+impl Interface {
+    pub fn of(module: &Module) -> Interface {
+        let regions = regions(module);
+
+        // Sections are just a way to organize a module; what's in them is
+        // still at the top level.
+        let definitions = module
+            .statements()
+            .into_iter()
+            .filter_map(|s| match &s.inner {
+                Statement::Procedure(p) => Some((s, &p.name, DefinitionKind::Procedure)),
+                Statement::Expr(e) => match &e.inner {
+                    Expr::Assign(a) => Some((s, &a.name, DefinitionKind::Binding)),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .map(|(s, name, kind)| Definition {
+                name: name.clone(),
+                kind,
+                underlined: name.style.is_underlined(),
+                border: region_of(&regions, &name.style, &name.span, &s.span),
+            })
+            .collect();
+
+        Interface { definitions }
+    }
+
+    pub fn exports(&self) -> impl Iterator<Item = &Definition> + '_ {
+        self.definitions.iter().filter(|d| d.underlined && d.border.is_none())
+    }
+
+    pub fn export(&self, name: &str) -> Option<&Definition> {
+        self.exports().find(|d| d.name.inner == name)
+    }
+
+    pub fn definition(&self, name: &str) -> Option<&Definition> {
+        self.definitions.iter().find(|d| d.name.inner == name)
+    }
+}
+
+/// Finds the bordered regions in a module: runs of consecutive bordered
+/// top-level statements.
+pub fn regions(module: &Module) -> Vec<Span> {
+    fn collect(program: &[S<Statement>], regions: &mut Vec<Span>) {
+        let mut current: Option<Span> = None;
+
+        for s in program {
+            if let Statement::Section(sec) = &s.inner {
+                regions.extend(current.take());
+                collect(&sec.body, regions);
+            } else if s.style.is_bordered() {
+                current = Some(match current.take() {
+                    Some(region) => region | s.span.clone(),
+                    None => s.span.clone(),
+                });
+            } else {
+                regions.extend(current.take());
+            }
+        }
+
+        regions.extend(current);
+    }
+
+    let mut regions = vec![];
+    collect(&module.body, &mut regions);
+    regions
+}
+
+/// The region a definition is private to, if it's private.
+///
+/// Definitions are private when their name is bordered. Usually the whole
+/// statement is too, in which case the definition belongs to the statement's
+/// region; if just the name is bordered the definition is private to the
+/// top-level statement it's in (`statement`).
+pub fn region_of(regions: &[Span], name: &Style, span: &Span, statement: &Span) -> Option<Span> {
+    if !name.is_bordered() {
+        return None;
+    }
+
+    Some(
+        regions
+            .iter()
+            .find(|r| r.contains(span))
+            .cloned()
+            .unwrap_or_else(|| statement.clone()),
+    )
+}
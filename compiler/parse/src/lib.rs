@@ -9,6 +9,12 @@
 )]
 
 pub mod ast;
+pub mod errors;
+pub mod interface;
+pub mod pretty;
+pub mod visit;
+
+use std::{collections::HashSet, rc::Rc};
 
 use abogado_lex as lex;
 
@@ -16,9 +22,10 @@ use chumsky::prelude::*;
 pub use chumsky::Parser;
 
 use ast::*;
+use errors::SyntaxError;
 use lex::{
     spanned::S,
-    Keyword,
+    Heading, Interpolation, Keyword,
     Op, Sigil, Span, Token,
 };
 
@@ -55,6 +62,16 @@ kw_filters! {
     otherwise  => Else,
     emit       => Emit,
     from       => From,
+    both       => Both,
+    either     => Either,
+    alternatively => Or,
+    import     => Import,
+    ledger     => Ledger,
+    where_kw   => Where,
+    when       => When,
+    give       => Give,
+    stop       => Stop,
+    skip_kw    => Skip,
 }
 
 macro_rules! sigil_filters {
@@ -77,6 +94,22 @@ sigil_filters! {
     exclamation => Exclamation,
     dot         => Dot,
     semicolon   => Semicolon,
+    newline     => Newline,
+}
+
+/// What ends a statement: a `.`, `;`, `!` or `?`, or a line break (see
+/// [`line_breaks`]).
+///
+/// `!` also ends an `emit`, so a statement that's just an `emit` doesn't take
+/// one of these (see [`printed`]). `?` can't be confused with the one in a
+/// conditional: that one always comes right after the condition.
+fn terminator() -> impl Clone + Parser<Tok, Tok, Error = Simple<Tok, Span>> {
+    dot()
+        .or(semicolon())
+        .or(exclamation())
+        .or(question())
+        .or(newline())
+        .labelled("terminator")
 }
 
 macro_rules! bin_op_filters {
@@ -121,30 +154,69 @@ fn ident() -> impl Clone + Parser<Tok, S<Ident>, Error = Simple<Tok, Span>> {
     })
 }
 
-// TODO
-//
-// eventually support (for comma):
-//   - inner
-//   - inner as well as inner
-//   - inner,+ inner, and inner
-fn delimited<T>(
+/// A plain word that isn't a keyword (i.e. the `doing` in `keep doing`).
+fn word(word: &'static str) -> impl Clone + Parser<Tok, Tok, Error = Simple<Tok, Span>> {
+    filter::<_, _, Simple<Tok, Span>>(move |t: &Tok| matches!(&t.inner, Token::Ident(w) if w == word))
+        .labelled(word)
+}
+
+fn heading() -> impl Clone + Parser<Tok, S<Heading>, Error = Simple<Tok, Span>> {
+    filter(|t: &Tok| matches!(t.inner, Token::Heading(_)))
+        .map(|t: Tok| {
+            t.map(|tok| match tok {
+                Token::Heading(heading) => heading,
+                _ => unreachable!(),
+            })
+        })
+        .labelled("heading")
+}
+
+/// A list, written the way you'd write it in English:
+///   - `inner`
+///   - `inner as well as inner`
+///   - `inner, inner, inner` (just commas)
+///   - `inner, inner, and inner` (with or without the last comma)
+///
+/// This can be empty.
+///
+/// An item that's a `both` takes the `and` after it before the list can:
+/// `do f using both a and b, c` has two arguments, `both a and b` and `c`
+/// (and so does `do f using both a and b and c`). The `ambiguity` pass asks
+/// for parentheses around those items, since they read like two.
+fn listed<T>(
     inner: impl Clone + Parser<Tok, S<T>, Error = Simple<Tok, Span>>,
-    delimiter: Sigil,
 ) -> impl Clone + Parser<Tok, Vec<S<T>>, Error = Simple<Tok, Span>> {
-    let comma =
-        filter(move |tok: &Tok| matches!(tok.inner, Token::Sigil(sigil) if sigil == delimiter));
+    let as_well_as = word("as").then(word("well")).then(word("as"));
+
+    let commas = comma()
+        .padding_for(inner.clone())
+        .repeated()
+        .then(comma().or_not().then(and()).padding_for(inner.clone()).or_not())
+        .map(|(mut items, last)| {
+            items.extend(last);
+            items
+        });
+    let rest = as_well_as
+        .padding_for(inner.clone())
+        .map(|second| vec![second])
+        .or(commas);
 
     inner
-        .clone()
-        .chain(comma.clone().padding_for(inner.clone()).repeated())
+        .then(rest)
+        .map(|(first, rest)| {
+            let mut items = vec![first];
+            items.extend(rest);
+            items
+        })
         .or_not()
-        .map(|m| m.unwrap_or(vec![]))
+        .map(|m| m.unwrap_or_default())
+        .labelled("items")
 }
 
 pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
     recursive::<Tok, S<Expr>, _, _, Simple<Tok, Span>>(|expr| {
         let list = start_list()
-            .then(delimited(expr.clone(), Sigil::Comma))
+            .then(listed(expr.clone()))
             .then(end_list())
             .map(|((start, content), end)| {
                 S{
@@ -172,6 +244,80 @@ pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
             })
             .labelled("string");
 
+        let piece = |p: Interpolation| {
+            filter::<_, _, Simple<Tok, Span>>(move |t: &Tok| matches!(&t.inner, Token::Interpolation(i) if *i == p))
+        };
+        let text = filter(|t: &Tok| matches!(t.inner, Token::Interpolation(Interpolation::Text(_))))
+            .map(|t: Tok| {
+                Fragment::Text(t.map(|tok| match tok {
+                    Token::Interpolation(Interpolation::Text(s)) => s,
+                    _ => unreachable!(),
+                }))
+            });
+        let embedded = piece(Interpolation::OpenExpr)
+            .padding_for(expr.clone())
+            .then(piece(Interpolation::CloseExpr))
+            .map(|(e, _)| Fragment::Expr(e));
+        let interpolated = piece(Interpolation::Start)
+            .then(text.or(embedded).repeated())
+            .then(piece(Interpolation::End))
+            .map(|((start, fragments), end)| S {
+                span: start.clone() | end.clone(),
+                style: fragments
+                    .iter()
+                    .map(|f| match f {
+                        Fragment::Text(t) => t.style.clone(),
+                        Fragment::Expr(e) => e.style.clone(),
+                    })
+                    .fold(start & end, |acc, s| acc & s),
+                inner: Expr::Interpolated(fragments),
+            })
+            .labelled("interpolated string");
+
+        // `a ledger where apples is 3 and pears is 4`, or `an empty ledger`.
+        let key = ident().or(filter(|t: &Tok| matches!(t.inner, Token::StringConst(_))).map(
+            |t: Tok| {
+                t.map(|tok| match tok {
+                    Token::StringConst(s) => s,
+                    _ => unreachable!(),
+                })
+            },
+        ));
+        let entry = key
+            .then(is())
+            .then(expr.clone())
+            .map(|((key, is), value)| S {
+                span: key.clone() | value.clone(),
+                style: key.clone() & is & value.clone(),
+                inner: Entry { key, value },
+            });
+        let article = word("a").or(word("an")).or_not();
+        let filled = article
+            .clone()
+            .then(ledger())
+            .then(where_kw())
+            .then(listed(entry))
+            .map(|(((a, ledger), where_), entries)| {
+                let start = a.unwrap_or_else(|| ledger.clone());
+                S {
+                    span: entries.iter().fold(start.clone() | where_.clone(), |acc, e| acc | e.clone()),
+                    style: entries.iter().fold(start & ledger & where_, |acc, e| acc & e.clone()),
+                    inner: Expr::Ledger(entries.into_iter().map(|e| e.inner).collect()),
+                }
+            });
+        let empty = article
+            .then(word("empty"))
+            .then(ledger())
+            .map(|((a, empty), ledger)| {
+                let start = a.unwrap_or_else(|| empty.clone());
+                S {
+                    span: start.clone() | ledger.clone(),
+                    style: start & empty & ledger,
+                    inner: Expr::Ledger(vec![]),
+                }
+            });
+        let ledger = filled.or(empty).labelled("ledger");
+
         let op = un_not()
             .map(|t| t.map(|_| UnaryOperator::Not))
             .or(un_neg().map(|t| t.map(|_| UnaryOperator::Neg)));
@@ -189,10 +335,12 @@ pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
             })
             .labelled("unary op");
 
-            ident()
-                .map(|i| i.map(Expr::Ident))
+            // Before identifiers so that the `a` in `a ledger` isn't one.
+            ledger
+                .or(ident().map(|i| i.map(Expr::Ident)))
                 .or(num)
                 .or(string)
+                .or(interpolated)
                 .or(list)
                 .or(un_op)
                 .labelled("atom")
@@ -257,15 +405,59 @@ pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
             })
             .labelled("compare");
 
-        let emit = emit()
-            .then(expr.clone())
-            .then(exclamation())
-            .map(|((emit, expr), exclam)| S {
-                span: emit.clone() | expr.clone() | exclam.clone(),
-                style: emit.clone() & expr.clone() & exclam.clone(),
-                inner: Expr::Print(expr.map(Box::new)),
-            })
-            .labelled("print");
+        // `both a and b`, `either a or b`, and `either a or b but not both`.
+        //
+        // The operands are comparisons (or other connectives) so these bind
+        // more loosely than `=`, `<`, and `>`.
+        let logic = recursive::<Tok, S<Expr>, _, _, Simple<Tok, Span>>(|logic| {
+            let operand = logic.or(compare.clone());
+
+            let conjunction = both()
+                .then(operand.clone())
+                .then(and())
+                .then(operand.clone())
+                .map(|(((both, lhs), and), rhs)| S {
+                    span: both.clone() | lhs.clone() | and.clone() | rhs.clone(),
+                    style: both.clone() & lhs.clone() & and & rhs.clone(),
+                    inner: Expr::Logic(Logic {
+                        lhs: Box::new(lhs),
+                        op: both.map(|_| LogicOp::And),
+                        rhs: Box::new(rhs),
+                    }),
+                });
+
+            let disjunction = either()
+                .then(operand.clone())
+                .then(alternatively())
+                .then(operand)
+                .then(word("but").then(not()).then(both()).or_not())
+                .map(|((((either, lhs), or), rhs), exclusive)| {
+                    let mut span = either.clone() | lhs.clone() | or.clone() | rhs.clone();
+                    let mut style = either.clone() & lhs.clone() & or & rhs.clone();
+
+                    let op = if let Some(((but, not), both)) = exclusive {
+                        span = span | both.clone();
+                        style = style & but & not & both;
+                        LogicOp::Xor
+                    } else {
+                        LogicOp::Or
+                    };
+
+                    S {
+                        span,
+                        style,
+                        inner: Expr::Logic(Logic {
+                            lhs: Box::new(lhs),
+                            op: either.map(|_| op),
+                            rhs: Box::new(rhs),
+                        }),
+                    }
+                });
+
+            conjunction.or(disjunction).labelled("connective")
+        });
+
+        let emit = print(expr.clone());
 
         let get = get()
             .then(expr.clone())
@@ -281,55 +473,53 @@ pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
             })
             .labelled("list get");
 
-        // TODO: the trailing_dot logic is never run!
+        // Blocks hold expressions, control flow (`stop`, etc.) and loops.
+        let item = control(expr.clone())
+            .or(loops(expr.clone()))
+            .or(expr.clone().map(|e| S {
+                span: e.span.clone(),
+                style: e.style.clone(),
+                inner: Statement::Expr(e),
+            }));
+        let terminated = printed(expr.clone()).or(item.clone().then(terminator()).map(
+            |(s, punc)| S {
+                span: s.span.clone() | punc.clone(),
+                style: s.style.clone() & punc,
+                inner: s.inner,
+            },
+        ));
+
+        // The block's value is its last expression, whether or not it's
+        // terminated: `(set x to 1. x)` and `(set x to 1. x.)` are both `x`.
         let block = start_block()
-            .then(delimited(expr.clone(), Sigil::Dot))
-            .then(dot().or_not())
+            .then(terminated.repeated())
+            .then(item.or_not())
             .then(end_block())
-            .map(|(((s, exprs), trailing_dot), e)| S {
-                span: exprs
-                    .iter()
-                    .fold(s.clone() | s.clone(), |acc, e| acc | e.clone())
-                    | e.clone(),
-                style: exprs
-                    .iter()
-                    .fold(s.clone() & s.clone(), |acc, e| acc & e.clone())
-                    & e.clone(),
-                inner: Expr::Block(Block {
-                    body: {
-                        let exprs = if trailing_dot.is_none() && exprs.len() >= 1 {
-                            &exprs[..exprs.len() - 1]
-                        } else {
-                            &exprs[..]
-                        };
-
-                        // the outer S<Statement>'s span should include the `.`... but it doesn't!
-                        //
-                        // this is okay for now (TODO)
-                        exprs
-                            .iter()
-                            .map(|e| S {
-                                span: e.span.clone(),
-                                style: e.style.clone(),
-                                inner: Statement::Expr(e.clone()),
-                            })
-                            .collect()
-                    },
-                    end: {
-                        if trailing_dot.is_none() && exprs.len() >= 1 {
-                            Some(Box::new(exprs.last().unwrap().clone()))
-                        } else {
-                            None
-                        }
-                    },
-                }),
+            .map(|(((s, mut body), last), e)| {
+                body.extend(last);
+                let span = body.iter().fold(s.clone() | s.clone(), |acc, i| acc | i.clone());
+                let style = body.iter().fold(s.clone() & s.clone(), |acc, i| acc & i.clone());
+
+                let end = match body.pop() {
+                    Some(S { inner: Statement::Expr(e), .. }) => Some(Box::new(e)),
+                    other => {
+                        body.extend(other);
+                        None
+                    }
+                };
+
+                S {
+                    span: span | e.clone(),
+                    style: style & e,
+                    inner: Expr::Block(Block { body, end }),
+                }
             })
             .labelled("block");
 
         let call = call()
             .then(ident())
             .then(using())
-            .then(delimited(expr.clone(), Sigil::Comma))
+            .then(listed(expr.clone()))
             .map(|(((c, func), u), args)| S {
                 span: args
                     .iter()
@@ -360,6 +550,24 @@ pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
                 })
                 .labelled("assignment");
 
+        // `set apples in ledger to 5` (or `set 2 in list to 5`).
+        let update = set()
+            .then(expr.clone())
+            .then(inside())
+            .then(ident())
+            .then(to())
+            .then(expr.clone())
+            .map(|(((((set, key), inside), target), to), value)| S {
+                span: set.clone() | value.clone(),
+                style: set & key.clone() & inside & target.clone() & to & value.clone(),
+                inner: Expr::Update(ast::Update {
+                    key: Box::new(key),
+                    target,
+                    to: Box::new(value),
+                }),
+            })
+            .labelled("update");
+
         let conditional = is()
             .then(expr.clone())
             .then(question())
@@ -390,29 +598,222 @@ pub fn expr() -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
             })
             .labelled("conditional");
 
+        // `when x is 0 then “none”, is n where n < 0 then “negative”, otherwise “some”`
+        let article = word("a").or(word("an")).or_not();
+        let pattern = filter(|t: &Tok| matches!(t.inner, Token::Num(_) | Token::StringConst(_)))
+            .map(|t: Tok| {
+                t.map(|tok| match tok {
+                    Token::Num(n) => Pattern::Num(n),
+                    Token::StringConst(s) => Pattern::String(s),
+                    _ => unreachable!(),
+                })
+            })
+            .or(article
+                .clone()
+                .then(word("empty"))
+                .then(word("list"))
+                .map(|((a, empty), list)| {
+                    let start = a.unwrap_or_else(|| empty.clone());
+                    S {
+                        span: start.clone() | list.clone(),
+                        style: start & empty & list,
+                        inner: Pattern::EmptyList,
+                    }
+                }))
+            .or(article
+                .then(word("list"))
+                .then(word("starting"))
+                .then(word("with"))
+                .then(ident())
+                .then(word("followed").then(word("by")).then(ident()).or_not())
+                .map(|(((((a, list), starting), with), head), rest)| {
+                    let start = a.unwrap_or_else(|| list.clone());
+                    let mut span = start.clone() | head.clone();
+                    let mut style = start & list & starting & with & head.clone();
+                    let rest = rest.map(|((followed, by), rest)| {
+                        span = span.clone() | rest.clone();
+                        style = style.clone() & followed & by & rest.clone();
+                        rest
+                    });
+
+                    S {
+                        span,
+                        style,
+                        inner: Pattern::ListStartingWith { head, rest },
+                    }
+                }))
+            .or(word("anything").map(|t| t.map(|_| Pattern::Anything)))
+            .or(ident().map(|i| S {
+                span: i.span.clone(),
+                style: i.style.clone(),
+                inner: Pattern::Bind(i),
+            }))
+            .labelled("pattern");
+
+        let arm = is()
+            .then(pattern)
+            .then(where_kw().padding_for(expr.clone()).or_not())
+            .then(word("then"))
+            .then(expr.clone())
+            .map(|((((is, pattern), guard), then), body)| S {
+                span: is.clone() | body.clone(),
+                style: guard
+                    .iter()
+                    .fold(is & pattern.clone(), |acc, g| acc & g.clone())
+                    & then
+                    & body.clone(),
+                inner: Arm {
+                    pattern,
+                    guard,
+                    body,
+                },
+            })
+            .labelled("arm");
+
+        let when = when()
+            .then(expr.clone())
+            .then(arm.clone().chain(comma().or_not().padding_for(arm).repeated()))
+            .then(comma().or_not().padding_for(otherwise()).then(expr.clone()).or_not())
+            .map(|(((when, subject), arms), otherwise)| {
+                let mut span = arms.iter().fold(when.clone() | subject.clone(), |acc, a| acc | a.clone());
+                let mut style = arms.iter().fold(when & subject.clone(), |acc, a| acc & a.clone());
+
+                let otherwise = otherwise.map(|(o, e)| {
+                    span = span.clone() | e.clone();
+                    style = style.clone() & o & e.clone();
+                    Box::new(e)
+                });
+
+                S {
+                    span,
+                    style,
+                    inner: Expr::When(When {
+                        subject: Box::new(subject),
+                        arms,
+                        otherwise,
+                    }),
+                }
+            })
+            .labelled("when");
+
+        // `a procedure taking x and y that does (...)`
+        let lambda = word("a")
+            .or(word("an"))
+            .or_not()
+            .then(procedure())
+            .then(word("taking").padding_for(listed(ident())).or_not())
+            .then(word("that").or_not())
+            .then(does())
+            .then(expr.clone())
+            .map(|(((((a, p), args), _that), d), body)| {
+                let start = a.unwrap_or_else(|| p.clone());
+                let args = args.unwrap_or_default();
+                S {
+                    span: start.clone() | body.clone(),
+                    style: args.iter().fold(start & p, |acc, a| acc & a.clone()) & d & body.clone(),
+                    inner: Expr::Lambda(ast::Lambda {
+                        args,
+                        body: Box::new(body),
+                    }),
+                }
+            })
+            .labelled("anonymous procedure");
+
         conditional
+            .or(when)
             .or(assign)
+            .or(update)
             .or(call)
             .or(block)
             .or(get)
             .or(emit)
+            .or(lambda)
+            .or(logic)
             .or(compare)
     })
 }
 
-pub fn statement() -> impl Parser<Tok, S<Statement>, Error = Simple<Tok, Span>> {
-    let expr_statement = expr().then(dot()).map(|(exp, punc)| S {
-        inner: Statement::Expr(exp.clone()),
-        span: exp.clone() | punc.clone(),
-        style: exp.clone() & punc.clone(),
+/// `emit <expr>!`.
+fn print(
+    expr: impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>>,
+) -> impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>> {
+    emit()
+        .then(expr)
+        .then(exclamation())
+        .map(|((emit, expr), exclam)| S {
+            span: emit.clone() | expr.clone() | exclam.clone(),
+            style: emit.clone() & expr.clone() & exclam.clone(),
+            inner: Expr::Print(Box::new(expr)),
+        })
+        .labelled("print")
+}
+
+/// A statement that's just an `emit`; its `!` ends it, so `emit x! emit y!`
+/// is two statements.
+///
+/// It can still be followed by a `.`, `;` or line break, but not by another
+/// `!` or `?`: `emit x!!` and `emit x!?` are errors rather than two ways of
+/// writing the same thing.
+fn printed(
+    expr: impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>>,
+) -> impl Clone + Parser<Tok, S<Statement>, Error = Simple<Tok, Span>> {
+    print(expr)
+        .then(dot().or(semicolon()).or(newline()).or_not())
+        .map(|(e, punc)| {
+            let (span, style) = match punc {
+                Some(punc) => (e.span.clone() | punc.clone(), e.style.clone() & punc),
+                None => (e.span.clone(), e.style.clone()),
+            };
+
+            S {
+                span,
+                style,
+                inner: Statement::Expr(e),
+            }
+        })
+}
+
+/// `give back <expr>`, `stop`, and `skip ahead`.
+fn control(
+    expr: impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>>,
+) -> impl Clone + Parser<Tok, S<Statement>, Error = Simple<Tok, Span>> {
+    let give_back = give()
+        .then(word("back"))
+        .then(expr)
+        .map(|((give, back), value)| S {
+            span: give.clone() | value.clone(),
+            style: give & back & value.clone(),
+            inner: Statement::Return(ast::Return {
+                value: Box::new(value),
+            }),
+        });
+
+    let stop = stop().map(|t| S {
+        span: t.span.clone(),
+        style: t.style.clone(),
+        inner: Statement::Break,
+    });
+
+    let skip_ahead = skip_kw().then(word("ahead")).map(|(skip, ahead)| S {
+        span: skip.clone() | ahead.clone(),
+        style: skip & ahead,
+        inner: Statement::Continue,
     });
 
+    give_back.or(stop).or(skip_ahead).labelled("control flow")
+}
+
+/// `while`, `keep doing ... until` and `run ... for` loops; like [`control`]
+/// these can be in blocks as well as at the top level.
+fn loops(
+    expr: impl Clone + Parser<Tok, S<Expr>, Error = Simple<Tok, Span>>,
+) -> impl Clone + Parser<Tok, S<Statement>, Error = Simple<Tok, Span>> {
     let for_loop = run()
-        .then(expr())
+        .then(expr.clone())
         .then(for_loop())
         .then(ident())
         .then(inside())
-        .then(expr())
+        .then(expr.clone())
         .map(|(((((r, expr), f), binding), i), iteree)| S {
             span: r.clone() | expr.clone() | f.clone() | binding.clone() | i.clone() | iteree.clone(),
             style:  r.clone() & expr.clone() & f.clone() & binding.clone() & i.clone() & iteree.clone(),
@@ -424,9 +825,9 @@ pub fn statement() -> impl Parser<Tok, S<Statement>, Error = Simple<Tok, Span>>
         });
 
     let while_loop = while_loop()
-        .then(expr())
+        .then(expr.clone())
         .then(run())
-        .then(expr())
+        .then(expr.clone())
         .map(|(((start, cond), run), body)| {
             S{
                 span: start.clone() | body.clone(),
@@ -438,10 +839,55 @@ pub fn statement() -> impl Parser<Tok, S<Statement>, Error = Simple<Tok, Span>>
             }
         });
 
+    let until_loop = keep()
+        .then(word("doing").or(word("running")).or_not())
+        .then(expr.clone())
+        .then(until())
+        .then(expr.clone())
+        .map(|((((keep, _), body), until), cond)| S {
+            span: keep.clone() | cond.clone(),
+            style: keep.clone() & body.clone() & until.clone() & cond.clone(),
+            inner: Statement::Until(ast::Until {
+                body: Box::new(body),
+                cond: Box::new(cond),
+            }),
+        });
+
+    while_loop.or(until_loop).or(for_loop).labelled("loop")
+}
+
+/// Loops and procedures don't need a terminator, since they end with an
+/// expression, but they can have one.
+fn optionally_terminated(
+    statement: impl Clone + Parser<Tok, S<Statement>, Error = Simple<Tok, Span>>,
+) -> impl Clone + Parser<Tok, S<Statement>, Error = Simple<Tok, Span>> {
+    statement.then(terminator().or_not()).map(|(s, punc)| match punc {
+        Some(punc) => S {
+            span: s.span.clone() | punc.clone(),
+            style: s.style.clone() & punc,
+            inner: s.inner,
+        },
+        None => s,
+    })
+}
+
+pub fn statement() -> impl Parser<Tok, S<Statement>, Error = Simple<Tok, Span>> {
+    let control = control(expr()).then(terminator()).map(|(s, punc)| S {
+        span: s.span.clone() | punc.clone(),
+        style: s.style.clone() & punc,
+        inner: s.inner,
+    });
+
+    let expr_statement = expr().then(terminator()).map(|(exp, punc)| S {
+        inner: Statement::Expr(exp.clone()),
+        span: exp.clone() | punc.clone(),
+        style: exp.clone() & punc.clone(),
+    });
+
     let proc = procedure()
         .then(ident())
         .then(takes())
-        .then(delimited(ident(), Sigil::Comma))
+        .then(listed(ident()))
         .then(does())
         .then(expr())
         .map(|(((((p, name), t), args), d), body)| S {
@@ -454,5 +900,562 @@ pub fn statement() -> impl Parser<Tok, S<Statement>, Error = Simple<Tok, Span>>
             }),
         });
 
-    proc.or(while_loop).or(for_loop).or(expr_statement)
+    let source = filter(|t: &Tok| matches!(t.inner, Token::StringConst(_)))
+        .map(|t: Tok| {
+            t.map(|tok| match tok {
+                Token::StringConst(s) => s,
+                _ => unreachable!(),
+            })
+        })
+        .labelled("import source");
+
+    let import_stmt = import()
+        .then(listed(ident()).then(from()).or_not())
+        .then(source)
+        .then(terminator())
+        .map(|(((i, names), source), punc)| S {
+            span: i.clone() | punc.clone(),
+            style: names
+                .iter()
+                .flat_map(|(names, f)| {
+                    names
+                        .iter()
+                        .map(|n| n.style.clone())
+                        .chain(Some(f.style.clone()))
+                })
+                .fold(i.style.clone() & source.clone(), |acc, s| acc & s)
+                & punc,
+            inner: Statement::Import(ast::Import {
+                names: names.map(|(names, _)| names),
+                source,
+            }),
+        })
+        .labelled("import");
+
+    optionally_terminated(proc)
+        .or(import_stmt)
+        .or(optionally_terminated(loops(expr())))
+        .or(control)
+        .or(printed(expr()))
+        .or(expr_statement)
+}
+
+enum Item {
+    Statement(S<Statement>),
+    Heading(S<Heading>),
+    /// Tokens that aren't a statement; see [`skipped`].
+    Skipped(Span),
+    /// A line break after a statement that doesn't need a terminator (like a
+    /// `while` loop's).
+    LineBreak,
+}
+
+/// Nests the statements that follow each heading into a [`Section`], closing
+/// sections when a heading of the same (or a higher) level comes along.
+fn sections(items: Vec<Item>) -> Vec<S<Statement>> {
+    // The bottom of the stack is the top level of the program; it has no
+    // heading.
+    let mut stack: Vec<(Option<S<Heading>>, Vec<S<Statement>>)> = vec![(None, vec![])];
+
+    fn close(stack: &mut Vec<(Option<S<Heading>>, Vec<S<Statement>>)>) {
+        let (heading, body) = stack.pop().unwrap();
+        let heading = heading.unwrap();
+
+        let section = S {
+            span: body.iter().fold(heading.span.clone(), |acc, s| acc | s.span.clone()),
+            style: heading.style.clone(),
+            inner: Statement::Section(Section {
+                level: heading.level,
+                title: heading.map(|h| h.title),
+                body,
+            }),
+        };
+        stack.last_mut().unwrap().1.push(section);
+    }
+
+    for item in items {
+        match item {
+            Item::Statement(s) => stack.last_mut().unwrap().1.push(s),
+            Item::Skipped(_) | Item::LineBreak => {}
+            Item::Heading(h) => {
+                while matches!(stack.last(), Some((Some(open), _)) if open.level >= h.level) {
+                    close(&mut stack);
+                }
+
+                stack.push((Some(h), vec![]));
+            }
+        }
+    }
+
+    while stack.len() > 1 {
+        close(&mut stack);
+    }
+
+    stack.pop().unwrap().1
+}
+
+/// A whole program: statements, grouped into [`Section`]s by the headings
+/// between them.
+///
+/// This stops at the first syntax error; [`parse`] doesn't.
+pub fn program() -> impl Parser<Tok, Vec<S<Statement>>, Error = Simple<Tok, Span>> {
+    statement()
+        .map(Item::Statement)
+        .or(heading().map(Item::Heading))
+        .repeated()
+        .map(sections)
+}
+
+/// What's skipped when a statement doesn't parse: everything up to the next
+/// `.`, `;` or line break that ends a statement, the next paragraph break or
+/// heading, or the `)` that closes the block the statement is in.
+/// Parentheses opened along the way are skipped as a whole, so a `.` inside a
+/// block doesn't end the statement.
+///
+/// `breaks` has the start of every token that comes after a paragraph break.
+fn skipped(breaks: Rc<HashSet<usize>>) -> impl Parser<Tok, Span, Error = Simple<Tok, Span>> {
+    let not_heading = |t: &Tok| !matches!(t.inner, Token::Heading(_));
+    let plain = |t: &Tok| {
+        !matches!(
+            t.inner,
+            Token::Heading(_) | Token::Sigil(Sigil::StartBlock | Sigil::EndBlock)
+        )
+    };
+
+    let group = recursive::<Tok, Span, _, _, Simple<Tok, Span>>(|group| {
+        start_block()
+            .then(group.or(filter(plain).map(|t: Tok| t.span)).repeated())
+            .then(end_block())
+            .map(|((start, _), end)| start | end)
+    });
+
+    let starts_paragraph = move |t: &Tok| breaks.contains(&t.span.inner.start);
+    let inside = {
+        let starts_paragraph = starts_paragraph.clone();
+        move |t: &Tok| {
+            plain(t)
+                && !starts_paragraph(t)
+                && !matches!(t.inner, Token::Sigil(Sigil::Dot | Sigil::Semicolon | Sigil::Newline))
+        }
+    };
+    let end = move |t: &Tok| {
+        !starts_paragraph(t)
+            && matches!(t.inner, Token::Sigil(Sigil::Dot | Sigil::Semicolon | Sigil::Newline))
+    };
+
+    // The first token is always skipped (even if it's a stray `)` or `.`) so
+    // that this always gets somewhere.
+    group
+        .clone()
+        .or(filter(not_heading).map(|t: Tok| t.span))
+        .then(group.or(filter(inside).map(|t: Tok| t.span)).repeated())
+        .then(filter(end).or_not())
+        .map(|((first, rest), end)| {
+            let span = rest.into_iter().fold(first, |acc, s| acc | s);
+            match end {
+                Some(end) => span | end.span,
+                None => span,
+            }
+        })
+}
+
+/// Parses a whole program like [`program`] but doesn't stop at the first
+/// syntax error: statements that don't parse are skipped (see [`skipped`])
+/// and every one of them is reported.
+///
+/// `name` is what the [`Module`] is called; `source` is the text the tokens
+/// were lexed from (blank lines in it are paragraph breaks).
+pub fn parse(
+    name: impl Into<String>,
+    tokens: Vec<Tok>,
+    source: &str,
+) -> (Module, Vec<SyntaxError>) {
+    parse_paragraphs(name.into(), tokens, source, false)
+}
+
+/// [`parse`] for tokens lexed from a document. Every paragraph of a
+/// document ends with a single line break in its source, so each of them is
+/// a paragraph break, not just the blank ones.
+pub fn parse_docx(
+    name: impl Into<String>,
+    tokens: Vec<Tok>,
+    source: &str,
+) -> (Module, Vec<SyntaxError>) {
+    parse_paragraphs(name.into(), tokens, source, true)
+}
+
+fn parse_paragraphs(
+    name: String,
+    tokens: Vec<Tok>,
+    source: &str,
+    every_line: bool,
+) -> (Module, Vec<SyntaxError>) {
+    let tokens = line_breaks(tokens, source);
+    let breaks = paragraph_breaks(&tokens, source, every_line);
+    let spanned = tokens
+        .iter()
+        .map(|t| (t.clone(), t.span.clone()))
+        .collect::<Vec<_>>();
+
+    let items = statement()
+        .map(Item::Statement)
+        .or(heading().map(Item::Heading))
+        .or(newline().map(|_| Item::LineBreak))
+        .or(skipped(Rc::new(breaks)).map(Item::Skipped))
+        .repeated()
+        .parse(spanned);
+
+    // Anything can be skipped, so this shouldn't fail; if it does, what
+    // chumsky said is all there is to go on.
+    let items = match items {
+        Ok(items) => items,
+        Err(errs) => {
+            let everything = match (tokens.first(), tokens.last()) {
+                (Some(first), Some(last)) => first.span.clone() | last.span.clone(),
+                _ => Span::from(0..0),
+            };
+            let errors = errs
+                .iter()
+                .map(|e| SyntaxError::new(&tokens, everything.clone(), Some(e)))
+                .collect();
+            return (Module::new(name, vec![]), errors);
+        }
+    };
+
+    // The error that made each statement get skipped comes from parsing just
+    // the skipped tokens again; that way it's about this statement and not
+    // one further along.
+    let errors = items
+        .iter()
+        .filter_map(|item| match item {
+            Item::Skipped(span) => Some(span),
+            _ => None,
+        })
+        .map(|span| {
+            let these = tokens
+                .iter()
+                .filter(|t| span.contains(&t.span))
+                .cloned()
+                .collect::<Vec<_>>();
+            let spanned = these
+                .iter()
+                .map(|t| (t.clone(), t.span.clone()))
+                .collect::<Vec<_>>();
+
+            // The whole statement might parse on its own (if what tripped it
+            // up came after it); then there's no better error to give.
+            let error = statement()
+                .parse(spanned)
+                .err()
+                .and_then(|errs| errs.into_iter().max_by_key(|e| e.span().inner.start));
+
+            SyntaxError::new(&these, span.clone(), error.as_ref())
+        })
+        .collect();
+
+    (Module::new(name, sections(items)), errors)
+}
+
+/// Puts a [`Sigil::Newline`] at every line break that ends a statement, so
+/// that statements can end with a line break instead of a `.`.
+///
+/// A line break doesn't end a statement if it's inside brackets (or a string),
+/// if the line ends partway through something (after a keyword, an operator
+/// or a `,`, for example) or if the next line carries on from it (starting
+/// with `and`, `otherwise`, an operator, a `.` and so on). The end of the
+/// source counts as a line break.
+fn line_breaks(tokens: Vec<Tok>, source: &str) -> Vec<Tok> {
+    // Spans count utf16 code units.
+    let mut newlines = vec![];
+    let mut offset = 0;
+    for c in source.chars() {
+        if c == '\n' {
+            newlines.push(offset);
+        }
+        offset += c.len_utf16();
+    }
+
+    // Only keywords count: plain words like `a` or `back` can be names too, and
+    // a line that ends with a name is finished.
+    fn ends_line_early(t: &Token) -> bool {
+        match t {
+            // `stop` is a whole statement.
+            Token::Keyword(Keyword::Stop) => false,
+            Token::Keyword(_) | Token::Operator(_) | Token::Heading(_) => true,
+            // `emit x!` still needs ending; `x.` doesn't.
+            Token::Sigil(s) => {
+                !matches!(s, Sigil::EndBlock | Sigil::EndList | Sigil::Exclamation)
+            }
+            _ => false,
+        }
+    }
+
+    fn carries_on(t: &Token) -> bool {
+        use Keyword::*;
+
+        match t {
+            Token::Operator(_) => true,
+            Token::Sigil(s) => !matches!(s, Sigil::StartBlock | Sigil::StartList),
+            Token::Keyword(kw) => matches!(
+                kw,
+                To | Is | Using | Also | And | Until | Run | For | In | Takes | Does | Else
+                    | From | Or | Where
+            ),
+            _ => false,
+        }
+    }
+
+    let mut out = Vec::with_capacity(tokens.len());
+    let mut depth = 0usize;
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(t) = tokens.next() {
+        match &t.inner {
+            Token::Sigil(Sigil::StartBlock | Sigil::StartList)
+            | Token::Interpolation(Interpolation::Start | Interpolation::OpenExpr) => depth += 1,
+            Token::Sigil(Sigil::EndBlock | Sigil::EndList)
+            | Token::Interpolation(Interpolation::End | Interpolation::CloseExpr) => {
+                depth = depth.saturating_sub(1)
+            }
+            _ => {}
+        }
+
+        let end = t.span.inner.end;
+        let at = match tokens.peek() {
+            _ if depth != 0 || ends_line_early(&t.inner) => None,
+            None => Some(end),
+            Some(next) if carries_on(&next.inner) => None,
+            Some(next) => {
+                let next_line = newlines.partition_point(|n| *n < end);
+                newlines.get(next_line).filter(|n| **n < next.span.inner.start).copied()
+            }
+        };
+
+        out.push(t);
+        if let Some(at) = at {
+            out.push(S {
+                inner: Token::Sigil(Sigil::Newline),
+                span: Span::from(at..at),
+                style: Default::default(),
+            });
+        }
+    }
+
+    out
+}
+
+/// The start of every token that's the first after a blank line (or after
+/// any line break, if `every_line`).
+fn paragraph_breaks(tokens: &[Tok], source: &str, every_line: bool) -> HashSet<usize> {
+    // Spans count utf16 code units; these are sorted.
+    let mut blank = vec![];
+    let mut offset = 0;
+    let mut line_is_blank = true;
+    for c in source.chars() {
+        if c == '\n' {
+            if line_is_blank || every_line {
+                blank.push(offset);
+            }
+            line_is_blank = true;
+        } else if !c.is_whitespace() {
+            line_is_blank = false;
+        }
+        offset += c.len_utf16();
+    }
+
+    tokens
+        .windows(2)
+        .filter(|pair| {
+            let (before, after) = (pair[0].span.inner.end, pair[1].span.inner.start);
+            let next = blank.partition_point(|b| *b < before);
+            blank.get(next).map(|b| *b < after).unwrap_or(false)
+        })
+        .map(|pair| pair[1].span.inner.start)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `source`; it has to parse without errors.
+    pub(crate) fn parse_ok(source: &str) -> Module {
+        let (tokens, source) = lex::lex_cado(source.to_string());
+        let (module, errors) = parse("test.cado", tokens.unwrap(), &source);
+        assert!(errors.is_empty(), "{:?}", errors);
+        module
+    }
+
+    /// The expression in a module that's just one expression statement.
+    fn only_expr(module: &Module) -> &Expr {
+        match &module.body[..] {
+            [S { inner: Statement::Expr(e), .. }] => &e.inner,
+            other => panic!("expected one expression, got {:?}", other),
+        }
+    }
+
+    fn args(source: &str) -> Vec<String> {
+        match only_expr(&parse_ok(source)) {
+            Expr::Call(Call { args, .. }) => args.iter().map(|a| a.inner.to_string()).collect(),
+            other => panic!("expected a call, got {}", other),
+        }
+    }
+
+    #[test]
+    fn connectives_take_their_and_before_argument_lists() {
+        assert_eq!(args("do f using both a and b, c."), ["(a && b)", "c"]);
+        assert_eq!(args("do f using both a and b and c."), ["(a && b)", "c"]);
+        assert_eq!(args("do f using c and both a and b."), ["c", "(a && b)"]);
+        assert_eq!(args("do f using c, both a and b, and d."), ["c", "(a && b)", "d"]);
+        assert_eq!(args("do f using both a and b."), ["(a && b)"]);
+    }
+
+    #[test]
+    fn either_in_argument_lists() {
+        assert_eq!(args("do f using either a or b, c."), ["(a || b)", "c"]);
+        assert_eq!(args("do f using either a or b and c."), ["(a || b)", "c"]);
+        assert_eq!(
+            args("do f using c and either a or b but not both."),
+            ["c", "(a ^^ b)"],
+        );
+        assert_eq!(args("do f using both either a or b and c."), ["((a || b) && c)"]);
+    }
+
+    #[test]
+    fn connectives_in_list_literals() {
+        match only_expr(&parse_ok("[both a and b, c, and either d or e]")) {
+            Expr::List(List(items)) => {
+                let items = items.iter().map(|i| i.inner.to_string()).collect::<Vec<_>>();
+                assert_eq!(items, ["(a && b)", "c", "(d || e)"]);
+            }
+            other => panic!("expected a list, got {}", other),
+        }
+    }
+
+    /// Parses `source`, returning what it could and the errors as messages.
+    fn parse_err(source: &str) -> (Module, Vec<String>) {
+        let (tokens, source) = lex::lex_cado(source.to_string());
+        let (module, errors) = parse("test.cado", tokens.unwrap(), &source);
+        (module, errors.iter().map(|e| e.to_string()).collect())
+    }
+
+    #[test]
+    fn every_syntax_error_is_reported() {
+        let (module, errors) = parse_err("set x 3.\nset z to 1.\nset y 4.\n");
+        assert_eq!(
+            errors,
+            [
+                "expected the word ‘to’ after ‘set x’",
+                "expected the word ‘to’ after ‘set y’",
+            ],
+        );
+        // The statement between them still parses.
+        assert_eq!(module.body.len(), 1, "{:#?}", module.body);
+    }
+
+    #[test]
+    fn skipping_stops_at_the_next_paragraph_of_a_document() {
+        use docx_rs::{Docx, Paragraph, Run};
+
+        let doc = ["set x to", "set y to 2."].iter().fold(Docx::new(), |doc, line| {
+            doc.add_paragraph(Paragraph::new().add_run(Run::new().add_text(*line)))
+        });
+        let (tokens, source) = lex::lex_docx(&doc);
+        let tokens = tokens.unwrap();
+
+        let (module, errors) = parse_docx("test.docx", tokens.clone(), &source);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(module.body.len(), 1, "{:#?}", module.body);
+
+        // In plain text the paragraphs are just lines, so both are skipped.
+        let (module, errors) = parse("test.cado", tokens, &source);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(module.body.is_empty(), "{:#?}", module.body);
+    }
+
+    #[test]
+    fn every_terminator_ends_a_statement() {
+        for punc in [".", ";", "!", "?"] {
+            let source = format!("set x to 1{} set y to 2{}", punc, punc);
+            let module = parse_ok(&source);
+            assert_eq!(module.body.len(), 2, "{}", source);
+            // The terminator is part of the statement.
+            assert_eq!(module.body[0].span.inner, 0..11, "{}", source);
+            assert_eq!(module.body[1].span.inner, 12..23, "{}", source);
+        }
+
+        let module = parse_ok("set x to 1\nset y to 2");
+        assert_eq!(module.body.len(), 2);
+        assert_eq!(module.body[0].span.inner, 0..10);
+    }
+
+    #[test]
+    fn emits_are_ended_by_their_own_exclamation_mark() {
+        for source in ["emit x! emit y!", "emit x!. emit y!;", "emit x!\nemit y!\n"] {
+            let module = parse_ok(source);
+            assert_eq!(module.body.len(), 2, "{}", source);
+            assert!(module.body.iter().all(|s| matches!(
+                &s.inner,
+                Statement::Expr(S { inner: Expr::Print(_), .. })
+            )));
+        }
+        assert_eq!(parse_ok("emit x! emit y!").body[1].span.inner, 8..15);
+
+        // In blocks too; the last one is still the block's value.
+        match &parse_ok("(emit x! emit y!).").body[..] {
+            [S { inner: Statement::Expr(e), .. }] => match &e.inner {
+                Expr::Block(b) => {
+                    assert_eq!(b.body.len(), 1);
+                    assert!(matches!(b.end.as_deref(), Some(S { inner: Expr::Print(_), .. })));
+                }
+                other => panic!("expected a block, got {}", other),
+            },
+            other => panic!("expected one statement, got {:?}", other),
+        }
+
+        // A second `!` or `?` doesn't end them again.
+        for source in ["emit x!! emit y!", "emit x!? emit y!"] {
+            let (_, errors) = parse_err(source);
+            assert!(!errors.is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn question_marks_after_conditionals_end_the_statement() {
+        let module = parse_ok("set z to is x = 1 ? 0? set w to 1?");
+        assert_eq!(module.body.len(), 2, "{:#?}", module.body);
+        match &module.body[0].inner {
+            Statement::Expr(S { inner: Expr::Assign(a), .. }) => match &a.to.inner {
+                Expr::If(i) => {
+                    assert!(matches!(i.then.inner, Expr::Num(n) if n == 0.0));
+                    assert!(i.otherwise.is_none());
+                }
+                other => panic!("expected a conditional, got {}", other),
+            },
+            other => panic!("expected an assignment, got {}", other),
+        }
+    }
+
+    #[test]
+    fn lines_can_end_with_names_that_are_also_words() {
+        let module = parse_ok("set x to a\nset y to back\n");
+        assert_eq!(module.body.len(), 2, "{:#?}", module.body);
+    }
+
+    #[test]
+    fn control_words_arent_names() {
+        let (_, errors) = parse_err("set stop to 1.\n");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+
+        // A block that ends with `stop` stops; it doesn't give back a `stop`.
+        match &parse_ok("(set x to 1. stop).").body[..] {
+            [S { inner: Statement::Expr(e), .. }] => match &e.inner {
+                Expr::Block(b) => {
+                    assert!(matches!(b.body.last().map(|s| &s.inner), Some(Statement::Break)));
+                    assert!(b.end.is_none());
+                }
+                other => panic!("expected a block, got {}", other),
+            },
+            other => panic!("expected one statement, got {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,683 @@
+//! Prints programs as `.cado` source that parses back into the same program
+//! (up to spans and styles), or as a `.docx` document that keeps the styles
+//! too.
+//!
+//! The [`Display`](std::fmt::Display) impls in [`ast`](crate::ast) are for
+//! debugging; this is what `avocadocx translate` and formatters should use.
+//!
+//! Parentheses are never added: the parser keeps them around as
+//! [`Block`](crate::ast::Block)s, so programs that came from the parser
+//! already have them wherever they're needed.
+
+use std::convert::TryFrom;
+
+use abogado_lex::{spanned::S, Keyword, Op, Sigil, Style};
+use docx_rs::{Docx, Paragraph, Run};
+
+use crate::ast::*;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrettyOptions {
+    /// Spaces per level of indentation.
+    pub indent: usize,
+    /// Blocks and `when`s that would go past this column are split across
+    /// lines.
+    pub width: usize,
+}
+
+impl Default for PrettyOptions {
+    fn default() -> Self {
+        PrettyOptions {
+            indent: 4,
+            width: 80,
+        }
+    }
+}
+
+pub fn module(module: &Module, options: &PrettyOptions) -> String {
+    Printer::new(options, false).program(&module.body).text()
+}
+
+pub fn statement(statement: &S<Statement>, options: &PrettyOptions) -> String {
+    Printer::new(options, false).statement(statement, 0).text()
+}
+
+pub fn expr(expr: &S<Expr>, options: &PrettyOptions) -> String {
+    Printer::new(options, false).expr(expr, 0).text()
+}
+
+/// Like [`module`] but as a document: every piece of source is written in
+/// the [`Style`] of the node it's from, so that lexing the document gives back
+/// the same styles.
+///
+/// Each line is a paragraph, with the paragraph style and alignment of the
+/// first thing on it. Sections with a heading style are headings (without
+/// the `§`s).
+///
+/// Run properties are copied over as they are; formatting that the reader
+/// doesn't keep (strikethrough, for one) isn't there to copy.
+pub fn docx(module: &Module, options: &PrettyOptions) -> Docx {
+    let Doc(pieces) = Printer::new(options, true).program(&module.body);
+
+    let mut lines = vec![vec![]];
+    for (text, style) in pieces {
+        let style = style.unwrap_or_default();
+        for (i, line) in text.split('\n').enumerate() {
+            if i != 0 {
+                lines.push(vec![]);
+            }
+            if !line.is_empty() {
+                lines.last_mut().unwrap().push((line.to_string(), style.clone()));
+            }
+        }
+    }
+    // The last piece is always a `\n`; there's no paragraph after it.
+    lines.pop();
+
+    lines.into_iter().fold(Docx::new(), |doc, line| {
+        let mut paragraph = Paragraph::new();
+        if let Some((_, first)) = line.first() {
+            paragraph.property.style = first.paragraph_style.clone();
+            paragraph.property.alignment = first.alignment.clone();
+        }
+
+        for (text, style) in line {
+            let mut run = Run::new().add_text(text);
+            run.run_property = style.prop;
+            paragraph = paragraph.add_run(run);
+        }
+
+        doc.add_paragraph(paragraph)
+    })
+}
+
+/// Source text, in pieces that know which node (and so which style) they came
+/// from.
+///
+/// Pieces start out without a style (i.e. keywords); they take the style of
+/// the innermost node that they're a part of.
+#[derive(Debug, Clone, Default)]
+struct Doc(Vec<(String, Option<Style>)>);
+
+impl Doc {
+    fn push(&mut self, part: impl Into<Doc>) {
+        self.0.extend(part.into().0);
+    }
+
+    fn styled(mut self, style: &Style) -> Doc {
+        for (_, s) in self.0.iter_mut().filter(|(_, s)| s.is_none()) {
+            *s = Some(style.clone());
+        }
+        self
+    }
+
+    fn text(&self) -> String {
+        self.0.iter().map(|(t, _)| t.as_str()).collect()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.iter().all(|(t, _)| t.is_empty())
+    }
+}
+
+impl From<&str> for Doc {
+    fn from(text: &str) -> Doc {
+        Doc(vec![(text.to_string(), None)])
+    }
+}
+
+impl From<String> for Doc {
+    fn from(text: String) -> Doc {
+        Doc(vec![(text, None)])
+    }
+}
+
+macro_rules! doc {
+    ($($part:expr),* $(,)?) => {{
+        let mut doc = Doc::default();
+        $(doc.push($part);)*
+        doc
+    }};
+}
+
+fn join(items: Vec<Doc>, sep: &str) -> Doc {
+    let mut out = Doc::default();
+    for (i, item) in items.into_iter().enumerate() {
+        if i != 0 {
+            out.push(sep);
+        }
+        out.push(item);
+    }
+    out
+}
+
+/// `a`, `a and b`, `a, b, and c`
+fn listed(mut items: Vec<Doc>) -> Doc {
+    match items.len() {
+        0 | 1 => join(items, ""),
+        2 => join(items, " and "),
+        _ => {
+            let last = items.pop().unwrap();
+            doc![join(items, ", "), ", and ", last]
+        }
+    }
+}
+
+/// Whether `key` can be written as a bare name (instead of as a string).
+fn is_bare(key: &str) -> bool {
+    const RESERVED: &str = " \n\r\t\"“”{}§";
+
+    !key.is_empty()
+        && !key.starts_with(|c: char| c.is_numeric() || c == '.')
+        && !key.contains(|c: char| {
+            RESERVED.contains(c) || Op::OPERATORS.contains(c) || Sigil::SIGILS.contains(c)
+        })
+        && Keyword::try_from(key).is_err()
+}
+
+/// `{` and `}` start and end interpolations; doubling them makes them text.
+fn escape(text: &str) -> String {
+    text.replace('{', "{{").replace('}', "}}")
+}
+
+/// Curly quotes nest, so they can hold anything except curly quotes that
+/// don't; straight quotes are for those.
+fn quote(body: Doc, text: &str) -> Doc {
+    let mut depth = 0i32;
+    let balanced = text
+        .chars()
+        .filter_map(|c| match c {
+            '“' => Some(1),
+            '”' => Some(-1),
+            _ => None,
+        })
+        .all(|d| {
+            depth += d;
+            depth >= 0
+        })
+        && depth == 0;
+
+    if balanced {
+        doc!["“", body, "”"]
+    } else {
+        doc!["\"", body, "\""]
+    }
+}
+
+fn string(s: &str) -> Doc {
+    quote(escape(s).into(), s)
+}
+
+fn name(name: &S<Ident>) -> Doc {
+    Doc::from(name.inner.as_str()).styled(&name.style)
+}
+
+struct Printer<'o> {
+    options: &'o PrettyOptions,
+    /// Whether sections that have a heading style can be written as just
+    /// their title.
+    headings: bool,
+}
+
+impl<'o> Printer<'o> {
+    fn new(options: &'o PrettyOptions, headings: bool) -> Self {
+        Printer { options, headings }
+    }
+
+    fn pad(&self, level: usize) -> String {
+        " ".repeat(self.options.indent * level)
+    }
+
+    fn fits(&self, level: usize, flat: &Doc) -> bool {
+        let flat = flat.text();
+        let column = self.options.indent * level;
+        !flat.contains('\n') && column + flat.chars().count() <= self.options.width
+    }
+
+    fn names(&self, names: &[S<Ident>]) -> Doc {
+        listed(names.iter().map(name).collect())
+    }
+
+    fn program(&self, program: &[S<Statement>]) -> Doc {
+        let mut out = Doc::default();
+        for s in program {
+            out.push(self.statement(s, 0));
+            out.push("\n");
+        }
+
+        out
+    }
+
+    fn statement(&self, s: &S<Statement>, level: usize) -> Doc {
+        let doc = match &s.inner {
+            Statement::Expr(e) => doc![self.expr(e, level), "."],
+            Statement::While(While { cond, body }) => doc![
+                "while ",
+                self.expr(cond, level),
+                " run ",
+                self.expr(body, level),
+            ],
+            Statement::Until(Until { body, cond }) => doc![
+                "keep doing ",
+                self.expr(body, level),
+                " until ",
+                self.expr(cond, level),
+            ],
+            Statement::For(For { name: n, list, body }) => doc![
+                "run ",
+                self.expr(body, level),
+                " for ",
+                name(n),
+                " in ",
+                self.expr(list, level),
+            ],
+            Statement::Procedure(Procedure { name: n, args, body }) => {
+                let args = self.names(args);
+                let args = if args.is_empty() { args } else { doc![args, " "] };
+                doc![
+                    "procedure ",
+                    name(n),
+                    " takes ",
+                    args,
+                    "does ",
+                    self.expr(body, level),
+                ]
+            }
+            Statement::Section(Section { title, level: depth, body }) => {
+                let heading = Doc::from(title.inner.as_str()).styled(&title.style);
+                let mut out = if self.headings && s.style.heading_level() == Some(*depth) {
+                    heading
+                } else {
+                    doc!["§".repeat(*depth), " ", heading]
+                };
+                // Only the heading is in the section's style; what's under it
+                // has its own.
+                out = out.styled(&s.style);
+
+                for s in body {
+                    out.push(doc!["\n", self.pad(level), self.statement(s, level)]);
+                }
+                return out;
+            }
+            Statement::Import(Import { names, source }) => {
+                let source = string(&source.inner).styled(&source.style);
+                match names {
+                    Some(names) => doc!["import ", self.names(names), " from ", source, "."],
+                    None => doc!["import ", source, "."],
+                }
+            }
+            Statement::Return(_) | Statement::Break | Statement::Continue => {
+                doc![self.control(s, level), "."]
+            }
+        };
+
+        doc.styled(&s.style)
+    }
+
+    /// Control flow without its terminator; in blocks, the `.` separates it
+    /// from what's next.
+    fn control(&self, s: &S<Statement>, level: usize) -> Doc {
+        let doc = match &s.inner {
+            Statement::Return(Return { value }) => doc!["give back ", self.expr(value, level)],
+            Statement::Break => doc!["stop"],
+            Statement::Continue => doc!["skip ahead"],
+            _ => unreachable!(),
+        };
+
+        doc.styled(&s.style)
+    }
+
+    /// A statement inside a block.
+    fn item(&self, s: &S<Statement>, level: usize) -> Doc {
+        match &s.inner {
+            Statement::Expr(e) => self.expr(e, level).styled(&s.style),
+            Statement::Return(_) | Statement::Break | Statement::Continue => self.control(s, level),
+            Statement::While(_) | Statement::Until(_) | Statement::For(_) => {
+                self.statement(s, level)
+            }
+            Statement::Procedure(_) | Statement::Import(_) | Statement::Section(_) => {
+                unreachable!("blocks only have expressions, control flow and loops")
+            }
+        }
+    }
+
+    fn expr(&self, e: &S<Expr>, level: usize) -> Doc {
+        let doc = match &e.inner {
+            Expr::If(If {
+                cond,
+                then,
+                otherwise,
+            }) => {
+                let mut out = doc!["is ", self.expr(cond, level), " ? ", self.expr(then, level)];
+                if let Some(o) = otherwise {
+                    out.push(doc![" otherwise ", self.expr(o, level)]);
+                }
+                out
+            }
+            Expr::When(w) => self.when(w, level),
+            Expr::BinOp(BinOp { lhs, op, rhs }) => doc![
+                self.expr(lhs, level),
+                " ",
+                Doc::from(op.inner.to_string()).styled(&op.style),
+                " ",
+                self.expr(rhs, level),
+            ],
+            Expr::Logic(Logic { lhs, op, rhs }) => {
+                let (lhs, rhs) = (self.expr(lhs, level), self.expr(rhs, level));
+                match op.inner {
+                    LogicOp::And => doc!["both ", lhs, " and ", rhs],
+                    LogicOp::Or => doc!["either ", lhs, " or ", rhs],
+                    LogicOp::Xor => doc!["either ", lhs, " or ", rhs, " but not both"],
+                }
+            }
+            Expr::UnOp(UnOp { op, expr }) => {
+                let symbol = match op.inner {
+                    UnaryOperator::Neg => "-",
+                    UnaryOperator::Not => "not ",
+                };
+                doc![Doc::from(symbol).styled(&op.style), self.expr(expr, level)]
+            }
+            Expr::Call(Call { name: n, args }) if args.is_empty() => doc!["do ", name(n), " using"],
+            Expr::Call(Call { name: n, args }) => {
+                let args = args.iter().map(|a| self.expr(a, level)).collect();
+                doc!["do ", name(n), " using ", listed(args)]
+            }
+            Expr::Num(n) => doc![format!("{}", n)],
+            Expr::String(s) => string(s),
+            Expr::Ident(i) => doc![i.as_str()],
+            Expr::List(List(items)) => {
+                doc!["[", listed(items.iter().map(|i| self.expr(i, level)).collect()), "]"]
+            }
+            Expr::Assign(Assign { name: n, to }) => {
+                doc!["set ", name(n), " to ", self.expr(to, level)]
+            }
+            Expr::Update(Update { key, target, to }) => doc![
+                "set ",
+                self.expr(key, level),
+                " in ",
+                name(target),
+                " to ",
+                self.expr(to, level),
+            ],
+            Expr::Ledger(entries) if entries.is_empty() => doc!["an empty ledger"],
+            Expr::Ledger(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|Entry { key, value }| {
+                        let k = if is_bare(&key.inner) {
+                            Doc::from(key.inner.as_str())
+                        } else {
+                            string(&key.inner)
+                        };
+                        doc![k.styled(&key.style), " is ", self.expr(value, level)]
+                    })
+                    .collect();
+                doc!["a ledger where ", listed(entries)]
+            }
+            Expr::Print(p) => doc!["emit ", self.expr(p, level), "!"],
+            Expr::Get(Get { index, from }) => {
+                doc!["get ", self.expr(index, level), " from ", self.expr(from, level)]
+            }
+            Expr::Block(b) => self.block(b, level),
+            Expr::Lambda(Lambda { args, body }) => {
+                let args = self.names(args);
+                let args = if args.is_empty() { args } else { doc!["taking ", args, " "] };
+                doc!["a procedure ", args, "that does ", self.expr(body, level)]
+            }
+            Expr::Interpolated(fragments) => {
+                let mut body = Doc::default();
+                let mut text = String::new();
+                for f in fragments {
+                    match f {
+                        Fragment::Text(t) => {
+                            body.push(Doc::from(escape(&t.inner)).styled(&t.style));
+                            text.push_str(&t.inner);
+                        }
+                        Fragment::Expr(e) => body.push(doc!["{", self.expr(e, level), "}"]),
+                    }
+                }
+                quote(body, &text)
+            }
+        };
+
+        doc.styled(&e.style)
+    }
+
+    fn block(&self, Block { body, end }: &Block, level: usize) -> Doc {
+        let mut items = body.iter().map(|s| self.item(s, level + 1)).collect::<Vec<_>>();
+        let trailing_dot = end.is_none() && !items.is_empty();
+        items.extend(end.iter().map(|e| self.expr(e, level + 1)));
+
+        let flat = doc!["(", join(items.clone(), ". "), if trailing_dot { "." } else { "" }, ")"];
+        if self.fits(level, &flat) {
+            return flat;
+        }
+
+        let mut out = doc!["("];
+        let last = items.len().saturating_sub(1);
+        for (i, item) in items.into_iter().enumerate() {
+            out.push(doc!["\n", self.pad(level + 1), item]);
+            if i != last || trailing_dot {
+                out.push(".");
+            }
+        }
+        out.push(doc!["\n", self.pad(level), ")"]);
+        out
+    }
+
+    fn when(&self, When { subject, arms, otherwise }: &When, level: usize) -> Doc {
+        let mut parts = arms
+            .iter()
+            .map(|arm| {
+                let Arm { pattern, guard, body } = &arm.inner;
+                let guard = guard
+                    .as_ref()
+                    .map(|g| doc![" where ", self.expr(g, level + 1)])
+                    .unwrap_or_default();
+                doc![
+                    "is ",
+                    self.pattern(pattern),
+                    guard,
+                    " then ",
+                    self.expr(body, level + 1),
+                ]
+                .styled(&arm.style)
+            })
+            .collect::<Vec<_>>();
+        parts.extend(otherwise.iter().map(|o| doc!["otherwise ", self.expr(o, level + 1)]));
+
+        let head = doc!["when ", self.expr(subject, level)];
+        let flat = doc![head.clone(), " ", join(parts.clone(), ", ")];
+        if self.fits(level, &flat) {
+            return flat;
+        }
+
+        let sep = format!(",\n{}", self.pad(level + 1));
+        doc![head, "\n", self.pad(level + 1), join(parts, &sep)]
+    }
+
+    fn pattern(&self, pattern: &S<Pattern>) -> Doc {
+        let doc = match &pattern.inner {
+            Pattern::Num(n) => doc![format!("{}", n)],
+            Pattern::String(s) => string(s),
+            Pattern::EmptyList => doc!["an empty list"],
+            Pattern::ListStartingWith { head, rest: None } => {
+                doc!["a list starting with ", name(head)]
+            }
+            Pattern::ListStartingWith { head, rest: Some(rest) } => {
+                doc!["a list starting with ", name(head), " followed by ", name(rest)]
+            }
+            Pattern::Bind(n) => name(n),
+            Pattern::Anything => doc!["anything"],
+        };
+
+        doc.styled(&pattern.style)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_ok;
+    use docx_rs::{AlignmentType, RunProperty};
+
+    /// Every kind of statement and expression, in the form the parser reads.
+    const PROGRAM: &str = "\
+import \"lib.cado\".
+import f and g from “lib.cado”.
+set x to 1.5.
+set name to \"a {{literal}}\".
+set greeting to “hi {name}, {x + 1}!”.
+set l to [1, -x, not x, “a”].
+set k to a ledger where apples is 3, “two words” is [1], and “is” is 2.
+set e to an empty ledger.
+set apples in k to get 0 from l.
+emit both x and l!
+emit either x or l!
+emit either x or l but not both!
+set y to is x > 1 ? x * 2 otherwise (x - 1) / 2.
+set z to is x = 1 ? 0.
+procedure f takes a and b does (
+    set c to a + b.
+    while c > 0 run (set c to c - 1. skip ahead).
+    run (emit n!) for n in l.
+    keep doing (set c to c + 1) until c > 10.
+    give back c
+)
+procedure g takes does do f using 1, 2, and 3
+set h to a procedure taking n that does n * 2.
+set i to a procedure that does do h using 1.
+run (is x = 2 ? 1. emit x!) for x in l
+keep doing (set x to x + 1) until x > 10
+while x run (set x to x - 1)
+emit when l is an empty list then 0, is a list starting with first followed by rest \
+where first > 1 then rest, is a list starting with only then only, is 1 then 2, \
+is “s” then 3, is anything then 4!
+emit when x is n then n, otherwise 5!
+set last to (set x to 1. x.)
+set nothing to ().
+§ Section
+set s to 1.
+§§ Sub
+stop.
+§ Other
+skip ahead.
+";
+
+    fn round_trip(source: &str, options: &PrettyOptions) {
+        let parsed = parse_ok(source);
+        let printed = module(&parsed, options);
+        let reparsed = parse_ok(&printed);
+
+        // `{:#?}` leaves out spans (and styles, which `.cado` doesn't have).
+        assert_eq!(
+            format!("{:#?}", reparsed.body),
+            format!("{:#?}", parsed.body),
+            "printed as:\n{}",
+            printed,
+        );
+    }
+
+    #[test]
+    fn programs_parse_back() {
+        round_trip(PROGRAM, &PrettyOptions::default());
+    }
+
+    #[test]
+    fn split_lines_parse_back() {
+        // Narrow enough that every block and `when` is split.
+        round_trip(PROGRAM, &PrettyOptions { indent: 2, width: 10 });
+    }
+
+    #[test]
+    fn loops_in_blocks_parse_back() {
+        let parsed = parse_ok("set x to (while x run (set x to x - 1). x).");
+        let printed = module(&parsed, &PrettyOptions::default());
+        assert_eq!(printed, "set x to (while x run (set x to x - 1). x).\n");
+
+        // The inner block fits on its own line.
+        let narrow = PrettyOptions { indent: 2, width: 20 };
+        assert_eq!(
+            module(&parsed, &narrow),
+            "set x to (\n  while x run (set x to x - 1).\n  x\n).\n",
+        );
+        round_trip("set x to (while x run (set x to x - 1). x).", &narrow);
+    }
+
+    /// A document with a paragraph for each line: its paragraph style, its
+    /// alignment and its runs.
+    type Lines<'a> = &'a [(Option<&'a str>, Option<AlignmentType>, &'a [(&'a str, RunProperty)])];
+
+    /// Writes `doc` out and reads it back, like a document that was saved.
+    fn saved(doc: Docx) -> Docx {
+        let mut file = std::io::Cursor::new(vec![]);
+        doc.build().pack(&mut file).unwrap();
+        docx_rs::read_docx(file.get_ref()).unwrap()
+    }
+
+    fn document(lines: Lines<'_>) -> Docx {
+        let doc = lines.iter().fold(Docx::new(), |doc, (style, align, runs)| {
+            let mut paragraph = runs.iter().fold(Paragraph::new(), |p, (text, prop)| {
+                let mut run = Run::new().add_text(*text);
+                run.run_property = prop.clone();
+                p.add_run(run)
+            });
+            if let Some(style) = style {
+                paragraph = paragraph.style(style);
+            }
+            if let Some(align) = align {
+                paragraph = paragraph.align(*align);
+            }
+            doc.add_paragraph(paragraph)
+        });
+
+        saved(doc)
+    }
+
+    fn tokens(doc: &Docx) -> Vec<(abogado_lex::Token, Style)> {
+        let (tokens, _) = abogado_lex::lex_docx(doc);
+        tokens.unwrap().into_iter().map(|t| (t.inner, t.style)).collect()
+    }
+
+    #[test]
+    fn documents_keep_their_styles() {
+        use docx_rs::{Color, Highlight, RunFonts, TextBorder, Underline};
+
+        let plain = RunProperty::new;
+        let coded = RunProperty {
+            fonts: Some(RunFonts::new().ascii("Courier New")),
+            ..plain()
+        };
+        let marked = RunProperty {
+            highlight: Some(Highlight::new("yellow")),
+            color: Some(Color::new("FF0000")),
+            ..plain()
+        };
+        let bordered = RunProperty {
+            text_border: Some(TextBorder::new()),
+            ..plain()
+        };
+        let exported = RunProperty {
+            underline: Some(Underline::new("single")),
+            ..bordered.clone()
+        };
+
+        let original = document(&[
+            (Some("Heading1"), None, &[("Setup", plain())]),
+            (None, None, &[("set ", plain()), ("x", coded), (" to 1.", plain())]),
+            (None, None, &[("emit x!.", marked)]),
+            (
+                None,
+                Some(AlignmentType::Center),
+                &[("set ", bordered.clone()), ("y", exported), (" to “hi”.", bordered)],
+            ),
+        ]);
+
+        let (lexed, source) = abogado_lex::lex_docx(&original);
+        let (module, errors) = crate::parse_docx("test.docx", lexed.unwrap(), &source);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let written = saved(docx(&module, &PrettyOptions::default()));
+        assert_eq!(tokens(&written), tokens(&original));
+    }
+}
@@ -0,0 +1,750 @@
+//! Traversals over the [AST](crate::ast).
+//!
+//! [`Visitor`] and [`VisitorMut`] walk a module by reference; [`Fold`] takes
+//! it apart and builds a new one. Every method has a default that just walks
+//! the node's children (by calling the free function with the same name), so
+//! implementors only override the nodes they care about and call back into
+//! the free function to keep going:
+//!
+//! ```ignore
+//! impl Visitor for Calls {
+//!     fn visit_expr(&mut self, e: &S<Expr>) {
+//!         if let Expr::Call(c) = &e.inner {
+//!             self.0.push(c.name.inner.clone());
+//!         }
+//!
+//!         visit_expr(self, e);
+//!     }
+//! }
+//! ```
+//!
+//! Names get their own methods, split by what they're doing: a
+//! [definition](Visitor::visit_definition) gives a name a value (variables,
+//! parameters, procedures, `when` bindings and imports) and a
+//! [reference](Visitor::visit_reference) is a name that's used but isn't an
+//! expression of its own (`do <name> using ...` and `set ... in <name> ...`).
+//! Plain uses of variables are [`Expr::Ident`]s.
+
+use abogado_lex::spanned::S;
+
+use crate::ast::*;
+
+pub trait Visitor {
+    fn visit_module(&mut self, module: &Module) {
+        visit_module(self, module)
+    }
+
+    fn visit_program(&mut self, program: &[S<Statement>]) {
+        visit_program(self, program)
+    }
+
+    fn visit_statement(&mut self, s: &S<Statement>) {
+        visit_statement(self, s)
+    }
+
+    fn visit_expr(&mut self, e: &S<Expr>) {
+        visit_expr(self, e)
+    }
+
+    fn visit_arm(&mut self, arm: &S<Arm>) {
+        visit_arm(self, arm)
+    }
+
+    fn visit_pattern(&mut self, pattern: &S<Pattern>) {
+        visit_pattern(self, pattern)
+    }
+
+    fn visit_definition(&mut self, _name: &S<Ident>) {}
+
+    fn visit_reference(&mut self, _name: &S<Ident>) {}
+
+    /// Section titles, ledger keys, import sources and the text parts of
+    /// interpolated strings.
+    fn visit_text(&mut self, _text: &S<String>) {}
+}
+
+pub fn visit_module<V: Visitor + ?Sized>(v: &mut V, module: &Module) {
+    v.visit_program(&module.body);
+}
+
+pub fn visit_program<V: Visitor + ?Sized>(v: &mut V, program: &[S<Statement>]) {
+    program.iter().for_each(|s| v.visit_statement(s));
+}
+
+pub fn visit_statement<V: Visitor + ?Sized>(v: &mut V, s: &S<Statement>) {
+    match &s.inner {
+        Statement::Expr(e) => v.visit_expr(e),
+        Statement::While(While { cond, body }) => {
+            v.visit_expr(cond);
+            v.visit_expr(body);
+        }
+        Statement::Until(Until { body, cond }) => {
+            v.visit_expr(body);
+            v.visit_expr(cond);
+        }
+        Statement::For(For { name, list, body }) => {
+            v.visit_definition(name);
+            v.visit_expr(list);
+            v.visit_expr(body);
+        }
+        Statement::Procedure(Procedure { name, args, body }) => {
+            v.visit_definition(name);
+            args.iter().for_each(|a| v.visit_definition(a));
+            v.visit_expr(body);
+        }
+        Statement::Section(Section { title, body, .. }) => {
+            v.visit_text(title);
+            body.iter().for_each(|s| v.visit_statement(s));
+        }
+        Statement::Return(Return { value }) => v.visit_expr(value),
+        Statement::Import(Import { names, source }) => {
+            names.iter().flatten().for_each(|n| v.visit_definition(n));
+            v.visit_text(source);
+        }
+        Statement::Break | Statement::Continue => {}
+    }
+}
+
+pub fn visit_expr<V: Visitor + ?Sized>(v: &mut V, e: &S<Expr>) {
+    match &e.inner {
+        Expr::If(If {
+            cond,
+            then,
+            otherwise,
+        }) => {
+            v.visit_expr(cond);
+            v.visit_expr(then);
+            otherwise.iter().for_each(|o| v.visit_expr(o));
+        }
+        Expr::When(When {
+            subject,
+            arms,
+            otherwise,
+        }) => {
+            v.visit_expr(subject);
+            arms.iter().for_each(|a| v.visit_arm(a));
+            otherwise.iter().for_each(|o| v.visit_expr(o));
+        }
+        Expr::BinOp(BinOp { lhs, rhs, .. }) | Expr::Logic(Logic { lhs, rhs, .. }) => {
+            v.visit_expr(lhs);
+            v.visit_expr(rhs);
+        }
+        Expr::UnOp(UnOp { expr, .. }) => v.visit_expr(expr),
+        Expr::Call(Call { name, args }) => {
+            v.visit_reference(name);
+            args.iter().for_each(|a| v.visit_expr(a));
+        }
+        Expr::Num(_) | Expr::String(_) | Expr::Ident(_) => {}
+        Expr::List(List(items)) => items.iter().for_each(|i| v.visit_expr(i)),
+        Expr::Assign(Assign { name, to }) => {
+            v.visit_definition(name);
+            v.visit_expr(to);
+        }
+        Expr::Update(Update { key, target, to }) => {
+            v.visit_expr(key);
+            v.visit_reference(target);
+            v.visit_expr(to);
+        }
+        Expr::Ledger(entries) => {
+            for Entry { key, value } in entries {
+                v.visit_text(key);
+                v.visit_expr(value);
+            }
+        }
+        Expr::Print(p) => v.visit_expr(p),
+        Expr::Get(Get { index, from }) => {
+            v.visit_expr(index);
+            v.visit_expr(from);
+        }
+        Expr::Block(Block { body, end }) => {
+            body.iter().for_each(|s| v.visit_statement(s));
+            end.iter().for_each(|e| v.visit_expr(e));
+        }
+        Expr::Lambda(Lambda { args, body }) => {
+            args.iter().for_each(|a| v.visit_definition(a));
+            v.visit_expr(body);
+        }
+        Expr::Interpolated(fragments) => {
+            for f in fragments {
+                match f {
+                    Fragment::Text(t) => v.visit_text(t),
+                    Fragment::Expr(e) => v.visit_expr(e),
+                }
+            }
+        }
+    }
+}
+
+pub fn visit_arm<V: Visitor + ?Sized>(v: &mut V, arm: &S<Arm>) {
+    let Arm { pattern, guard, body } = &arm.inner;
+    v.visit_pattern(pattern);
+    guard.iter().for_each(|g| v.visit_expr(g));
+    v.visit_expr(body);
+}
+
+pub fn visit_pattern<V: Visitor + ?Sized>(v: &mut V, pattern: &S<Pattern>) {
+    match &pattern.inner {
+        Pattern::Bind(name) => v.visit_definition(name),
+        Pattern::ListStartingWith { head, rest } => {
+            v.visit_definition(head);
+            rest.iter().for_each(|r| v.visit_definition(r));
+        }
+        Pattern::Num(_) | Pattern::String(_) | Pattern::EmptyList | Pattern::Anything => {}
+    }
+}
+
+/// [`Visitor`], but with mutable references.
+pub trait VisitorMut {
+    fn visit_module_mut(&mut self, module: &mut Module) {
+        visit_module_mut(self, module)
+    }
+
+    fn visit_program_mut(&mut self, program: &mut [S<Statement>]) {
+        visit_program_mut(self, program)
+    }
+
+    fn visit_statement_mut(&mut self, s: &mut S<Statement>) {
+        visit_statement_mut(self, s)
+    }
+
+    fn visit_expr_mut(&mut self, e: &mut S<Expr>) {
+        visit_expr_mut(self, e)
+    }
+
+    fn visit_arm_mut(&mut self, arm: &mut S<Arm>) {
+        visit_arm_mut(self, arm)
+    }
+
+    fn visit_pattern_mut(&mut self, pattern: &mut S<Pattern>) {
+        visit_pattern_mut(self, pattern)
+    }
+
+    fn visit_definition_mut(&mut self, _name: &mut S<Ident>) {}
+
+    fn visit_reference_mut(&mut self, _name: &mut S<Ident>) {}
+
+    fn visit_text_mut(&mut self, _text: &mut S<String>) {}
+}
+
+pub fn visit_module_mut<V: VisitorMut + ?Sized>(v: &mut V, module: &mut Module) {
+    v.visit_program_mut(&mut module.body);
+}
+
+pub fn visit_program_mut<V: VisitorMut + ?Sized>(v: &mut V, program: &mut [S<Statement>]) {
+    program.iter_mut().for_each(|s| v.visit_statement_mut(s));
+}
+
+pub fn visit_statement_mut<V: VisitorMut + ?Sized>(v: &mut V, s: &mut S<Statement>) {
+    match &mut s.inner {
+        Statement::Expr(e) => v.visit_expr_mut(e),
+        Statement::While(While { cond, body }) => {
+            v.visit_expr_mut(cond);
+            v.visit_expr_mut(body);
+        }
+        Statement::Until(Until { body, cond }) => {
+            v.visit_expr_mut(body);
+            v.visit_expr_mut(cond);
+        }
+        Statement::For(For { name, list, body }) => {
+            v.visit_definition_mut(name);
+            v.visit_expr_mut(list);
+            v.visit_expr_mut(body);
+        }
+        Statement::Procedure(Procedure { name, args, body }) => {
+            v.visit_definition_mut(name);
+            args.iter_mut().for_each(|a| v.visit_definition_mut(a));
+            v.visit_expr_mut(body);
+        }
+        Statement::Section(Section { title, body, .. }) => {
+            v.visit_text_mut(title);
+            body.iter_mut().for_each(|s| v.visit_statement_mut(s));
+        }
+        Statement::Return(Return { value }) => v.visit_expr_mut(value),
+        Statement::Import(Import { names, source }) => {
+            names.iter_mut().flatten().for_each(|n| v.visit_definition_mut(n));
+            v.visit_text_mut(source);
+        }
+        Statement::Break | Statement::Continue => {}
+    }
+}
+
+pub fn visit_expr_mut<V: VisitorMut + ?Sized>(v: &mut V, e: &mut S<Expr>) {
+    match &mut e.inner {
+        Expr::If(If {
+            cond,
+            then,
+            otherwise,
+        }) => {
+            v.visit_expr_mut(cond);
+            v.visit_expr_mut(then);
+            otherwise.iter_mut().for_each(|o| v.visit_expr_mut(o));
+        }
+        Expr::When(When {
+            subject,
+            arms,
+            otherwise,
+        }) => {
+            v.visit_expr_mut(subject);
+            arms.iter_mut().for_each(|a| v.visit_arm_mut(a));
+            otherwise.iter_mut().for_each(|o| v.visit_expr_mut(o));
+        }
+        Expr::BinOp(BinOp { lhs, rhs, .. }) | Expr::Logic(Logic { lhs, rhs, .. }) => {
+            v.visit_expr_mut(lhs);
+            v.visit_expr_mut(rhs);
+        }
+        Expr::UnOp(UnOp { expr, .. }) => v.visit_expr_mut(expr),
+        Expr::Call(Call { name, args }) => {
+            v.visit_reference_mut(name);
+            args.iter_mut().for_each(|a| v.visit_expr_mut(a));
+        }
+        Expr::Num(_) | Expr::String(_) | Expr::Ident(_) => {}
+        Expr::List(List(items)) => items.iter_mut().for_each(|i| v.visit_expr_mut(i)),
+        Expr::Assign(Assign { name, to }) => {
+            v.visit_definition_mut(name);
+            v.visit_expr_mut(to);
+        }
+        Expr::Update(Update { key, target, to }) => {
+            v.visit_expr_mut(key);
+            v.visit_reference_mut(target);
+            v.visit_expr_mut(to);
+        }
+        Expr::Ledger(entries) => {
+            for Entry { key, value } in entries {
+                v.visit_text_mut(key);
+                v.visit_expr_mut(value);
+            }
+        }
+        Expr::Print(p) => v.visit_expr_mut(p),
+        Expr::Get(Get { index, from }) => {
+            v.visit_expr_mut(index);
+            v.visit_expr_mut(from);
+        }
+        Expr::Block(Block { body, end }) => {
+            body.iter_mut().for_each(|s| v.visit_statement_mut(s));
+            end.iter_mut().for_each(|e| v.visit_expr_mut(e));
+        }
+        Expr::Lambda(Lambda { args, body }) => {
+            args.iter_mut().for_each(|a| v.visit_definition_mut(a));
+            v.visit_expr_mut(body);
+        }
+        Expr::Interpolated(fragments) => {
+            for f in fragments {
+                match f {
+                    Fragment::Text(t) => v.visit_text_mut(t),
+                    Fragment::Expr(e) => v.visit_expr_mut(e),
+                }
+            }
+        }
+    }
+}
+
+pub fn visit_arm_mut<V: VisitorMut + ?Sized>(v: &mut V, arm: &mut S<Arm>) {
+    let Arm { pattern, guard, body } = &mut arm.inner;
+    v.visit_pattern_mut(pattern);
+    guard.iter_mut().for_each(|g| v.visit_expr_mut(g));
+    v.visit_expr_mut(body);
+}
+
+pub fn visit_pattern_mut<V: VisitorMut + ?Sized>(v: &mut V, pattern: &mut S<Pattern>) {
+    match &mut pattern.inner {
+        Pattern::Bind(name) => v.visit_definition_mut(name),
+        Pattern::ListStartingWith { head, rest } => {
+            v.visit_definition_mut(head);
+            rest.iter_mut().for_each(|r| v.visit_definition_mut(r));
+        }
+        Pattern::Num(_) | Pattern::String(_) | Pattern::EmptyList | Pattern::Anything => {}
+    }
+}
+
+/// Rewrites a program into a new one.
+///
+/// Spans and styles are kept unless a method changes them; a method that
+/// replaces a node should usually give the replacement the old node's.
+pub trait Fold {
+    fn fold_module(&mut self, module: Module) -> Module {
+        fold_module(self, module)
+    }
+
+    fn fold_program(&mut self, program: Vec<S<Statement>>) -> Vec<S<Statement>> {
+        fold_program(self, program)
+    }
+
+    fn fold_statement(&mut self, s: S<Statement>) -> S<Statement> {
+        fold_statement(self, s)
+    }
+
+    fn fold_expr(&mut self, e: S<Expr>) -> S<Expr> {
+        fold_expr(self, e)
+    }
+
+    fn fold_arm(&mut self, arm: S<Arm>) -> S<Arm> {
+        fold_arm(self, arm)
+    }
+
+    fn fold_pattern(&mut self, pattern: S<Pattern>) -> S<Pattern> {
+        fold_pattern(self, pattern)
+    }
+
+    fn fold_definition(&mut self, name: S<Ident>) -> S<Ident> {
+        name
+    }
+
+    fn fold_reference(&mut self, name: S<Ident>) -> S<Ident> {
+        name
+    }
+
+    fn fold_text(&mut self, text: S<String>) -> S<String> {
+        text
+    }
+}
+
+fn fold_boxed<F: Fold + ?Sized>(f: &mut F, e: Box<S<Expr>>) -> Box<S<Expr>> {
+    Box::new(f.fold_expr(*e))
+}
+
+pub fn fold_module<F: Fold + ?Sized>(f: &mut F, module: Module) -> Module {
+    Module {
+        metadata: module.metadata,
+        ..Module::new(module.name, f.fold_program(module.body))
+    }
+}
+
+pub fn fold_program<F: Fold + ?Sized>(f: &mut F, program: Vec<S<Statement>>) -> Vec<S<Statement>> {
+    program.into_iter().map(|s| f.fold_statement(s)).collect()
+}
+
+pub fn fold_statement<F: Fold + ?Sized>(f: &mut F, s: S<Statement>) -> S<Statement> {
+    s.map(|s| match s {
+        Statement::Expr(e) => Statement::Expr(f.fold_expr(e)),
+        Statement::While(While { cond, body }) => Statement::While(While {
+            cond: fold_boxed(f, cond),
+            body: fold_boxed(f, body),
+        }),
+        Statement::Until(Until { body, cond }) => Statement::Until(Until {
+            body: fold_boxed(f, body),
+            cond: fold_boxed(f, cond),
+        }),
+        Statement::For(For { name, list, body }) => Statement::For(For {
+            name: f.fold_definition(name),
+            list: fold_boxed(f, list),
+            body: fold_boxed(f, body),
+        }),
+        Statement::Procedure(Procedure { name, args, body }) => Statement::Procedure(Procedure {
+            name: f.fold_definition(name),
+            args: args.into_iter().map(|a| f.fold_definition(a)).collect(),
+            body: fold_boxed(f, body),
+        }),
+        Statement::Section(Section { title, level, body }) => Statement::Section(Section {
+            title: f.fold_text(title),
+            level,
+            body: body.into_iter().map(|s| f.fold_statement(s)).collect(),
+        }),
+        Statement::Return(Return { value }) => Statement::Return(Return {
+            value: fold_boxed(f, value),
+        }),
+        Statement::Import(Import { names, source }) => Statement::Import(Import {
+            names: names.map(|n| n.into_iter().map(|n| f.fold_definition(n)).collect()),
+            source: f.fold_text(source),
+        }),
+        s @ Statement::Break | s @ Statement::Continue => s,
+    })
+}
+
+pub fn fold_expr<F: Fold + ?Sized>(f: &mut F, e: S<Expr>) -> S<Expr> {
+    e.map(|e| match e {
+        Expr::If(If {
+            cond,
+            then,
+            otherwise,
+        }) => Expr::If(If {
+            cond: fold_boxed(f, cond),
+            then: fold_boxed(f, then),
+            otherwise: otherwise.map(|o| fold_boxed(f, o)),
+        }),
+        Expr::When(When {
+            subject,
+            arms,
+            otherwise,
+        }) => Expr::When(When {
+            subject: fold_boxed(f, subject),
+            arms: arms.into_iter().map(|a| f.fold_arm(a)).collect(),
+            otherwise: otherwise.map(|o| fold_boxed(f, o)),
+        }),
+        Expr::BinOp(BinOp { lhs, op, rhs }) => Expr::BinOp(BinOp {
+            lhs: fold_boxed(f, lhs),
+            op,
+            rhs: fold_boxed(f, rhs),
+        }),
+        Expr::Logic(Logic { lhs, op, rhs }) => Expr::Logic(Logic {
+            lhs: fold_boxed(f, lhs),
+            op,
+            rhs: fold_boxed(f, rhs),
+        }),
+        Expr::UnOp(UnOp { op, expr }) => Expr::UnOp(UnOp {
+            op,
+            expr: fold_boxed(f, expr),
+        }),
+        Expr::Call(Call { name, args }) => Expr::Call(Call {
+            name: f.fold_reference(name),
+            args: args.into_iter().map(|a| f.fold_expr(a)).collect(),
+        }),
+        e @ Expr::Num(_) | e @ Expr::String(_) | e @ Expr::Ident(_) => e,
+        Expr::List(List(items)) => {
+            Expr::List(List(items.into_iter().map(|i| f.fold_expr(i)).collect()))
+        }
+        Expr::Assign(Assign { name, to }) => Expr::Assign(Assign {
+            name: f.fold_definition(name),
+            to: fold_boxed(f, to),
+        }),
+        Expr::Update(Update { key, target, to }) => Expr::Update(Update {
+            key: fold_boxed(f, key),
+            target: f.fold_reference(target),
+            to: fold_boxed(f, to),
+        }),
+        Expr::Ledger(entries) => Expr::Ledger(
+            entries
+                .into_iter()
+                .map(|Entry { key, value }| Entry {
+                    key: f.fold_text(key),
+                    value: f.fold_expr(value),
+                })
+                .collect(),
+        ),
+        Expr::Print(p) => Expr::Print(fold_boxed(f, p)),
+        Expr::Get(Get { index, from }) => Expr::Get(Get {
+            index: fold_boxed(f, index),
+            from: fold_boxed(f, from),
+        }),
+        Expr::Block(Block { body, end }) => Expr::Block(Block {
+            body: body.into_iter().map(|s| f.fold_statement(s)).collect(),
+            end: end.map(|e| fold_boxed(f, e)),
+        }),
+        Expr::Lambda(Lambda { args, body }) => Expr::Lambda(Lambda {
+            args: args.into_iter().map(|a| f.fold_definition(a)).collect(),
+            body: fold_boxed(f, body),
+        }),
+        Expr::Interpolated(fragments) => Expr::Interpolated(
+            fragments
+                .into_iter()
+                .map(|frag| match frag {
+                    Fragment::Text(t) => Fragment::Text(f.fold_text(t)),
+                    Fragment::Expr(e) => Fragment::Expr(f.fold_expr(e)),
+                })
+                .collect(),
+        ),
+    })
+}
+
+pub fn fold_arm<F: Fold + ?Sized>(f: &mut F, arm: S<Arm>) -> S<Arm> {
+    arm.map(|Arm { pattern, guard, body }| Arm {
+        pattern: f.fold_pattern(pattern),
+        guard: guard.map(|g| f.fold_expr(g)),
+        body: f.fold_expr(body),
+    })
+}
+
+pub fn fold_pattern<F: Fold + ?Sized>(f: &mut F, pattern: S<Pattern>) -> S<Pattern> {
+    pattern.map(|p| match p {
+        Pattern::Bind(name) => Pattern::Bind(f.fold_definition(name)),
+        Pattern::ListStartingWith { head, rest } => Pattern::ListStartingWith {
+            head: f.fold_definition(head),
+            rest: rest.map(|r| f.fold_definition(r)),
+        },
+        p @ Pattern::Num(_)
+        | p @ Pattern::String(_)
+        | p @ Pattern::EmptyList
+        | p @ Pattern::Anything => p,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::parse_ok;
+
+    use std::collections::BTreeSet;
+
+    /// Every kind of statement and expression; the names say where they are.
+    const PROGRAM: &str = "\
+import f from “lib.cado”.
+set k to a ledger where apples is in_ledger.
+set apples in k to get 0 from [1, -x, “s”].
+set g to a procedure taking n that does in_lambda.
+set y to is both a and b ? “hi {in_string}” otherwise (x - 1).
+emit when l is a list starting with first followed by rest where in_guard then in_arm, \
+otherwise 4!
+procedure p takes a does (
+    while a run (set a to a - 1. skip ahead).
+    keep doing (stop) until a.
+    run (do f using a) for n in l.
+    give back a
+)
+§ Section
+set z to in_section.
+";
+
+    // These are exhaustive so that new kinds of nodes have to be added here
+    // (and to the program).
+    fn statement_kind(s: &Statement) -> &'static str {
+        match s {
+            Statement::Expr(_) => "expression statement",
+            Statement::While(_) => "while",
+            Statement::Until(_) => "until",
+            Statement::For(_) => "for",
+            Statement::Procedure(_) => "procedure",
+            Statement::Section(_) => "section",
+            Statement::Return(_) => "return",
+            Statement::Import(_) => "import",
+            Statement::Break => "break",
+            Statement::Continue => "continue",
+        }
+    }
+
+    fn expr_kind(e: &Expr) -> &'static str {
+        match e {
+            Expr::If(_) => "if",
+            Expr::When(_) => "when",
+            Expr::BinOp(_) => "binary operator",
+            Expr::Logic(_) => "logic",
+            Expr::UnOp(_) => "unary operator",
+            Expr::Call(_) => "call",
+            Expr::Num(_) => "number",
+            Expr::String(_) => "string",
+            Expr::Ident(_) => "name",
+            Expr::List(_) => "list",
+            Expr::Assign(_) => "assignment",
+            Expr::Update(_) => "update",
+            Expr::Ledger(_) => "ledger",
+            Expr::Print(_) => "emit",
+            Expr::Get(_) => "get",
+            Expr::Block(_) => "block",
+            Expr::Lambda(_) => "anonymous procedure",
+            Expr::Interpolated(_) => "interpolated string",
+        }
+    }
+
+    /// What a [`Visitor`] saw, in order.
+    #[derive(Default)]
+    struct Seen(Vec<String>);
+
+    impl Visitor for Seen {
+        fn visit_statement(&mut self, s: &S<Statement>) {
+            self.0.push(statement_kind(&s.inner).to_string());
+            visit_statement(self, s);
+        }
+
+        fn visit_expr(&mut self, e: &S<Expr>) {
+            match &e.inner {
+                Expr::Ident(i) => self.0.push(format!("name {}", i)),
+                other => self.0.push(expr_kind(other).to_string()),
+            }
+            visit_expr(self, e);
+        }
+
+        fn visit_arm(&mut self, arm: &S<Arm>) {
+            self.0.push("arm".to_string());
+            visit_arm(self, arm);
+        }
+
+        fn visit_pattern(&mut self, pattern: &S<Pattern>) {
+            self.0.push("pattern".to_string());
+            visit_pattern(self, pattern);
+        }
+
+        fn visit_definition(&mut self, name: &S<Ident>) {
+            self.0.push(format!("definition {}", name.inner));
+        }
+
+        fn visit_reference(&mut self, name: &S<Ident>) {
+            self.0.push(format!("reference {}", name.inner));
+        }
+
+        fn visit_text(&mut self, text: &S<String>) {
+            self.0.push(format!("text {}", text.inner));
+        }
+    }
+
+    #[test]
+    fn every_node_is_visited() {
+        let mut seen = Seen::default();
+        seen.visit_module(&parse_ok(PROGRAM));
+        let seen = seen.0.iter().map(String::as_str).collect::<BTreeSet<_>>();
+
+        let kinds = [
+            "expression statement",
+            "while",
+            "until",
+            "for",
+            "procedure",
+            "section",
+            "return",
+            "import",
+            "break",
+            "continue",
+            "if",
+            "when",
+            "binary operator",
+            "logic",
+            "unary operator",
+            "call",
+            "number",
+            "string",
+            "list",
+            "assignment",
+            "update",
+            "ledger",
+            "emit",
+            "get",
+            "block",
+            "anonymous procedure",
+            "interpolated string",
+            "arm",
+            "pattern",
+        ];
+        for kind in kinds {
+            assert!(seen.contains(kind), "{} wasn't visited", kind);
+        }
+
+        for inside in [
+            // Imports, sections and ledgers:
+            "definition f",
+            "text lib.cado",
+            "text Section",
+            "name in_section",
+            "text apples",
+            "name in_ledger",
+            // Updates and calls:
+            "reference k",
+            "reference f",
+            // Anonymous procedures:
+            "definition n",
+            "name in_lambda",
+            // Strings:
+            "text hi ",
+            "name in_string",
+            // `when` arms:
+            "definition first",
+            "definition rest",
+            "name in_guard",
+            "name in_arm",
+            // Procedures and loops:
+            "definition p",
+            "definition a",
+        ] {
+            assert!(seen.contains(inside), "{} wasn't visited", inside);
+        }
+    }
+
+    struct Identity;
+
+    impl Fold for Identity {}
+
+    #[test]
+    fn folding_without_changes_gives_back_the_same_module() {
+        let module = parse_ok(PROGRAM);
+        let folded = Identity.fold_module(module.clone());
+
+        // Without `#`, `Debug` shows every span and style too.
+        assert_eq!(format!("{:?}", folded), format!("{:?}", module));
+    }
+}
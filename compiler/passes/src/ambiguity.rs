@@ -0,0 +1,136 @@
+//! Finds lists that can be read more than one way.
+//!
+//! `do f using do g using a, b` parses as `f(g(a, b))` but could just as well
+//! mean `f(g(a), b)`; calls that are inside of a list and have more than one
+//! argument need parentheses to say which items are theirs.
+//!
+//! `and` also separates items, so `do f using both a and b` reads like it has
+//! two arguments, `a` and `b`. The parser gives it one (`both` takes the
+//! `and`); a `both` that's an item in a list needs parentheses to say so.
+
+use abogado_lex::{spanned::S, Span};
+use abogado_parse::ast::{Call, Expr, Ident, List, Logic, LogicOp, Module};
+use abogado_parse::visit::{self, Visitor};
+use thiserror::Error;
+
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum AmbiguityError {
+    #[error(
+        "it's not clear which items are arguments to `{name}`; put parentheses around `do {name} \
+         using ...` and its arguments"
+    )]
+    NestedCall { name: Ident, span: Span },
+    #[error(
+        "`both ... and ...` in a list reads like two items; put parentheses around it if it's \
+         one, or drop the `both` if it's two"
+    )]
+    Connective { span: Span },
+}
+
+impl AmbiguityError {
+    /// The spans this error points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            AmbiguityError::NestedCall { span, .. } => vec![(span, "this call")],
+            AmbiguityError::Connective { span } => vec![(span, "this is one item")],
+        }
+    }
+}
+
+#[derive(Default)]
+struct Lists {
+    errors: Vec<AmbiguityError>,
+}
+
+impl Lists {
+    fn items(&mut self, items: &[S<Expr>]) {
+        for item in items {
+            match &item.inner {
+                Expr::Call(Call { name, args }) if args.len() > 1 => {
+                    self.errors.push(AmbiguityError::NestedCall {
+                        name: name.inner.clone(),
+                        span: item.span.clone(),
+                    });
+                }
+                Expr::Logic(Logic { op, .. }) if op.inner == LogicOp::And => {
+                    self.errors.push(AmbiguityError::Connective {
+                        span: item.span.clone(),
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl Visitor for Lists {
+    fn visit_expr(&mut self, e: &S<Expr>) {
+        match &e.inner {
+            Expr::Call(Call { args: items, .. }) | Expr::List(List(items)) => self.items(items),
+            _ => {}
+        }
+
+        visit::visit_expr(self, e);
+    }
+}
+
+pub fn check(module: &Module) -> Vec<AmbiguityError> {
+    let mut lists = Lists::default();
+    lists.visit_module(module);
+    lists.errors
+}
+
+pub struct AmbiguityCheck;
+
+impl Pass for AmbiguityCheck {
+    fn name(&self) -> &'static str {
+        "ambiguity"
+    }
+
+    fn run(&mut self, module: &Module, _: &mut Analyses) -> Vec<Diagnostic> {
+        check(module).iter().map(|e| Diagnostic::error(e, e.labels())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::cado;
+
+    #[test]
+    fn nested_calls_with_several_arguments() {
+        let errors = check(&cado("test.cado", "do f using do g using a, b."));
+        assert!(matches!(&errors[..], [AmbiguityError::NestedCall { name, .. }] if name == "g"));
+
+        assert!(check(&cado("test.cado", "do f using (do g using a, b).")).is_empty());
+        assert!(check(&cado("test.cado", "do f using do g using a.")).is_empty());
+    }
+
+    #[test]
+    fn both_in_a_list() {
+        for source in &[
+            "do f using both a and b.",
+            "do f using both a and b, c.",
+            "do f using c and both a and b.",
+            "set x to [both a and b, c].",
+        ] {
+            let errors = check(&cado("test.cado", source));
+            assert!(
+                matches!(&errors[..], [AmbiguityError::Connective { .. }]),
+                "{}: {:?}",
+                source,
+                errors
+            );
+        }
+
+        for source in &[
+            "do f using (both a and b), c.",
+            "do f using either a or b, c.",
+            "set x to both a and b.",
+        ] {
+            assert!(check(&cado("test.cado", source)).is_empty(), "{}", source);
+        }
+    }
+}
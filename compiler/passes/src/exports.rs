@@ -0,0 +1,301 @@
+//! Checks that modules only use the names that the modules they import export.
+//!
+//! Names can come from another module in two ways: by name (`import greet
+//! from "greetings.docx"`) or with the whole module (`import
+//! "greetings.docx"`). Both only see what the other module exports; see
+//! [`abogado_parse::interface`] for what that is.
+
+use std::collections::{HashMap, HashSet};
+
+use abogado_lex::{spanned::S, Span};
+use abogado_parse::ast::{Ident, Import, Module};
+use thiserror::Error;
+
+use crate::names::Names;
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ExportError {
+    #[error("`{name}` isn't exported by `{module}`; only its underlined names are")]
+    NotExported {
+        name: Ident,
+        module: String,
+        /// Where the name is used.
+        span: Span,
+        /// Where `module` defines it.
+        definition: Span,
+    },
+    #[error("`{module}` doesn't define `{name}`")]
+    Undefined {
+        name: Ident,
+        module: String,
+        span: Span,
+    },
+    #[error("`{name}` is private to a border in `{module}` and can't be imported")]
+    Private {
+        name: Ident,
+        module: String,
+        /// Where the name is imported or used.
+        span: Span,
+        /// The border in `module` that `name` is private to.
+        border: Span,
+    },
+    #[error("`{name}` isn't exported by `{module}`, so importing `{module}` doesn't bring it in")]
+    NotImported {
+        name: Ident,
+        module: String,
+        /// Where the name is used.
+        span: Span,
+        /// The `import` of the whole module.
+        import: Span,
+    },
+}
+
+impl ExportError {
+    /// The spans this error points at, with a note for each.
+    ///
+    /// These are spans in the importing module, except for the border of a
+    /// [`Private`](ExportError::Private) definition; that's in the other one.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            ExportError::Private { span, border, .. } => {
+                vec![(span, "used here"), (border, "private to this border in its module")]
+            }
+            ExportError::NotExported { span, .. } | ExportError::Undefined { span, .. } => {
+                vec![(span, "imported here")]
+            }
+            ExportError::NotImported { span, import, .. } => {
+                vec![(span, "used here"), (import, "module imported here")]
+            }
+        }
+    }
+}
+
+/// Checks the names an importing module refers to (`uses`) against the
+/// [interface](Module::interface) of `module`, the module they come from.
+pub fn check<'a>(
+    module: &Module,
+    uses: impl IntoIterator<Item = &'a S<Ident>>,
+) -> Vec<ExportError> {
+    let interface = module.interface();
+    uses.into_iter()
+        .filter_map(|name| {
+            if interface.export(&name.inner).is_some() {
+                return None;
+            }
+
+            Some(match interface.definition(&name.inner) {
+                Some(def) if def.border.is_some() => ExportError::Private {
+                    name: name.inner.clone(),
+                    module: module.name.clone(),
+                    span: name.span.clone(),
+                    border: def.border.clone().unwrap(),
+                },
+                Some(def) => ExportError::NotExported {
+                    name: name.inner.clone(),
+                    module: module.name.clone(),
+                    span: name.span.clone(),
+                    definition: def.name.span.clone(),
+                },
+                None => ExportError::Undefined {
+                    name: name.inner.clone(),
+                    module: module.name.clone(),
+                    span: name.span.clone(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Checks the names a module uses that can only come from the modules it
+/// imports whole: names it doesn't define (or import by name) itself.
+///
+/// A name is fine if one of those modules exports it; it's an error if one
+/// of them defines it without exporting it. Names that none of them define
+/// are left to [`resolve`](crate::resolve).
+pub fn check_uses(module: &Module, modules: &HashMap<String, Module>) -> Vec<ExportError> {
+    check_names(module, &Names::of(module), modules)
+}
+
+fn check_names(
+    module: &Module,
+    names: &Names,
+    modules: &HashMap<String, Module>,
+) -> Vec<ExportError> {
+    let whole = module
+        .imports
+        .iter()
+        .filter(|i| i.names.is_none())
+        .filter_map(|Import { source, .. }| {
+            let imported = modules.get(&source.inner)?;
+            Some((imported.interface(), imported, source))
+        })
+        .collect::<Vec<_>>();
+    if whole.is_empty() {
+        return vec![];
+    }
+
+    // Selectively imported names count as definitions here.
+    let defined = names.definitions.iter().map(|d| &d.name).collect::<HashSet<_>>();
+    names
+        .uses
+        .iter()
+        .filter(|u| !defined.contains(&u.name))
+        .filter(|u| whole.iter().all(|(i, ..)| i.export(&u.name).is_none()))
+        .filter_map(|u| {
+            let (def, imported, source) = whole
+                .iter()
+                .find_map(|(i, m, source)| Some((i.definition(&u.name)?, m, source)))?;
+            Some(match &def.border {
+                Some(border) => ExportError::Private {
+                    name: u.name.clone(),
+                    module: imported.name.clone(),
+                    span: u.span.clone(),
+                    border: border.clone(),
+                },
+                None => ExportError::NotImported {
+                    name: u.name.clone(),
+                    module: imported.name.clone(),
+                    span: u.span.clone(),
+                    import: source.span.clone(),
+                },
+            })
+        })
+        .collect()
+}
+
+/// [`check`]s every selective import in a module against the module it's
+/// from, and every name the module uses through a whole-module import (see
+/// [`check_uses`]).
+pub struct ExportCheck<'m> {
+    /// Keyed by source, like the `import`s in the checked module.
+    pub modules: &'m HashMap<String, Module>,
+}
+
+impl Pass for ExportCheck<'_> {
+    fn name(&self) -> &'static str {
+        "exports"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["names"]
+    }
+
+    fn run(&mut self, module: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        let names = analyses.get::<Names>().expect("`names` runs first");
+        let selective = module
+            .imports
+            .iter()
+            .filter_map(|i| Some((self.modules.get(&i.source.inner)?, i.names.as_ref()?)))
+            .flat_map(|(imported, names)| check(imported, names));
+
+        selective
+            .chain(check_names(module, names, self.modules))
+            .map(|e| Diagnostic::error(&e, e.labels()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{bordered, cado, docx, plain, underlined};
+    use docx_rs::RunProperty;
+
+    fn modules() -> HashMap<String, Module> {
+        let lib = docx(
+            "lib.docx",
+            &[
+                &[
+                    ("procedure ", plain()),
+                    ("greet", underlined()),
+                    (" takes name does emit name!", plain()),
+                ],
+                &[("procedure secret takes does 1.", plain())],
+                // Underlined, but private to its border.
+                &[
+                    ("procedure ", bordered()),
+                    ("hidden", RunProperty { underline: underlined().underline, ..bordered() }),
+                    (" takes does 2.", bordered()),
+                ],
+            ],
+        );
+
+        vec![("lib.docx".to_string(), lib)].into_iter().collect()
+    }
+
+    #[test]
+    fn only_underlined_names_are_exported() {
+        let modules = modules();
+        let exports = &modules["lib.docx"].exports;
+        assert_eq!(exports.len(), 1);
+        assert_eq!(exports[0].inner, "greet");
+
+        // Nothing is underlined, so nothing is exported.
+        let plain = cado("plain.cado", "procedure f takes does 1.\nset x to 2.");
+        assert!(plain.exports.is_empty());
+    }
+
+    #[test]
+    fn selective_imports_need_exports() {
+        let modules = modules();
+        let main = cado("main.cado", "import greet and secret from \"lib.docx\".");
+        let names = match main.imports[0].names.as_ref() {
+            Some(names) => names.clone(),
+            None => unreachable!(),
+        };
+
+        let errors = check(&modules["lib.docx"], &names);
+        assert!(matches!(
+            &errors[..],
+            [ExportError::NotExported { name, .. }] if name == "secret"
+        ));
+    }
+
+    #[test]
+    fn bordered_definitions_are_not_exported() {
+        let modules = modules();
+        let hidden = modules["lib.docx"].interface().definition("hidden").cloned().unwrap();
+        assert!(hidden.underlined);
+        let border = hidden.border.expect("`hidden` is in a border");
+
+        let main = cado("main.cado", "import hidden from \"lib.docx\".");
+        let names = main.imports[0].names.clone().unwrap();
+        let errors = check(&modules["lib.docx"], &names);
+        assert!(matches!(
+            &errors[..],
+            [ExportError::Private { name, border: b, .. }] if name == "hidden" && *b == border
+        ));
+
+        let whole = cado("main.cado", "import \"lib.docx\".\ndo hidden using.");
+        assert!(matches!(
+            &check_uses(&whole, &modules)[..],
+            [ExportError::Private { name, border: b, .. }] if name == "hidden" && *b == border
+        ));
+    }
+
+    #[test]
+    fn whole_module_imports_only_bring_in_exports() {
+        let modules = modules();
+        let main = cado(
+            "main.cado",
+            "import \"lib.docx\".\ndo greet using \"you\".\ndo secret using.\ndo missing using.",
+        );
+
+        // `missing` isn't anywhere; that's for `resolve` to report.
+        let errors = check_uses(&main, &modules);
+        assert!(matches!(
+            &errors[..],
+            [ExportError::NotImported { name, module, .. }]
+                if name == "secret" && module == "lib.docx"
+        ));
+        assert_eq!(errors[0].labels().len(), 2);
+
+        // Defining the name locally means it doesn't come from the import.
+        let local = cado(
+            "main.cado",
+            "import \"lib.docx\".\nprocedure secret takes does 2.\ndo secret using.",
+        );
+        assert!(check_uses(&local, &modules).is_empty());
+    }
+}
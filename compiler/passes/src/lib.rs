@@ -8,6 +8,40 @@
     html_root_url = "https://docs.rs/abogado-passes/0.0.0", // remember to bump!
 )]
 
+pub mod ambiguity;
+pub mod exports;
+pub mod matches;
+mod names;
+pub mod namespaces;
+pub mod pipeline;
+pub mod privacy;
+pub mod resolve;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
+pub mod types;
+
+use std::collections::HashMap;
+
+use abogado_parse::ast::Module;
+
+use pipeline::PassPipeline;
+
+/// Every check, in a pipeline; `modules` are the modules the checked ones
+/// import, keyed by source (for [`exports`] and [`resolve`]).
+pub fn checks(modules: &HashMap<String, Module>) -> PassPipeline<'_> {
+    PassPipeline::new("checks")
+        .with(names::CollectNames)
+        .with(privacy::FindRegions)
+        .with(ambiguity::AmbiguityCheck)
+        .with(namespaces::NamespaceCheck)
+        .with(resolve::ResolveCheck { modules })
+        .with(types::Lower)
+        .with(types::TypeCheck)
+        .with(privacy::PrivacyCheck)
+        .with(matches::MatchCheck)
+        .with(exports::ExportCheck { modules })
+}
+
 #[cfg(test)]
 mod tests {
     #[test]
@@ -0,0 +1,200 @@
+//! Warns about `when` expressions that don't handle every value or that have
+//! arms that can never be picked.
+//!
+//! Only arms without a `where` guard count: a guarded arm might not match.
+
+use abogado_lex::{spanned::S, Span};
+use abogado_parse::ast::{Expr, Module, Pattern, When};
+use abogado_parse::visit::{self, Visitor};
+use thiserror::Error;
+
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum MatchWarning {
+    #[error("this `when` doesn't handle every value; add an `otherwise`")]
+    NonExhaustive { span: Span },
+    #[error("this arm can never be picked")]
+    Unreachable {
+        span: Span,
+        /// The arm that matches everything this one would.
+        because: Span,
+    },
+}
+
+impl MatchWarning {
+    /// The spans this warning points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            MatchWarning::NonExhaustive { span } => vec![(span, "this `when`")],
+            MatchWarning::Unreachable { span, because } => vec![
+                (span, "this arm"),
+                (because, "everything it matches is matched here first"),
+            ],
+        }
+    }
+}
+
+/// Whether `earlier` matches every value `later` does.
+fn covers(earlier: &Pattern, later: &Pattern) -> bool {
+    match (earlier, later) {
+        (e, _) if e.is_catch_all() => true,
+        (Pattern::Num(a), Pattern::Num(b)) => a == b,
+        (Pattern::String(a), Pattern::String(b)) => a == b,
+        (Pattern::EmptyList, Pattern::EmptyList) => true,
+        (Pattern::ListStartingWith { .. }, Pattern::ListStartingWith { .. }) => true,
+        _ => false,
+    }
+}
+
+fn check_when(when: &When, span: &Span, warnings: &mut Vec<MatchWarning>) {
+    let mut unguarded: Vec<(&Pattern, &Span)> = vec![];
+
+    for arm in &when.arms {
+        let pattern = &arm.pattern.inner;
+        if let Some((_, because)) = unguarded.iter().find(|(p, _)| covers(p, pattern)) {
+            warnings.push(MatchWarning::Unreachable {
+                span: arm.span.clone(),
+                because: (*because).clone(),
+            });
+            continue;
+        }
+
+        if arm.guard.is_none() {
+            unguarded.push((pattern, &arm.span));
+        }
+    }
+
+    // Lists are either empty or start with something.
+    let catch_all = unguarded.iter().find(|(p, _)| p.is_catch_all()).map(|(_, s)| *s);
+    let empty = unguarded.iter().find(|(p, _)| matches!(p, Pattern::EmptyList));
+    let starting = unguarded
+        .iter()
+        .find(|(p, _)| matches!(p, Pattern::ListStartingWith { .. }));
+    let exhausted_by = catch_all.or_else(|| empty.and(starting).map(|(_, s)| *s));
+
+    match (&when.otherwise, exhausted_by) {
+        (Some(o), Some(because)) => warnings.push(MatchWarning::Unreachable {
+            span: o.span.clone(),
+            because: because.clone(),
+        }),
+        (None, None) => warnings.push(MatchWarning::NonExhaustive { span: span.clone() }),
+        _ => {}
+    }
+}
+
+#[derive(Default)]
+struct Whens {
+    warnings: Vec<MatchWarning>,
+}
+
+impl Visitor for Whens {
+    fn visit_expr(&mut self, e: &S<Expr>) {
+        if let Expr::When(w) = &e.inner {
+            check_when(w, &e.span, &mut self.warnings);
+        }
+
+        visit::visit_expr(self, e);
+    }
+}
+
+pub fn check(module: &Module) -> Vec<MatchWarning> {
+    let mut whens = Whens::default();
+    whens.visit_module(module);
+    whens.warnings
+}
+
+pub struct MatchCheck;
+
+impl Pass for MatchCheck {
+    fn name(&self) -> &'static str {
+        "matches"
+    }
+
+    fn run(&mut self, module: &Module, _: &mut Analyses) -> Vec<Diagnostic> {
+        check(module).iter().map(|w| Diagnostic::warning(w, w.labels())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::cado;
+
+    fn warnings(src: &str) -> Vec<MatchWarning> {
+        check(&cado("test.cado", src))
+    }
+
+    /// The span of the first `text` in `src`.
+    fn span(src: &str, text: &str) -> Span {
+        let start = src.find(text).unwrap();
+        Span::from(start..start + text.len())
+    }
+
+    #[test]
+    fn whens_without_a_catch_all_dont_handle_every_value() {
+        let src = "set s to when n is 0 then 1, is 1 then 2.";
+        assert_eq!(
+            warnings(src),
+            [MatchWarning::NonExhaustive { span: span(src, "when n is 0 then 1, is 1 then 2") }],
+        );
+    }
+
+    #[test]
+    fn catch_alls_and_otherwise_handle_every_value() {
+        assert!(warnings("set s to when n is 0 then 1, is anything then 2.").is_empty());
+        assert!(warnings("set s to when n is 0 then 1, is m then m.").is_empty());
+        assert!(warnings("set s to when n is 0 then 1, otherwise 2.").is_empty());
+    }
+
+    #[test]
+    fn empty_and_non_empty_lists_handle_every_list() {
+        let src = "set s to when l is an empty list then 0, is a list starting with x then x.";
+        assert!(warnings(src).is_empty());
+
+        let src = "set s to when l is a list starting with x followed by rest then rest.";
+        assert!(matches!(&warnings(src)[..], [MatchWarning::NonExhaustive { .. }]));
+    }
+
+    #[test]
+    fn guarded_arms_might_not_match() {
+        let src = "set s to when n is m where m > 0 then 1.";
+        assert!(matches!(&warnings(src)[..], [MatchWarning::NonExhaustive { .. }]));
+
+        // So they don't make later arms unreachable either.
+        let src = "set s to when n is m where m > 0 then 1, is 0 then 2, otherwise 3.";
+        assert!(warnings(src).is_empty());
+    }
+
+    #[test]
+    fn arms_after_a_catch_all_are_unreachable() {
+        let src = "set s to when n is anything then 1, is 0 then 2.";
+        assert_eq!(
+            warnings(src),
+            [MatchWarning::Unreachable {
+                span: span(src, "is 0 then 2"),
+                because: span(src, "is anything then 1"),
+            }],
+        );
+    }
+
+    #[test]
+    fn repeated_patterns_are_unreachable() {
+        let src = "set s to when n is 0 then 1, is 0 then 2, otherwise 3.";
+        assert_eq!(
+            warnings(src),
+            [MatchWarning::Unreachable {
+                span: span(src, "is 0 then 2"),
+                because: span(src, "is 0 then 1"),
+            }],
+        );
+    }
+
+    #[test]
+    fn otherwise_after_a_catch_all_is_unreachable() {
+        let src = "set s to when n is m then m, otherwise 0.";
+        let warnings = warnings(src);
+        assert!(matches!(&warnings[..], [MatchWarning::Unreachable { .. }]));
+        assert_eq!(warnings[0].labels()[1].0, &span(src, "is m then m"));
+    }
+}
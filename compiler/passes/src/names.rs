@@ -0,0 +1,117 @@
+//! Collects every place a name is defined or used in a program.
+
+use abogado_lex::{spanned::S, Span, Style};
+use abogado_parse::ast::{Expr, Ident, Module, Procedure, Section, Statement};
+use abogado_parse::visit::{self, Visitor};
+
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+#[derive(Debug, Clone)]
+pub(crate) struct Occurrence {
+    pub name: Ident,
+    pub style: Style,
+    pub span: Span,
+    /// The span of the top-level statement this is in.
+    pub statement: Span,
+    /// The named procedure this is in, if any. Procedures only see their own
+    /// names and the ones at the top level.
+    pub scope: Option<Span>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct Names {
+    pub definitions: Vec<Occurrence>,
+    pub uses: Vec<Occurrence>,
+    statement: Option<Span>,
+    scope: Option<Span>,
+}
+
+impl Names {
+    pub fn of(module: &Module) -> Names {
+        let mut names = Names::default();
+        names.top_level(&module.body);
+        names
+    }
+
+    fn top_level(&mut self, program: &[S<Statement>]) {
+        for s in program {
+            match &s.inner {
+                Statement::Section(Section { body, .. }) => self.top_level(body),
+                _ => {
+                    self.statement = Some(s.span.clone());
+                    self.visit_statement(s);
+                }
+            }
+        }
+    }
+
+    fn occurrence(&self, name: &Ident, style: &Style, span: &Span) -> Occurrence {
+        Occurrence {
+            name: name.clone(),
+            style: style.clone(),
+            span: span.clone(),
+            statement: self.statement.clone().unwrap(),
+            scope: self.scope.clone(),
+        }
+    }
+
+    fn define(&mut self, name: &S<Ident>) {
+        let def = self.occurrence(&name.inner, &name.style, &name.span);
+        self.definitions.push(def);
+    }
+
+    fn refer(&mut self, name: &Ident, style: &Style, span: &Span) {
+        let usage = self.occurrence(name, style, span);
+        self.uses.push(usage);
+    }
+}
+
+/// Leaves the module's [`Names`] in the [`Analyses`].
+pub(crate) struct CollectNames;
+
+impl Pass for CollectNames {
+    fn name(&self) -> &'static str {
+        "names"
+    }
+
+    fn run(&mut self, module: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        analyses.insert(Names::of(module));
+        vec![]
+    }
+}
+
+// Imported names are definitions too: they're defined where they're imported.
+impl Visitor for Names {
+    fn visit_statement(&mut self, s: &S<Statement>) {
+        match &s.inner {
+            // Procedures can be called from anywhere, but their parameters
+            // and variables are theirs alone.
+            Statement::Procedure(Procedure { name, args, body }) => {
+                let outer = self.scope.take();
+                self.define(name);
+
+                self.scope = Some(s.span.clone());
+                args.iter().for_each(|a| self.define(a));
+                self.visit_expr(body);
+                self.scope = outer;
+            }
+            _ => visit::visit_statement(self, s),
+        }
+    }
+
+    fn visit_expr(&mut self, e: &S<Expr>) {
+        if let Expr::Ident(i) = &e.inner {
+            self.refer(i, &e.style, &e.span);
+        }
+
+        visit::visit_expr(self, e);
+    }
+
+    fn visit_definition(&mut self, name: &S<Ident>) {
+        self.define(name);
+    }
+
+    fn visit_reference(&mut self, name: &S<Ident>) {
+        self.refer(&name.inner, &name.style, &name.span);
+    }
+}
@@ -0,0 +1,201 @@
+//! Checks that names only refer to definitions in their own namespace.
+//!
+//! Namespaces are fonts: `total` written in Comic Sans and `total` written in
+//! Garamond are different names. Names that don't have a font (i.e. the ones
+//! in `.cado` files, like `std`) are visible from every namespace.
+//!
+//! Names are only checked against the definitions they can see: the ones at
+//! the top level and the ones in the same procedure.
+
+use std::collections::HashMap;
+
+use abogado_lex::{Span, Style};
+use abogado_parse::ast::{Ident, Module};
+use thiserror::Error;
+
+use crate::names::{Names, Occurrence};
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+fn describe(font: &Option<String>) -> String {
+    match font {
+        Some(f) => format!("the `{}` font", f),
+        None => "no font".to_string(),
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum NamespaceError {
+    #[error(
+        "`{name}` is written in {} but it's only defined in {}",
+        describe(.font),
+        describe(.defined_in)
+    )]
+    DifferentFont {
+        name: Ident,
+        font: Option<String>,
+        span: Span,
+        defined_in: Option<String>,
+        definition: Span,
+    },
+}
+
+impl NamespaceError {
+    /// The spans this error points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            NamespaceError::DifferentFont {
+                span, definition, ..
+            } => vec![(span, "used here"), (definition, "defined here")],
+        }
+    }
+}
+
+/// Whether a name written in `used_in` can refer to a definition in
+/// `defined_in`.
+pub fn is_visible(defined_in: Option<&str>, used_in: Option<&str>) -> bool {
+    defined_in.is_none() || defined_in == used_in
+}
+
+fn font(style: &Style) -> Option<String> {
+    style.font().map(str::to_string)
+}
+
+/// Finds names that are only defined in other namespaces (fonts).
+///
+/// Names that aren't defined anywhere they can see aren't reported here.
+pub fn check(module: &Module) -> Vec<NamespaceError> {
+    check_names(&Names::of(module))
+}
+
+fn check_names(names: &Names) -> Vec<NamespaceError> {
+    let Names {
+        definitions, uses, ..
+    } = names;
+
+    let mut defined: HashMap<&Ident, Vec<&Occurrence>> = HashMap::new();
+    for def in definitions.iter() {
+        defined.entry(&def.name).or_default().push(def);
+    }
+
+    uses.iter()
+        .filter_map(|usage| {
+            // Only definitions at the top level or in the same procedure can
+            // be what this refers to.
+            let defs = defined
+                .get(&usage.name)?
+                .iter()
+                .filter(|def| def.scope.is_none() || def.scope == usage.scope)
+                .collect::<Vec<_>>();
+            let first = defs.first()?;
+
+            let font = font(&usage.style);
+            if defs
+                .iter()
+                .any(|def| is_visible(def.style.font(), font.as_deref()))
+            {
+                return None;
+            }
+
+            Some(NamespaceError::DifferentFont {
+                name: usage.name.clone(),
+                font,
+                span: usage.span.clone(),
+                defined_in: first.style.font().map(str::to_string),
+                definition: first.span.clone(),
+            })
+        })
+        .collect()
+}
+
+pub struct NamespaceCheck;
+
+impl Pass for NamespaceCheck {
+    fn name(&self) -> &'static str {
+        "namespaces"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["names"]
+    }
+
+    fn run(&mut self, _: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        let names = analyses.get::<Names>().expect("`names` runs first");
+        check_names(names).iter().map(|e| Diagnostic::error(e, e.labels())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{docx, in_font, plain};
+
+    #[test]
+    fn names_in_other_fonts_clash() {
+        let module = docx(
+            "main.docx",
+            &[
+                &[("set ", plain()), ("x", in_font("Garamond")), (" to 1.", plain())],
+                &[("emit ", plain()), ("x", in_font("Comic Sans")), ("!", plain())],
+            ],
+        );
+
+        let errors = check(&module);
+        assert!(matches!(
+            &errors[..],
+            [NamespaceError::DifferentFont { name, font: Some(f), defined_in: Some(d), .. }]
+                if name == "x" && f == "Comic Sans" && d == "Garamond"
+        ));
+        assert_eq!(
+            errors[0].to_string(),
+            "`x` is written in the `Comic Sans` font but it's only defined in the `Garamond` font"
+        );
+    }
+
+    #[test]
+    fn names_in_the_same_font_or_no_font_dont_clash() {
+        let module = docx(
+            "main.docx",
+            &[
+                &[("set ", plain()), ("x", in_font("Garamond")), (" to 1.", plain())],
+                &[("emit ", plain()), ("x", in_font("Garamond")), ("!", plain())],
+                &[("set y to 2.", plain())],
+                &[("emit ", plain()), ("y", in_font("Comic Sans")), ("!", plain())],
+            ],
+        );
+
+        assert_eq!(check(&module), vec![]);
+    }
+
+    #[test]
+    fn procedures_only_see_their_own_names_and_the_top_level() {
+        let garamond = || in_font("Garamond");
+        let comic = || in_font("Comic Sans");
+        let module = docx(
+            "main.docx",
+            &[
+                &[
+                    ("procedure p takes ", plain()),
+                    ("a", garamond()),
+                    (" does emit ", plain()),
+                    ("a", garamond()),
+                    ("!", plain()),
+                ],
+                &[("set ", plain()), ("a", comic()), (" to 1.", plain())],
+                // `p`'s `a` isn't visible here, so this one is the top level's.
+                &[("procedure q takes does emit ", plain()), ("a", garamond()), ("!", plain())],
+                &[("emit ", plain()), ("a", comic()), ("!", plain())],
+                // Undefined (outside of `p`); that's not for this pass to say.
+                &[("emit ", plain()), ("b", comic()), ("!", plain())],
+                &[("procedure r takes ", plain()), ("b", garamond()), (" does 1.", plain())],
+            ],
+        );
+
+        let errors = check(&module);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert!(matches!(
+            &errors[0],
+            NamespaceError::DifferentFont { name, font: Some(f), defined_in: Some(d), .. }
+                if name == "a" && f == "Garamond" && d == "Comic Sans"
+        ));
+    }
+}
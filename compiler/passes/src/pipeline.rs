@@ -0,0 +1,418 @@
+//! Runs passes over a module in order.
+//!
+//! A [`Pass`] looks at a [`Module`] and returns [`Diagnostic`]s. Passes say
+//! which other passes have to run before them; a [`PassPipeline`] puts its
+//! passes in an order that respects that (and otherwise keeps the order they
+//! were added in).
+//!
+//! Passes can leave results behind for later passes in the [`Analyses`]
+//! (the bordered regions of a module, for example, which the privacy check
+//! reads). A pipeline is a pass itself, so pipelines can be nested.
+
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+use abogado_lex::Span;
+use abogado_parse::ast::Module;
+use thiserror::Error;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Something a pass found.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The spans it points at, with a note for each.
+    pub labels: Vec<(Span, String)>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: &impl Display, labels: Vec<(&Span, &str)>) -> Self {
+        Diagnostic {
+            severity,
+            message: message.to_string(),
+            labels: labels
+                .into_iter()
+                .map(|(span, note)| (span.clone(), note.to_string()))
+                .collect(),
+        }
+    }
+
+    pub fn error(message: &impl Display, labels: Vec<(&Span, &str)>) -> Self {
+        Diagnostic::new(Severity::Error, message, labels)
+    }
+
+    pub fn warning(message: &impl Display, labels: Vec<(&Span, &str)>) -> Self {
+        Diagnostic::new(Severity::Warning, message, labels)
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// What passes leave for the passes after them, one value per type.
+#[derive(Default)]
+pub struct Analyses(HashMap<TypeId, Box<dyn Any>>);
+
+impl Analyses {
+    pub fn insert<T: Any>(&mut self, analysis: T) {
+        self.0.insert(TypeId::of::<T>(), Box::new(analysis));
+    }
+
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.0.get(&TypeId::of::<T>()).and_then(|a| a.downcast_ref())
+    }
+
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.0.get_mut(&TypeId::of::<T>()).and_then(|a| a.downcast_mut())
+    }
+
+    pub fn get_or_default<T: Any + Default>(&mut self) -> &mut T {
+        self.0
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(T::default()))
+            .downcast_mut()
+            .unwrap()
+    }
+}
+
+/// How long each pass took, in the order they ran; pipelines record this in
+/// the [`Analyses`].
+///
+/// Passes in nested pipelines are listed as `outer/inner`.
+#[derive(Debug, Clone, Default)]
+pub struct Timings(pub Vec<(String, Duration)>);
+
+pub trait Pass {
+    /// What the pass is called on the command line and in [`dependencies`].
+    ///
+    /// [`dependencies`]: Pass::dependencies
+    fn name(&self) -> &'static str;
+
+    /// The passes that have to run before this one (in the same pipeline);
+    /// what they leave in the [`Analyses`] is there for this one to read.
+    fn dependencies(&self) -> &'static [&'static str] {
+        &[]
+    }
+
+    /// Whether the pass runs unless it's turned on or off.
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    fn run(&mut self, module: &Module, analyses: &mut Analyses) -> Vec<Diagnostic>;
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PipelineError {
+    #[error("there's no pass named `{0}`")]
+    UnknownPass(String),
+    #[error("`{pass}` needs `{dependency}`, but there's no pass by that name")]
+    MissingDependency {
+        pass: &'static str,
+        dependency: &'static str,
+    },
+    #[error("`{pass}` needs `{dependency}`, which is turned off")]
+    DisabledDependency {
+        pass: &'static str,
+        dependency: &'static str,
+    },
+    #[error("these passes need each other: {}", .0.join(" -> "))]
+    Cycle(Vec<&'static str>),
+}
+
+/// What running a [`PassPipeline`] gives back.
+pub struct Report {
+    pub diagnostics: Vec<Diagnostic>,
+    pub analyses: Analyses,
+}
+
+impl Report {
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    pub fn timings(&self) -> &[(String, Duration)] {
+        self.analyses
+            .get::<Timings>()
+            .map(|t| t.0.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+struct Entry<'p> {
+    pass: Box<dyn Pass + 'p>,
+    enabled: bool,
+}
+
+/// A list of passes, run in the order their dependencies ask for.
+///
+/// ```ignore
+/// let mut pipeline = PassPipeline::new("checks")
+///     .with(FindRegions)
+///     .with(PrivacyCheck);
+/// pipeline.set_enabled("privacy", false)?;
+/// let report = pipeline.run(&module)?;
+/// ```
+pub struct PassPipeline<'p> {
+    name: &'static str,
+    passes: Vec<Entry<'p>>,
+}
+
+impl<'p> PassPipeline<'p> {
+    pub fn new(name: &'static str) -> Self {
+        PassPipeline { name, passes: vec![] }
+    }
+
+    pub fn with(mut self, pass: impl Pass + 'p) -> Self {
+        self.add(pass);
+        self
+    }
+
+    pub fn add(&mut self, pass: impl Pass + 'p) {
+        self.passes.push(Entry {
+            enabled: pass.enabled_by_default(),
+            pass: Box::new(pass),
+        });
+    }
+
+    /// The names of the passes, in the order they were added.
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.passes.iter().map(|e| e.pass.name())
+    }
+
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> Result<(), PipelineError> {
+        let entry = self
+            .passes
+            .iter_mut()
+            .find(|e| e.pass.name() == name)
+            .ok_or_else(|| PipelineError::UnknownPass(name.to_string()))?;
+
+        entry.enabled = enabled;
+        Ok(())
+    }
+
+    /// The enabled passes, in the order they'll run: each after its
+    /// dependencies, and otherwise in the order they were added.
+    fn order(&self) -> Result<Vec<usize>, PipelineError> {
+        fn visit(
+            i: usize,
+            passes: &[Entry<'_>],
+            stack: &mut Vec<usize>,
+            order: &mut Vec<usize>,
+        ) -> Result<(), PipelineError> {
+            if order.contains(&i) {
+                return Ok(());
+            }
+            if let Some(start) = stack.iter().position(|&s| s == i) {
+                let mut cycle = stack[start..]
+                    .iter()
+                    .map(|&s| passes[s].pass.name())
+                    .collect::<Vec<_>>();
+                cycle.push(passes[i].pass.name());
+                return Err(PipelineError::Cycle(cycle));
+            }
+
+            let pass = &passes[i].pass;
+            stack.push(i);
+            for &dependency in pass.dependencies() {
+                let d = passes
+                    .iter()
+                    .position(|e| e.pass.name() == dependency)
+                    .ok_or(PipelineError::MissingDependency { pass: pass.name(), dependency })?;
+                if !passes[d].enabled {
+                    return Err(PipelineError::DisabledDependency {
+                        pass: pass.name(),
+                        dependency,
+                    });
+                }
+
+                visit(d, passes, stack, order)?;
+            }
+            stack.pop();
+
+            order.push(i);
+            Ok(())
+        }
+
+        let mut order = vec![];
+        for (i, entry) in self.passes.iter().enumerate() {
+            if entry.enabled {
+                visit(i, &self.passes, &mut vec![], &mut order)?;
+            }
+        }
+
+        Ok(order)
+    }
+
+    pub fn run(&mut self, module: &Module) -> Result<Report, PipelineError> {
+        let mut analyses = Analyses::default();
+        let diagnostics = self.run_with(module, &mut analyses)?;
+
+        Ok(Report { diagnostics, analyses })
+    }
+
+    fn run_with(
+        &mut self,
+        module: &Module,
+        analyses: &mut Analyses,
+    ) -> Result<Vec<Diagnostic>, PipelineError> {
+        let mut diagnostics = vec![];
+        for i in self.order()? {
+            let pass = &mut self.passes[i].pass;
+
+            let start = Instant::now();
+            diagnostics.extend(pass.run(module, analyses));
+            let elapsed = start.elapsed();
+
+            analyses
+                .get_or_default::<Timings>()
+                .0
+                .push((pass.name().to_string(), elapsed));
+        }
+
+        Ok(diagnostics)
+    }
+}
+
+impl Pass for PassPipeline<'_> {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn run(&mut self, module: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        // The inner passes' timings go in their own list, so that they can be
+        // put under this pipeline's name.
+        let outer = analyses.get_mut::<Timings>().map(std::mem::take);
+
+        let diagnostics = self
+            .run_with(module, analyses)
+            .unwrap_or_else(|e| vec![Diagnostic::error(&e, vec![])]);
+
+        let inner = analyses.get_mut::<Timings>().map(std::mem::take).unwrap_or_default();
+        let timings = analyses.get_or_default::<Timings>();
+        *timings = outer.unwrap_or_default();
+        timings.0.extend(
+            inner
+                .0
+                .into_iter()
+                .map(|(name, time)| (format!("{}/{}", self.name, name), time)),
+        );
+
+        diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The passes that ran, in order.
+    #[derive(Default)]
+    struct Ran(Vec<&'static str>);
+
+    struct Mock {
+        name: &'static str,
+        dependencies: &'static [&'static str],
+        on: bool,
+    }
+
+    impl Pass for Mock {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn dependencies(&self) -> &'static [&'static str] {
+            self.dependencies
+        }
+
+        fn enabled_by_default(&self) -> bool {
+            self.on
+        }
+
+        fn run(&mut self, _: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+            analyses.get_or_default::<Ran>().0.push(self.name);
+            vec![]
+        }
+    }
+
+    fn pass(name: &'static str, dependencies: &'static [&'static str]) -> Mock {
+        Mock { name, dependencies, on: true }
+    }
+
+    fn ran(pipeline: &mut PassPipeline<'_>) -> Result<Vec<&'static str>, PipelineError> {
+        let report = pipeline.run(&Module::default())?;
+        Ok(report.analyses.get::<Ran>().map(|r| r.0.clone()).unwrap_or_default())
+    }
+
+    #[test]
+    fn dependencies_run_first() {
+        let mut pipeline = PassPipeline::new("test")
+            .with(pass("a", &["b"]))
+            .with(pass("c", &[]))
+            .with(pass("b", &[]));
+        assert_eq!(ran(&mut pipeline), Ok(vec!["b", "a", "c"]));
+    }
+
+    #[test]
+    fn cycles_are_errors() {
+        let mut pipeline = PassPipeline::new("test")
+            .with(pass("a", &["b"]))
+            .with(pass("b", &["a"]));
+        assert_eq!(ran(&mut pipeline), Err(PipelineError::Cycle(vec!["a", "b", "a"])));
+    }
+
+    #[test]
+    fn dependencies_have_to_exist_and_be_on() {
+        let mut pipeline = PassPipeline::new("test").with(pass("a", &["z"]));
+        assert_eq!(
+            ran(&mut pipeline),
+            Err(PipelineError::MissingDependency { pass: "a", dependency: "z" }),
+        );
+
+        let mut pipeline = PassPipeline::new("test")
+            .with(pass("a", &["b"]))
+            .with(pass("b", &[]));
+        pipeline.set_enabled("b", false).unwrap();
+        assert_eq!(
+            ran(&mut pipeline),
+            Err(PipelineError::DisabledDependency { pass: "a", dependency: "b" }),
+        );
+    }
+
+    #[test]
+    fn passes_can_be_turned_on_and_off() {
+        let mut pipeline = PassPipeline::new("test")
+            .with(pass("a", &[]))
+            .with(Mock { name: "b", dependencies: &[], on: false });
+        assert_eq!(ran(&mut pipeline), Ok(vec!["a"]));
+
+        pipeline.set_enabled("b", true).unwrap();
+        pipeline.set_enabled("a", false).unwrap();
+        assert_eq!(ran(&mut pipeline), Ok(vec!["b"]));
+
+        assert_eq!(
+            pipeline.set_enabled("nope", true),
+            Err(PipelineError::UnknownPass("nope".to_string())),
+        );
+    }
+
+    #[test]
+    fn nested_pipelines_time_their_passes_under_their_name() {
+        let inner = PassPipeline::new("inner").with(pass("b", &[]));
+        let mut outer = PassPipeline::new("outer").with(pass("a", &[])).with(inner);
+
+        let report = outer.run(&Module::default()).unwrap();
+        let names = report.timings().iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["a", "inner/b", "inner"]);
+        assert_eq!(report.analyses.get::<Ran>().unwrap().0, ["a", "b"]);
+    }
+}
@@ -0,0 +1,184 @@
+//! Checks that private definitions aren't used from outside of their border.
+//!
+//! Putting a border around a definition (or around the statements it's in)
+//! makes it private: it can only be used from inside that same border.
+//! Consecutive bordered statements share a border.
+
+use std::collections::HashMap;
+
+use abogado_lex::Span;
+use abogado_parse::ast::{Ident, Module};
+use thiserror::Error;
+
+pub use abogado_parse::interface::{region_of, regions};
+
+use crate::names::{Names, Occurrence};
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum PrivacyError {
+    #[error("`{name}` is private to its border and can't be used outside of it")]
+    Private {
+        name: Ident,
+        span: Span,
+        /// The bordered region `name` is defined in.
+        border: Span,
+    },
+}
+
+impl PrivacyError {
+    /// The spans this error points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            PrivacyError::Private { span, border, .. } => {
+                vec![(span, "used here"), (border, "private to this border")]
+            }
+        }
+    }
+}
+
+/// Finds uses of private definitions from outside of their regions.
+pub fn check(module: &Module) -> Vec<PrivacyError> {
+    check_names(&Names::of(module), &regions(module))
+}
+
+fn check_names(names: &Names, regions: &[Span]) -> Vec<PrivacyError> {
+    let Names {
+        definitions, uses, ..
+    } = names;
+
+    // For every name, the regions it's private to; `None` if there's a public
+    // definition of the name.
+    let mut private: HashMap<&Ident, Option<Vec<Span>>> = HashMap::new();
+    for Occurrence {
+        name,
+        style,
+        span,
+        statement,
+        ..
+    } in definitions.iter()
+    {
+        let entry = private.entry(name).or_insert_with(|| Some(vec![]));
+        match (region_of(regions, style, span, statement), entry) {
+            (Some(region), Some(list)) => list.push(region),
+            (None, entry) => *entry = None,
+            (Some(_), None) => {}
+        }
+    }
+
+    uses.iter()
+        .filter_map(|usage| {
+            let borders = private.get(&usage.name)?.as_ref()?;
+            if borders.iter().any(|b| b.contains(&usage.span)) {
+                return None;
+            }
+
+            Some(PrivacyError::Private {
+                name: usage.name.clone(),
+                span: usage.span.clone(),
+                border: borders.first()?.clone(),
+            })
+        })
+        .collect()
+}
+
+/// The module's [`regions`], left in the [`Analyses`] by [`FindRegions`].
+#[derive(Debug, Clone)]
+pub struct Regions(pub Vec<Span>);
+
+pub struct FindRegions;
+
+impl Pass for FindRegions {
+    fn name(&self) -> &'static str {
+        "regions"
+    }
+
+    fn run(&mut self, module: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        analyses.insert(Regions(regions(module)));
+        vec![]
+    }
+}
+
+pub struct PrivacyCheck;
+
+impl Pass for PrivacyCheck {
+    fn name(&self) -> &'static str {
+        "privacy"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["names", "regions"]
+    }
+
+    fn run(&mut self, _: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        let names = analyses.get::<Names>().expect("`names` runs first");
+        let Regions(regions) = analyses.get::<Regions>().expect("`regions` runs first");
+        check_names(names, regions).iter().map(|e| Diagnostic::error(e, e.labels())).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{bordered, docx, plain};
+
+    #[test]
+    fn bordered_definitions_are_private_to_their_border() {
+        let module = docx(
+            "main.docx",
+            &[
+                &[("set secret to 1.", bordered())],
+                // Consecutive bordered statements share a border:
+                &[("emit secret!", bordered())],
+                &[("emit secret!", plain())],
+            ],
+        );
+
+        let errors = check(&module);
+        assert!(matches!(
+            &errors[..],
+            [PrivacyError::Private { name, span, border }]
+                if name == "secret"
+                    && span.inner == (35..41)
+                    && border.contains(&Span { inner: 17..29 })
+        ));
+        assert_eq!(
+            errors[0].to_string(),
+            "`secret` is private to its border and can't be used outside of it"
+        );
+    }
+
+    #[test]
+    fn bordered_names_are_private_to_their_statement() {
+        let module = docx(
+            "main.docx",
+            &[
+                &[("set ", plain()), ("secret", bordered()), (" to 1.", plain())],
+                &[("emit secret!", plain())],
+            ],
+        );
+
+        let errors = check(&module);
+        assert!(matches!(
+            &errors[..],
+            [PrivacyError::Private { border, .. }] if border.inner == (0..16)
+        ));
+    }
+
+    #[test]
+    fn uses_inside_the_border_or_of_public_names_are_fine() {
+        let module = docx(
+            "main.docx",
+            &[
+                &[("set secret to 1.", bordered())],
+                &[("emit secret!", bordered())],
+                &[("emit 2!", plain())],
+                &[("set shared to 1.", bordered())],
+                &[("set shared to 2.", plain())],
+                &[("emit shared!", plain())],
+            ],
+        );
+
+        assert_eq!(check(&module), vec![]);
+    }
+}
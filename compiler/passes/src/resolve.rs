@@ -0,0 +1,576 @@
+//! Finds names that aren't defined, procedures that are defined twice and
+//! calls with the wrong number of arguments.
+//!
+//! Scopes follow what happens when a program runs:
+//!   - procedures (wherever they're written) and the procedures a module
+//!     imports are visible everywhere in it
+//!   - a call, each turn of a `for` loop and each `when` arm get a scope of
+//!     their own, with their parameters or bindings and the variables that
+//!     are first set in them
+//!   - blocks don't: what's set in a block is still there after it
+//!
+//! Named procedures only see their parameters and the top level; anonymous
+//! ones also see the variables around them.
+//!
+//! Order doesn't matter within a scope: a loop can set a variable on one turn
+//! and use it on the next, so a variable set anywhere in a scope counts as
+//! defined everywhere in it.
+//!
+//! Names are matched regardless of their font; [`namespaces`] checks that
+//! they're written in the right one.
+//!
+//! [`namespaces`]: crate::namespaces
+
+use std::collections::HashMap;
+
+use abogado_lex::{spanned::S, Span};
+use abogado_parse::ast::{
+    Arm, Call, Expr, For, Get, Ident, Import, Lambda, Module, Pattern, Procedure, Statement, Update,
+};
+use abogado_parse::interface::DefinitionKind;
+use abogado_parse::visit::{self, Visitor};
+use thiserror::Error;
+
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+fn suggest(suggestion: &Option<Ident>) -> String {
+    match suggestion {
+        Some(s) => format!("; did you mean `{}`?", s),
+        None => String::new(),
+    }
+}
+
+fn arguments(n: &usize) -> String {
+    match n {
+        0 => "no arguments".to_string(),
+        1 => "1 argument".to_string(),
+        n => format!("{} arguments", n),
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum ResolveError {
+    #[error("`{name}` isn't defined{}", suggest(.suggestion))]
+    Undefined {
+        name: Ident,
+        span: Span,
+        /// A name that is defined and is spelled almost the same.
+        suggestion: Option<Ident>,
+    },
+    #[error("`{name}` takes {} but it's given {}", arguments(.expected), arguments(.found))]
+    Arity {
+        name: Ident,
+        span: Span,
+        expected: usize,
+        found: usize,
+        /// Where the procedure is defined (or imported).
+        definition: Span,
+    },
+    #[error("`{name}` is already defined")]
+    Duplicate { name: Ident, span: Span, first: Span },
+}
+
+impl ResolveError {
+    /// The spans this error points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            ResolveError::Undefined { span, .. } => vec![(span, "not defined")],
+            ResolveError::Arity {
+                span, definition, ..
+            } => vec![(span, "this call"), (definition, "defined here")],
+            ResolveError::Duplicate { span, first, .. } => {
+                vec![(span, "defined again here"), (first, "first defined here")]
+            }
+        }
+    }
+}
+
+/// How many characters have to be added, removed or changed to turn `a` into
+/// `b`.
+fn distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut row = (0..=b.len()).collect::<Vec<_>>();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, &cb) in b.iter().enumerate() {
+            let changed = diagonal + (ca != cb) as usize;
+            diagonal = row[j + 1];
+            row[j + 1] = changed.min(diagonal + 1).min(row[j] + 1);
+        }
+    }
+
+    row[b.len()]
+}
+
+/// A procedure that can be called by name.
+#[derive(Debug, Clone)]
+struct Callable {
+    font: Option<String>,
+    /// Where it's defined or imported.
+    span: Span,
+    /// How many parameters it has, if that's known (it isn't for procedures
+    /// from modules that weren't loaded).
+    arity: Option<usize>,
+}
+
+/// The procedures and imports in a module, wherever they're written.
+#[derive(Default)]
+struct Hoisted {
+    procedures: Vec<(S<Ident>, usize)>,
+    imports: Vec<Import>,
+}
+
+impl Visitor for Hoisted {
+    fn visit_statement(&mut self, s: &S<Statement>) {
+        match &s.inner {
+            Statement::Procedure(p) => self.procedures.push((p.name.clone(), p.args.len())),
+            Statement::Import(i) => self.imports.push(i.clone()),
+            _ => {}
+        }
+
+        visit::visit_statement(self, s);
+    }
+}
+
+/// The variables that are set in a scope, not counting the scopes inside it.
+#[derive(Default)]
+struct Assigned(Vec<S<Ident>>);
+
+impl Visitor for Assigned {
+    fn visit_statement(&mut self, s: &S<Statement>) {
+        match &s.inner {
+            Statement::For(For { list, .. }) => self.visit_expr(list),
+            Statement::Procedure(_) => {}
+            _ => visit::visit_statement(self, s),
+        }
+    }
+
+    fn visit_expr(&mut self, e: &S<Expr>) {
+        match &e.inner {
+            Expr::Lambda(_) => {}
+            Expr::Assign(a) => {
+                self.0.push(a.name.clone());
+                self.visit_expr(&a.to);
+            }
+            _ => visit::visit_expr(self, e),
+        }
+    }
+
+    fn visit_arm(&mut self, _: &S<Arm>) {}
+}
+
+struct Resolver<'m> {
+    /// The modules imports refer to, keyed by source.
+    modules: &'m HashMap<String, Module>,
+    procedures: HashMap<Ident, Vec<Callable>>,
+    /// Innermost last; each maps a variable to where it's first defined.
+    scopes: Vec<HashMap<Ident, Span>>,
+    /// Whether everything is imported from a module that wasn't loaded; any
+    /// name could be from there.
+    partial: bool,
+    errors: Vec<ResolveError>,
+}
+
+impl<'m> Resolver<'m> {
+    fn hoist(&mut self, module: &Module) {
+        let mut hoisted = Hoisted::default();
+        hoisted.visit_module(module);
+
+        for (name, arity) in hoisted.procedures {
+            self.define(&name, Some(arity), true);
+        }
+        for import in hoisted.imports {
+            self.import(&import);
+        }
+    }
+
+    /// Makes `name` callable; only procedures that are named on their own
+    /// (not imported along with the rest of a module) can clash.
+    fn define(&mut self, name: &S<Ident>, arity: Option<usize>, named: bool) {
+        let font = name.style.font().map(str::to_string);
+        let defined = self.procedures.entry(name.inner.clone()).or_default();
+
+        match defined.iter().find(|c| c.font == font) {
+            Some(first) if named => self.errors.push(ResolveError::Duplicate {
+                name: name.inner.clone(),
+                span: name.span.clone(),
+                first: first.span.clone(),
+            }),
+            Some(_) => {}
+            None => defined.push(Callable {
+                font,
+                span: name.span.clone(),
+                arity,
+            }),
+        }
+    }
+
+    fn import(&mut self, Import { names, source }: &Import) {
+        let modules = self.modules;
+        let module = modules.get(&source.inner);
+        let arity = |name: &Ident| {
+            module
+                .and_then(|m| m.procedures.iter().find(|p| &p.name.inner == name))
+                .map(|p| p.args.len())
+        };
+
+        match (names, module) {
+            (Some(names), _) => {
+                for name in names {
+                    self.define(name, arity(&name.inner), true);
+                }
+            }
+            (None, Some(module)) => {
+                let exported = module
+                    .interface()
+                    .exports()
+                    .filter(|d| d.kind == DefinitionKind::Procedure)
+                    .map(|d| S {
+                        span: source.span.clone(),
+                        ..d.name.clone()
+                    })
+                    .collect::<Vec<_>>();
+                for name in exported {
+                    let arity = arity(&name.inner);
+                    self.define(&name, arity, false);
+                }
+            }
+            (None, None) => self.partial = true,
+        }
+    }
+
+    fn is_variable(&self, name: &Ident) -> bool {
+        self.scopes.iter().any(|s| s.contains_key(name))
+    }
+
+    /// The procedure a call to `name` runs: the one in the same font or the
+    /// one without a font, if there is one.
+    fn procedure(&self, name: &S<Ident>) -> Option<&Callable> {
+        let defined = self.procedures.get(&name.inner)?;
+        let font = name.style.font();
+
+        defined
+            .iter()
+            .find(|c| c.font.as_deref() == font)
+            .or_else(|| defined.iter().find(|c| c.font.is_none()))
+            .or_else(|| defined.first())
+    }
+
+    /// The closest name to `name` that's in scope, if any is close enough.
+    fn suggestion(&self, name: &str) -> Option<Ident> {
+        let limit = std::cmp::max(1, name.chars().count() / 3);
+
+        self.scopes
+            .iter()
+            .flat_map(|s| s.keys())
+            .chain(self.procedures.keys())
+            .map(|candidate| (distance(name, candidate), candidate))
+            .filter(|&(d, _)| d <= limit)
+            .min()
+            .map(|(_, candidate)| candidate.clone())
+    }
+
+    fn undefined(&mut self, name: &Ident, span: &Span) {
+        if self.partial {
+            return;
+        }
+
+        let suggestion = self.suggestion(name);
+        self.errors.push(ResolveError::Undefined {
+            name: name.clone(),
+            span: span.clone(),
+            suggestion,
+        });
+    }
+
+    /// Checks `body` in a new scope that has `bindings` in it.
+    fn scoped<'e>(
+        &mut self,
+        bindings: impl IntoIterator<Item = &'e S<Ident>>,
+        body: &[&'e S<Expr>],
+    ) {
+        let mut scope: HashMap<Ident, Span> = HashMap::new();
+        for b in bindings {
+            match scope.get(&b.inner) {
+                Some(first) => self.errors.push(ResolveError::Duplicate {
+                    name: b.inner.clone(),
+                    span: b.span.clone(),
+                    first: first.clone(),
+                }),
+                None => {
+                    scope.insert(b.inner.clone(), b.span.clone());
+                }
+            }
+        }
+
+        let mut assigned = Assigned::default();
+        body.iter().for_each(|e| assigned.visit_expr(e));
+        for name in assigned.0 {
+            scope.entry(name.inner).or_insert(name.span);
+        }
+
+        self.scopes.push(scope);
+        body.iter().for_each(|e| self.visit_expr(e));
+        self.scopes.pop();
+    }
+
+    /// Ledger keys can be bare names; those aren't variables.
+    fn visit_key(&mut self, key: &S<Expr>) {
+        if !matches!(key.inner, Expr::Ident(_)) {
+            self.visit_expr(key);
+        }
+    }
+}
+
+impl Visitor for Resolver<'_> {
+    fn visit_statement(&mut self, s: &S<Statement>) {
+        match &s.inner {
+            Statement::For(For { name, list, body }) => {
+                self.visit_expr(list);
+                self.scoped(Some(name), &[&**body]);
+            }
+            Statement::Procedure(Procedure { args, body, .. }) => {
+                let locals = self.scopes.split_off(1);
+                self.scoped(args, &[&**body]);
+                self.scopes.extend(locals);
+            }
+            _ => visit::visit_statement(self, s),
+        }
+    }
+
+    fn visit_expr(&mut self, e: &S<Expr>) {
+        match &e.inner {
+            Expr::Ident(name) => {
+                if !self.is_variable(name) && !self.procedures.contains_key(name) {
+                    self.undefined(name, &e.span);
+                }
+            }
+            Expr::Call(Call { name, args }) => {
+                // Variables that hold procedures shadow named procedures.
+                if !self.is_variable(&name.inner) {
+                    match self.procedure(name).cloned() {
+                        Some(Callable {
+                            span,
+                            arity: Some(expected),
+                            ..
+                        }) if expected != args.len() => {
+                            self.errors.push(ResolveError::Arity {
+                                name: name.inner.clone(),
+                                span: name.span.clone(),
+                                expected,
+                                found: args.len(),
+                                definition: span,
+                            })
+                        }
+                        Some(_) => {}
+                        None => self.undefined(&name.inner, &name.span),
+                    }
+                }
+
+                args.iter().for_each(|a| self.visit_expr(a));
+            }
+            Expr::Lambda(Lambda { args, body }) => self.scoped(args, &[&**body]),
+            Expr::Get(Get { index, from }) => {
+                self.visit_key(index);
+                self.visit_expr(from);
+            }
+            Expr::Update(Update { key, target, to }) => {
+                self.visit_key(key);
+                if !self.is_variable(&target.inner) {
+                    self.undefined(&target.inner, &target.span);
+                }
+                self.visit_expr(to);
+            }
+            _ => visit::visit_expr(self, e),
+        }
+    }
+
+    fn visit_arm(&mut self, arm: &S<Arm>) {
+        let Arm {
+            pattern,
+            guard,
+            body,
+        } = &arm.inner;
+        let bindings = match &pattern.inner {
+            Pattern::Bind(name) => vec![name],
+            Pattern::ListStartingWith { head, rest } => {
+                Some(head).into_iter().chain(rest).collect()
+            }
+            _ => vec![],
+        };
+
+        let mut body = vec![body];
+        body.extend(guard);
+        self.scoped(bindings, &body);
+    }
+}
+
+/// Resolves every name in `module`; `modules` are the modules it imports,
+/// keyed by source.
+pub fn check(module: &Module, modules: &HashMap<String, Module>) -> Vec<ResolveError> {
+    let mut resolver = Resolver {
+        modules,
+        procedures: HashMap::new(),
+        scopes: vec![],
+        partial: false,
+        errors: vec![],
+    };
+    resolver.hoist(module);
+
+    let mut top = Assigned::default();
+    top.visit_module(module);
+    let mut globals = HashMap::new();
+    for name in top.0 {
+        globals.entry(name.inner).or_insert(name.span);
+    }
+    resolver.scopes.push(globals);
+
+    resolver.visit_module(module);
+    resolver.errors
+}
+
+/// [`check`]s a module against the modules it imports.
+pub struct ResolveCheck<'m> {
+    /// Keyed by source, like the `import`s in the checked module.
+    pub modules: &'m HashMap<String, Module>,
+}
+
+impl Pass for ResolveCheck<'_> {
+    fn name(&self) -> &'static str {
+        "resolve"
+    }
+
+    fn run(&mut self, module: &Module, _: &mut Analyses) -> Vec<Diagnostic> {
+        check(module, self.modules)
+            .iter()
+            .map(|e| Diagnostic::error(e, e.labels()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{cado, docx, plain, underlined};
+
+    fn resolve(source: &str) -> Vec<ResolveError> {
+        check(&cado("main.cado", source), &HashMap::new())
+    }
+
+    /// A module that exports `greet`, which takes one argument.
+    fn lib() -> HashMap<String, Module> {
+        let lib = docx(
+            "lib.docx",
+            &[&[
+                ("procedure ", plain()),
+                ("greet", underlined()),
+                (" takes name does emit name!", plain()),
+            ]],
+        );
+
+        vec![("lib.docx".to_string(), lib)].into_iter().collect()
+    }
+
+    #[test]
+    fn undefined_names() {
+        let errors = resolve("set total to 1.\nemit count!");
+        assert!(matches!(
+            &errors[..],
+            [ResolveError::Undefined { name, suggestion: None, .. }] if name == "count"
+        ));
+        assert_eq!(errors[0].to_string(), "`count` isn't defined");
+    }
+
+    #[test]
+    fn misspelled_names_get_a_suggestion() {
+        let errors = resolve("set total to 1.\nemit totl!");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].to_string(), "`totl` isn't defined; did you mean `total`?");
+
+        let errors = resolve("procedure greet takes does 1.\ndo gret using.");
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].to_string(), "`gret` isn't defined; did you mean `greet`?");
+    }
+
+    #[test]
+    fn calls_with_the_wrong_number_of_arguments() {
+        let errors = resolve("procedure add takes a, b does a + b.\ndo add using 1.");
+        assert!(matches!(
+            &errors[..],
+            [ResolveError::Arity { name, expected: 2, found: 1, .. }] if name == "add"
+        ));
+        assert_eq!(errors[0].to_string(), "`add` takes 2 arguments but it's given 1 argument");
+
+        let fine = resolve("procedure add takes a, b does a + b.\ndo add using 1, 2.");
+        assert!(fine.is_empty(), "{:?}", fine);
+    }
+
+    #[test]
+    fn procedures_defined_twice() {
+        let errors = resolve("procedure f takes does 1.\nprocedure f takes does 2.");
+        match &errors[..] {
+            [ResolveError::Duplicate { name, span, first }] => {
+                assert_eq!(name, "f");
+                assert!(first.inner.start < span.inner.start);
+            }
+            other => panic!("expected one duplicate, got {:?}", other),
+        }
+        assert_eq!(errors[0].to_string(), "`f` is already defined");
+    }
+
+    #[test]
+    fn parameters_named_twice() {
+        let errors = resolve("procedure f takes a, a does a.");
+        assert!(matches!(
+            &errors[..],
+            [ResolveError::Duplicate { name, .. }] if name == "a"
+        ));
+
+        let errors = resolve("set g to a procedure taking x, x that does x.");
+        assert!(matches!(
+            &errors[..],
+            [ResolveError::Duplicate { name, .. }] if name == "x"
+        ));
+    }
+
+    #[test]
+    fn imported_names_clash_with_local_procedures() {
+        let main = cado(
+            "main.cado",
+            "import greet from \"lib.docx\".\nprocedure greet takes does 1.",
+        );
+        let errors = check(&main, &lib());
+        match &errors[..] {
+            [ResolveError::Duplicate { name, span, first }] => {
+                assert_eq!(name, "greet");
+                // The procedure is defined first, wherever it's written.
+                assert!(span.inner.start < first.inner.start);
+            }
+            other => panic!("expected one duplicate, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn local_procedures_shadow_whole_module_imports() {
+        let main = cado(
+            "main.cado",
+            "import \"lib.docx\".\nprocedure greet takes does 1.\ndo greet using.",
+        );
+        assert!(check(&main, &lib()).is_empty());
+
+        // Without one, calls go to the import (and are checked against it).
+        let main = cado("main.cado", "import \"lib.docx\".\ndo greet using.");
+        let errors = check(&main, &lib());
+        assert!(matches!(
+            &errors[..],
+            [ResolveError::Arity { name, expected: 1, found: 0, .. }] if name == "greet"
+        ));
+    }
+
+    #[test]
+    fn anything_could_come_from_a_module_that_isnt_loaded() {
+        assert!(resolve("import \"other.docx\".\nemit anything!").is_empty());
+    }
+}
@@ -0,0 +1,58 @@
+//! Builds modules for tests: from `.cado` source or, when styles matter, from
+//! a document made of styled runs.
+//!
+//! Other crates' tests can use these too, with the `test-utils` feature.
+
+use abogado_parse::ast::Module;
+use docx_rs::{Docx, Paragraph, Run, RunFonts, RunProperty, TextBorder, Underline};
+
+/// Parses `source`; it has to parse without errors.
+pub fn cado(name: &str, source: &str) -> Module {
+    let (tokens, source) = abogado_lex::lex_cado(source.to_string());
+    let (module, errors) = abogado_parse::parse(name, tokens.unwrap(), &source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    module
+}
+
+/// Lexes and parses a document with a paragraph for each line; each line is
+/// a list of runs. It has to parse without errors.
+pub fn docx(name: &str, lines: &[&[(&str, RunProperty)]]) -> Module {
+    let doc = lines.iter().fold(Docx::new(), |doc, runs| {
+        let paragraph = runs.iter().fold(Paragraph::new(), |p, (text, prop)| {
+            let mut run = Run::new().add_text(*text);
+            run.run_property = prop.clone();
+            p.add_run(run)
+        });
+        doc.add_paragraph(paragraph)
+    });
+
+    let (tokens, source) = abogado_lex::lex_docx(&doc);
+    let (module, errors) = abogado_parse::parse_docx(name, tokens.unwrap(), &source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    module
+}
+
+pub fn plain() -> RunProperty {
+    RunProperty::new()
+}
+
+pub fn underlined() -> RunProperty {
+    RunProperty {
+        underline: Some(Underline::new("single")),
+        ..RunProperty::new()
+    }
+}
+
+pub fn bordered() -> RunProperty {
+    RunProperty {
+        text_border: Some(TextBorder::new()),
+        ..RunProperty::new()
+    }
+}
+
+pub fn in_font(font: &str) -> RunProperty {
+    RunProperty {
+        fonts: Some(RunFonts::new().ascii(font)),
+        ..RunProperty::new()
+    }
+}
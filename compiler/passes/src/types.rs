@@ -0,0 +1,1082 @@
+//! Infers the types of values and finds operations that can never succeed:
+//! subtracting lists, indexing a number, looping over a procedure, ...
+//!
+//! This is Hindley–Milner inference, loosened for a language whose values
+//! don't have to have one type:
+//!   - only operations that fail when they're given the wrong kind of value
+//!     (arithmetic, indexing, loops, calls) constrain their operands, and only
+//!     they report errors
+//!   - a variable that's set to different kinds of values, a list with
+//!     different kinds of items and a conditional whose branches give back
+//!     different kinds of values are [`Type::Any`], which goes with anything
+//!   - patterns don't constrain what they match (a `when` can pick between
+//!     kinds of values)
+//!
+//! Procedures are inferred the first time they're called (or at the end, if
+//! they never are) and their signatures are generalized, so a procedure that
+//! works on any value can be given a number in one place and a list in
+//! another. Procedures only see the types of top-level variables that are
+//! completely known.
+//!
+//! Procedures from other modules aren't looked into; calls to them give back
+//! [`Type::Any`].
+//!
+//! This works on the module's [`Lowered`] program; what it finds is keyed by
+//! the ids of the nodes there.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+
+use abogado_lex::{spanned::S, Op, Span};
+use abogado_parse::ast::arena::{
+    Arena, Expr, ExprId, Fragment, Lowered, SideTable, Statement, StatementId,
+};
+use abogado_parse::ast::{Ident, Module, Pattern, UnaryOperator};
+use thiserror::Error;
+
+use crate::pipeline::{Analyses, Diagnostic, Pass};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Num,
+    String,
+    List(Box<Type>),
+    Ledger,
+    /// The parameters' types and the type of what it gives back.
+    Procedure(Vec<Type>, Box<Type>),
+    /// A type that isn't known (yet); in a signature, one that can be
+    /// anything as long as it's the same everywhere it's used.
+    Var(usize),
+    /// A value that can be more than one kind of thing.
+    Any,
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Num => write!(f, "number"),
+            Type::String => write!(f, "string"),
+            Type::List(item) => write!(f, "[{}]", item),
+            Type::Ledger => write!(f, "ledger"),
+            Type::Procedure(params, ret) => write!(
+                f,
+                "({}) -> {}",
+                params.iter().map(Type::to_string).collect::<Vec<_>>().join(", "),
+                ret
+            ),
+            Type::Var(v) if *v < 26 => write!(f, "'{}", (b'a' + *v as u8) as char),
+            Type::Var(v) => write!(f, "'{}{}", (b'a' + (v % 26) as u8) as char, v / 26),
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
+impl Type {
+    fn vars(&self, out: &mut Vec<usize>) {
+        match self {
+            Type::Var(v) if !out.contains(v) => out.push(*v),
+            Type::List(item) => item.vars(out),
+            Type::Procedure(params, ret) => {
+                params.iter().for_each(|p| p.vars(out));
+                ret.vars(out);
+            }
+            _ => {}
+        }
+    }
+
+    fn substitute(&self, vars: &HashMap<usize, Type>) -> Type {
+        match self {
+            Type::Var(v) => vars.get(v).cloned().unwrap_or(Type::Var(*v)),
+            Type::List(item) => Type::List(Box::new(item.substitute(vars))),
+            Type::Procedure(params, ret) => Type::Procedure(
+                params.iter().map(|p| p.substitute(vars)).collect(),
+                Box::new(ret.substitute(vars)),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    /// The same type, with its variables numbered from `'a` in the order they
+    /// appear.
+    fn normalized(&self) -> Type {
+        let mut vars = vec![];
+        self.vars(&mut vars);
+        let renamed = vars.into_iter().enumerate().map(|(i, v)| (v, Type::Var(i))).collect();
+        self.substitute(&renamed)
+    }
+
+    /// What a value of this type is, as it'd be said in a message.
+    pub fn describe(&self) -> String {
+        fn plural(t: &Type) -> Option<&'static str> {
+            Some(match t {
+                Type::Num => "numbers",
+                Type::String => "strings",
+                Type::List(_) => "lists",
+                Type::Ledger => "ledgers",
+                Type::Procedure(..) => "procedures",
+                Type::Var(_) | Type::Any => return None,
+            })
+        }
+
+        match self {
+            Type::Num => "a number".to_string(),
+            Type::String => "a string".to_string(),
+            Type::List(item) => match plural(item) {
+                Some(items) => format!("a list of {}", items),
+                None => "a list".to_string(),
+            },
+            Type::Ledger => "a ledger".to_string(),
+            Type::Procedure(..) => "a procedure".to_string(),
+            Type::Var(_) | Type::Any => "a value".to_string(),
+        }
+    }
+}
+
+fn operands(op: &Op, lhs: &str, rhs: &str) -> String {
+    match op {
+        Op::Add => format!("can't add {} to {}", rhs, lhs),
+        Op::Sub => format!("can't subtract {} from {}", rhs, lhs),
+        Op::Mul => format!("can't multiply {} by {}", lhs, rhs),
+        Op::Div => format!("can't divide {} by {}", lhs, rhs),
+        _ => format!("can't compare {} and {}", lhs, rhs),
+    }
+}
+
+/// The kinds of values in these errors are [described](Type::describe).
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum TypeError {
+    #[error("{}", operands(.op, .lhs, .rhs))]
+    Operands {
+        op: Op,
+        lhs: String,
+        rhs: String,
+        lhs_span: Span,
+        rhs_span: Span,
+    },
+    #[error("can't negate {found}")]
+    Negate { found: String, span: Span },
+    #[error("can't get an item from {from} using {index}")]
+    Get {
+        from: String,
+        index: String,
+        from_span: Span,
+        index_span: Span,
+    },
+    #[error("can't set an item in {target} using {key}")]
+    Update {
+        target: String,
+        key: String,
+        target_span: Span,
+        key_span: Span,
+    },
+    #[error("can't loop over {found}")]
+    Loop { found: String, span: Span },
+    #[error("`{name}` is {found}, not a procedure")]
+    NotProcedure { name: Ident, found: String, span: Span },
+    #[error("`{name}` needs {expected} here, but it's given {found}")]
+    Argument {
+        name: Ident,
+        expected: String,
+        found: String,
+        span: Span,
+        /// The parameter, if it's a named procedure's.
+        parameter: Option<Span>,
+    },
+}
+
+impl TypeError {
+    /// The spans this error points at, with a note for each.
+    pub fn labels(&self) -> Vec<(&Span, &'static str)> {
+        match self {
+            TypeError::Operands {
+                lhs_span, rhs_span, ..
+            } => vec![(lhs_span, "the left side"), (rhs_span, "the right side")],
+            TypeError::Negate { span, .. } | TypeError::Loop { span, .. } => vec![(span, "this")],
+            TypeError::Get {
+                from_span,
+                index_span,
+                ..
+            } => vec![(from_span, "getting from this"), (index_span, "using this")],
+            TypeError::Update {
+                target_span,
+                key_span,
+                ..
+            } => vec![(target_span, "setting in this"), (key_span, "using this")],
+            TypeError::NotProcedure { span, .. } => vec![(span, "called here")],
+            TypeError::Argument {
+                span, parameter, ..
+            } => {
+                let mut labels = vec![(span, "this argument")];
+                labels.extend(parameter.iter().map(|p| (p, "the parameter")));
+                labels
+            }
+        }
+    }
+}
+
+/// What [`infer`] found out about a module, keyed by the nodes of its
+/// [`Lowered`] program.
+#[derive(Debug, Clone, Default)]
+pub struct Types {
+    /// The type of each expression.
+    pub exprs: SideTable<Expr, Type>,
+    /// The signatures of the procedures the module defines, by the definition
+    /// that counts (the last one, if a name is defined more than once).
+    pub procedures: SideTable<Statement, Type>,
+    /// The types of the variables set at the top level, by the `set` that sets
+    /// each one first.
+    pub variables: SideTable<Expr, Type>,
+}
+
+impl Types {
+    /// The signatures and then the variables, as `// name: type` lines;
+    /// `arena` is the one the types are from.
+    pub fn listing<'t>(&'t self, arena: &'t Arena) -> Listing<'t> {
+        Listing { types: self, arena }
+    }
+}
+
+/// See [`Types::listing`].
+pub struct Listing<'t> {
+    types: &'t Types,
+    arena: &'t Arena,
+}
+
+impl Display for Listing<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arena = self.arena;
+        let procedures = self.types.procedures.iter().filter_map(|(id, t)| match &arena[id] {
+            Statement::Procedure { name, .. } => Some((name, t)),
+            _ => None,
+        });
+        let variables = self.types.variables.iter().filter_map(|(id, t)| match &arena[id] {
+            Expr::Assign { name, .. } => Some((name, t)),
+            _ => None,
+        });
+
+        for (name, t) in procedures.chain(variables) {
+            writeln!(f, "// {}: {}", name.inner, t)?;
+        }
+
+        Ok(())
+    }
+}
+
+enum State {
+    NotYet,
+    /// Calls in the procedure's own body (or in the bodies of procedures it
+    /// calls) use this type as it is, without generalizing it.
+    Inferring(Type),
+    Done(Type),
+}
+
+struct Signature {
+    /// The `procedure` statement.
+    definition: StatementId,
+    state: State,
+}
+
+struct Infer<'m> {
+    arena: &'m Arena,
+
+    /// What each type variable has turned out to be.
+    bindings: Vec<Option<Type>>,
+    /// Variables that stand for "nothing yet" (the items of an empty list,
+    /// say) rather than for something unknown; joining fills them in.
+    holes: HashSet<usize>,
+
+    /// Innermost last; the first is the top level.
+    scopes: Vec<HashMap<Ident, Type>>,
+    /// The scopes before this one belong to the top level; inside a named
+    /// procedure, that's all of them but its own.
+    barrier: usize,
+    /// The types given back by `give back`, for each procedure being
+    /// inferred.
+    returns: Vec<Vec<Type>>,
+
+    procedures: HashMap<Ident, Signature>,
+    /// The type of each expression when it was inferred; its variables are
+    /// filled in at the end.
+    exprs: SideTable<Expr, Type>,
+
+    errors: Vec<TypeError>,
+}
+
+impl<'m> Infer<'m> {
+    fn fresh(&mut self) -> Type {
+        self.bindings.push(None);
+        Type::Var(self.bindings.len() - 1)
+    }
+
+    fn hole(&mut self) -> Type {
+        let hole = self.fresh();
+        if let Type::Var(v) = hole {
+            self.holes.insert(v);
+        }
+        hole
+    }
+
+    /// Follows `t`'s bindings until it's not a bound variable.
+    fn resolve(&self, t: &Type) -> Type {
+        match t {
+            Type::Var(v) => match &self.bindings[*v] {
+                Some(bound) => self.resolve(bound),
+                None => t.clone(),
+            },
+            other => other.clone(),
+        }
+    }
+
+    /// `t` with all of its bound variables replaced.
+    fn apply(&self, t: &Type) -> Type {
+        match self.resolve(t) {
+            Type::List(item) => Type::List(Box::new(self.apply(&item))),
+            Type::Procedure(params, ret) => Type::Procedure(
+                params.iter().map(|p| self.apply(p)).collect(),
+                Box::new(self.apply(&ret)),
+            ),
+            other => other,
+        }
+    }
+
+    /// `t` if everything about it is known and [`Type::Any`] otherwise.
+    fn known(&self, t: &Type) -> Type {
+        let t = self.apply(t);
+        let mut vars = vec![];
+        t.vars(&mut vars);
+        if vars.is_empty() {
+            t
+        } else {
+            Type::Any
+        }
+    }
+
+    fn instantiate(&mut self, t: &Type) -> Type {
+        let t = self.apply(t);
+        let mut vars = vec![];
+        t.vars(&mut vars);
+        let fresh = vars.into_iter().map(|v| (v, self.fresh())).collect();
+        t.substitute(&fresh)
+    }
+
+    fn bind(&mut self, v: usize, t: Type) -> Result<(), ()> {
+        let mut vars = vec![];
+        self.apply(&t).vars(&mut vars);
+        if vars.contains(&v) {
+            return Err(());
+        }
+
+        self.bindings[v] = Some(t);
+        Ok(())
+    }
+
+    /// Makes `a` and `b` the same type if they can be; nothing changes if
+    /// they can't.
+    fn unify(&mut self, a: &Type, b: &Type) -> Result<(), ()> {
+        let before = self.bindings.clone();
+        let unified = self.unify_inner(a, b);
+        if unified.is_err() {
+            self.bindings = before;
+        }
+        unified
+    }
+
+    fn unify_inner(&mut self, a: &Type, b: &Type) -> Result<(), ()> {
+        match (self.resolve(a), self.resolve(b)) {
+            (Type::Any, _) | (_, Type::Any) => Ok(()),
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(v), t) | (t, Type::Var(v)) => self.bind(v, t),
+            (Type::List(x), Type::List(y)) => self.unify_inner(&x, &y),
+            (Type::Procedure(p, r), Type::Procedure(q, s)) => {
+                // The wrong number of arguments is the `resolve` pass's to
+                // report.
+                if p.len() != q.len() {
+                    return Ok(());
+                }
+
+                for (p, q) in p.iter().zip(&q) {
+                    self.unify_inner(p, q)?;
+                }
+                self.unify_inner(&r, &s)
+            }
+            (x, y) if x == y => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    /// A type for values that are either an `a` or a `b`.
+    fn join(&mut self, a: &Type, b: &Type) -> Type {
+        let (a, b) = (self.apply(a), self.apply(b));
+        if a == b {
+            return a;
+        }
+
+        match (&a, &b) {
+            (Type::Var(v), t) | (t, Type::Var(v)) if self.holes.contains(v) => {
+                match self.bind(*v, t.clone()) {
+                    Ok(()) => t.clone(),
+                    Err(()) => Type::Any,
+                }
+            }
+            (Type::List(x), Type::List(y)) => Type::List(Box::new(self.join(x, y))),
+            (Type::Procedure(..), Type::Procedure(..)) if self.unify(&a, &b).is_ok() => {
+                self.apply(&a)
+            }
+            _ => Type::Any,
+        }
+    }
+
+    fn lookup(&self, name: &Ident) -> Option<Type> {
+        let (i, t) = self
+            .scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find_map(|(i, s)| Some((i, s.get(name)?)))?;
+
+        Some(if i < self.barrier { self.known(t) } else { t.clone() })
+    }
+
+    /// Sets a variable the way running the program would: the innermost
+    /// existing one or, if there isn't one, a new one in the innermost scope.
+    fn assign(&mut self, name: &Ident, t: Type) {
+        match self.scopes.iter().rposition(|s| s.contains_key(name)) {
+            Some(i) => {
+                let t = if i < self.barrier { self.known(&t) } else { t };
+                let old = self.scopes[i][name].clone();
+                let joined = self.join(&old, &t);
+                self.scopes[i].insert(name.clone(), joined);
+            }
+            None => {
+                self.scopes.last_mut().unwrap().insert(name.clone(), t);
+            }
+        }
+    }
+
+    fn define(&mut self, definition: StatementId) {
+        if let Statement::Procedure { name, .. } = &self.arena[definition] {
+            // Like when the program runs, later definitions replace earlier
+            // ones.
+            self.procedures.insert(
+                name.inner.clone(),
+                Signature {
+                    definition,
+                    state: State::NotYet,
+                },
+            );
+        }
+    }
+
+    /// The type of a call to `name`, if it's a named procedure.
+    fn signature(&mut self, name: &Ident) -> Option<Type> {
+        let signature = self.procedures.get(name)?;
+        match &signature.state {
+            State::Done(t) => {
+                let t = t.clone();
+                Some(self.instantiate(&t))
+            }
+            State::Inferring(t) => Some(t.clone()),
+            State::NotYet => {
+                let definition = signature.definition;
+                let t = self.procedure(definition);
+                Some(self.instantiate(&t))
+            }
+        }
+    }
+
+    fn procedure(&mut self, definition: StatementId) -> Type {
+        let arena = self.arena;
+        let (name, args, body) = match &arena[definition] {
+            Statement::Procedure { name, args, body } => (name, args, *body),
+            _ => unreachable!("signatures are only made for procedures"),
+        };
+        let params = args.iter().map(|_| self.fresh()).collect::<Vec<_>>();
+        let ret = self.fresh();
+        let own = Type::Procedure(params.clone(), Box::new(ret.clone()));
+        self.set_state(name, State::Inferring(own.clone()));
+
+        // Named procedures only see the top level.
+        let locals = self.scopes.split_off(1);
+        let barrier = std::mem::replace(&mut self.barrier, 1);
+        self.scopes.push(args.iter().map(|a| a.inner.clone()).zip(params).collect());
+
+        let given = self.body(body);
+        if let Type::Var(r) = self.resolve(&ret) {
+            // Binding it directly (rather than unifying) keeps `Any`.
+            if self.resolve(&given) != Type::Var(r) {
+                let _ = self.bind(r, given);
+            }
+        } else {
+            let _ = self.unify(&ret, &given);
+        }
+
+        self.scopes.pop();
+        self.scopes.extend(locals);
+        self.barrier = barrier;
+
+        let done = self.apply(&own);
+        self.set_state(name, State::Done(done.clone()));
+        done
+    }
+
+    fn set_state(&mut self, name: &S<Ident>, state: State) {
+        if let Some(s) = self.procedures.get_mut(&name.inner) {
+            s.state = state;
+        }
+    }
+
+    /// The type of what a procedure body gives back: its value or whatever it
+    /// gives back with `give back`.
+    fn body(&mut self, body: ExprId) -> Type {
+        self.returns.push(vec![]);
+        let value = self.expr(body);
+        let returned = self.returns.pop().unwrap_or_default();
+
+        returned.iter().fold(value, |acc, t| self.join(&acc, t))
+    }
+
+    /// Checks `body` in a new scope that has `bindings` in it.
+    fn scoped<T>(
+        &mut self,
+        bindings: HashMap<Ident, Type>,
+        body: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        self.scopes.push(bindings);
+        let t = body(self);
+        self.scopes.pop();
+        t
+    }
+
+    /// Whether `t` can be a number; it is one from now on if it wasn't known.
+    fn number(&mut self, t: &Type) -> bool {
+        self.unify(t, &Type::Num).is_ok()
+    }
+
+    fn statement(&mut self, s: StatementId) {
+        let arena = self.arena;
+        match &arena[s] {
+            Statement::Expr(e) => {
+                self.expr(*e);
+            }
+            Statement::While { cond, body } => {
+                self.expr(*cond);
+                self.expr(*body);
+            }
+            Statement::Until { body, cond } => {
+                self.expr(*body);
+                self.expr(*cond);
+            }
+            Statement::For { name, list, body } => {
+                let list_type = self.expr(*list);
+                let item = match self.resolve(&list_type) {
+                    Type::List(item) => *item,
+                    // Strings are looped over by character and ledgers by key.
+                    Type::String | Type::Ledger => Type::String,
+                    Type::Var(_) | Type::Any => Type::Any,
+                    other => {
+                        self.errors.push(TypeError::Loop {
+                            found: self.apply(&other).describe(),
+                            span: arena.span(*list).clone(),
+                        });
+                        Type::Any
+                    }
+                };
+
+                let bindings = std::iter::once((name.inner.clone(), item)).collect();
+                self.scoped(bindings, |infer| infer.expr(*body));
+            }
+            Statement::Procedure { name, .. } => {
+                if !self.procedures.contains_key(&name.inner) {
+                    self.define(s);
+                }
+            }
+            Statement::Section { body, .. } => {
+                body.iter().for_each(|&s| self.statement(s));
+            }
+            Statement::Return { value } => {
+                let t = self.expr(*value);
+                if let Some(returns) = self.returns.last_mut() {
+                    returns.push(t);
+                }
+            }
+            Statement::Import(_) | Statement::Break | Statement::Continue => {}
+        }
+    }
+
+    /// The type of the index of a `get` or the key of a `set ... in`: a bare
+    /// name that isn't a variable is a ledger key.
+    fn key(&mut self, key: ExprId) -> Type {
+        let arena = self.arena;
+        match &arena[key] {
+            Expr::Ident(name) if self.lookup(name).is_none() => {
+                self.exprs.insert(key, Type::String);
+                Type::String
+            }
+            _ => self.expr(key),
+        }
+    }
+
+    fn expr(&mut self, e: ExprId) -> Type {
+        let arena = self.arena;
+        let t = match &arena[e] {
+            Expr::Num(_) => Type::Num,
+            Expr::String(_) => Type::String,
+            Expr::Interpolated(fragments) => {
+                for f in fragments {
+                    if let Fragment::Expr(e) = f {
+                        self.expr(*e);
+                    }
+                }
+                Type::String
+            }
+            Expr::Ledger(entries) => {
+                entries.iter().for_each(|(_, value)| {
+                    self.expr(*value);
+                });
+                Type::Ledger
+            }
+            Expr::List(items) => {
+                let mut item = self.hole();
+                for &i in items {
+                    let t = self.expr(i);
+                    item = self.join(&item, &t);
+                }
+                Type::List(Box::new(item))
+            }
+            Expr::Ident(name) => match self.lookup(name) {
+                Some(t) => t,
+                // Procedures are values too.
+                None => self.signature(name).unwrap_or(Type::Any),
+            },
+            Expr::Assign { name, to } => {
+                let t = self.expr(*to);
+                self.assign(&name.inner, t.clone());
+                t
+            }
+            Expr::Update { key, target, to } => {
+                let k = self.key(*key);
+                let t = self.expr(*to);
+                let target_type = self.lookup(&target.inner).unwrap_or(Type::Any);
+                let fits = match self.resolve(&target_type) {
+                    Type::List(_) => self.number(&k),
+                    Type::Ledger => self.unify(&k, &Type::String).is_ok(),
+                    Type::Var(_) | Type::Any => true,
+                    _ => false,
+                };
+
+                if fits {
+                    if let Type::List(_) = self.resolve(&target_type) {
+                        self.assign(&target.inner, Type::List(Box::new(t.clone())));
+                    }
+                } else {
+                    self.errors.push(TypeError::Update {
+                        target: self.apply(&target_type).describe(),
+                        key: self.apply(&k).describe(),
+                        target_span: target.span.clone(),
+                        key_span: arena.span(*key).clone(),
+                    });
+                }
+                t
+            }
+            Expr::Print(p) => self.expr(*p),
+            Expr::Block { body, end } => {
+                body.iter().for_each(|&s| self.statement(s));
+                match (end, body.last().map(|&s| &arena[s])) {
+                    (Some(end), _) => self.expr(*end),
+                    // Nothing after these runs.
+                    (None, Some(Statement::Return { .. }))
+                    | (None, Some(Statement::Break))
+                    | (None, Some(Statement::Continue)) => self.hole(),
+                    (None, _) => Type::Num,
+                }
+            }
+            Expr::If {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.expr(*cond);
+                let then = self.expr(*then);
+                let otherwise = match otherwise {
+                    Some(o) => self.expr(*o),
+                    None => Type::Num,
+                };
+                self.join(&then, &otherwise)
+            }
+            Expr::When {
+                subject,
+                arms,
+                otherwise,
+            } => {
+                let subject = self.expr(*subject);
+                let mut value = self.hole();
+                for arm in arms {
+                    let bindings = self.bindings(&arm.inner.pattern.inner, &subject);
+                    let t = self.scoped(bindings, |infer| {
+                        if let Some(g) = arm.inner.guard {
+                            infer.expr(g);
+                        }
+                        infer.expr(arm.inner.body)
+                    });
+                    value = self.join(&value, &t);
+                }
+
+                match otherwise {
+                    Some(o) => {
+                        let t = self.expr(*o);
+                        self.join(&value, &t)
+                    }
+                    None => value,
+                }
+            }
+            Expr::UnOp { op, expr } => {
+                let t = self.expr(*expr);
+                if matches!(op.inner, UnaryOperator::Neg) && !self.number(&t) {
+                    self.errors.push(TypeError::Negate {
+                        found: self.apply(&t).describe(),
+                        span: arena.span(*expr).clone(),
+                    });
+                }
+                Type::Num
+            }
+            Expr::BinOp { lhs, op, rhs } => self.binop(*lhs, op, *rhs),
+            Expr::Logic { lhs, rhs, .. } => {
+                self.expr(*lhs);
+                self.expr(*rhs);
+                Type::Num
+            }
+            Expr::Call { name, args } => self.call(name, args),
+            Expr::Get { index, from } => {
+                let from_type = self.expr(*from);
+                let index_type = self.key(*index);
+                let (fits, item) = match self.resolve(&from_type) {
+                    Type::List(item) => (self.number(&index_type), *item),
+                    Type::Ledger => (self.unify(&index_type, &Type::String).is_ok(), Type::Any),
+                    Type::String => (self.number(&index_type), Type::String),
+                    Type::Var(_) | Type::Any => (true, Type::Any),
+                    _ => (false, Type::Any),
+                };
+
+                if !fits {
+                    self.errors.push(TypeError::Get {
+                        from: self.apply(&from_type).describe(),
+                        index: self.apply(&index_type).describe(),
+                        from_span: arena.span(*from).clone(),
+                        index_span: arena.span(*index).clone(),
+                    });
+                }
+                item
+            }
+            Expr::Lambda { args, body } => {
+                let params = args.iter().map(|_| self.fresh()).collect::<Vec<_>>();
+                let bindings = args.iter().map(|a| a.inner.clone()).zip(params.clone()).collect();
+                let ret = self.scoped(bindings, |infer| infer.body(*body));
+                Type::Procedure(params, Box::new(ret))
+            }
+        };
+
+        self.exprs.insert(e, t.clone());
+        t
+    }
+
+    /// What a `when` pattern binds when it matches a `subject`.
+    fn bindings(&mut self, pattern: &Pattern, subject: &Type) -> HashMap<Ident, Type> {
+        let mut bindings = HashMap::new();
+        match pattern {
+            Pattern::Bind(name) => {
+                bindings.insert(name.inner.clone(), subject.clone());
+            }
+            Pattern::ListStartingWith { head, rest } => {
+                let item = match self.resolve(subject) {
+                    Type::List(item) => *item,
+                    _ => Type::Any,
+                };
+                if let Some(rest) = rest {
+                    bindings.insert(rest.inner.clone(), Type::List(Box::new(item.clone())));
+                }
+                bindings.insert(head.inner.clone(), item);
+            }
+            _ => {}
+        }
+        bindings
+    }
+
+    fn binop(&mut self, lhs: ExprId, op: &S<Op>, rhs: ExprId) -> Type {
+        let (l, r) = (self.expr(lhs), self.expr(rhs));
+
+        let (fits, t) = match op.inner {
+            Op::Sub | Op::Mul | Op::Div => {
+                let fits = self.number(&l);
+                (self.number(&r) && fits, Type::Num)
+            }
+            // Numbers can be added to numbers and anything can be added to (the
+            // end of) a list.
+            Op::Add => match self.resolve(&l) {
+                Type::Num => (self.number(&r), Type::Num),
+                Type::List(item) => (true, Type::List(Box::new(self.join(&item, &r)))),
+                Type::Var(_) | Type::Any => {
+                    if !matches!(self.resolve(&r), Type::Num | Type::Var(_) | Type::Any) {
+                        let item = self.fresh();
+                        let _ = self.unify(&l, &Type::List(Box::new(item)));
+                    }
+                    (true, self.apply(&l))
+                }
+                _ => (false, Type::Any),
+            },
+            Op::Eq | Op::Lt | Op::Gt => (true, Type::Num),
+        };
+
+        if !fits {
+            self.errors.push(TypeError::Operands {
+                op: op.inner,
+                lhs: self.apply(&l).describe(),
+                rhs: self.apply(&r).describe(),
+                lhs_span: self.arena.span(lhs).clone(),
+                rhs_span: self.arena.span(rhs).clone(),
+            });
+        }
+        t
+    }
+
+    fn call(&mut self, name: &S<Ident>, args: &[ExprId]) -> Type {
+        let arena = self.arena;
+        let given = args.iter().map(|&a| self.expr(a)).collect::<Vec<_>>();
+
+        // Variables that hold procedures shadow named procedures.
+        let (callee, parameters) = match self.lookup(&name.inner) {
+            Some(t) => (t, None),
+            None => match self.signature(&name.inner) {
+                Some(t) => match &arena[self.procedures[&name.inner].definition] {
+                    Statement::Procedure { args, .. } => (t, Some(args)),
+                    _ => (t, None),
+                },
+                None => return Type::Any,
+            },
+        };
+
+        match self.resolve(&callee) {
+            Type::Procedure(params, ret) if params.len() == given.len() => {
+                for (i, (param, arg)) in params.iter().zip(&given).enumerate() {
+                    if self.unify(param, arg).is_err() {
+                        self.errors.push(TypeError::Argument {
+                            name: name.inner.clone(),
+                            expected: self.apply(param).describe(),
+                            found: self.apply(arg).describe(),
+                            span: arena.span(args[i]).clone(),
+                            parameter: parameters.map(|p| p[i].span.clone()),
+                        });
+                    }
+                }
+                *ret
+            }
+            Type::Var(_) => {
+                let ret = self.fresh();
+                let _ = self.unify(&callee, &Type::Procedure(given, Box::new(ret.clone())));
+                ret
+            }
+            Type::Procedure(..) | Type::Any => Type::Any,
+            other => {
+                self.errors.push(TypeError::NotProcedure {
+                    name: name.inner.clone(),
+                    found: self.apply(&other).describe(),
+                    span: name.span.clone(),
+                });
+                Type::Any
+            }
+        }
+    }
+}
+
+/// Infers the types in a lowered program, along with the operations in it
+/// that can never succeed.
+pub fn infer(arena: &Arena, program: &[StatementId]) -> (Types, Vec<TypeError>) {
+    let mut infer = Infer {
+        arena,
+        bindings: vec![],
+        holes: HashSet::new(),
+        scopes: vec![HashMap::new()],
+        barrier: 0,
+        returns: vec![],
+        procedures: HashMap::new(),
+        exprs: SideTable::new(),
+        errors: vec![],
+    };
+
+    // Procedures can be called before they're defined.
+    let statements = arena.statements(program);
+    statements.iter().for_each(|&s| infer.define(s));
+    program.iter().for_each(|&s| infer.statement(s));
+
+    // Procedures that are never called haven't been inferred yet.
+    let mut procedures = SideTable::new();
+    for &s in &statements {
+        let signature = match &arena[s] {
+            Statement::Procedure { name, .. } => &infer.procedures[&name.inner],
+            _ => continue,
+        };
+        if signature.definition != s {
+            continue;
+        }
+
+        let done = match &signature.state {
+            State::Done(t) => Some(t.clone()),
+            State::NotYet | State::Inferring(_) => None,
+        };
+        let t = match done {
+            Some(t) => t,
+            None => infer.procedure(s),
+        };
+        procedures.insert(s, infer.apply(&t).normalized());
+    }
+
+    let mut variables = SideTable::new();
+    let mut seen = HashSet::new();
+    for &s in &statements {
+        let (set, name) = match &arena[s] {
+            Statement::Expr(e) => match &arena[*e] {
+                Expr::Assign { name, .. } => (*e, &name.inner),
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        if seen.insert(name) {
+            if let Some(t) = infer.scopes[0].get(name) {
+                variables.insert(set, infer.apply(t).normalized());
+            }
+        }
+    }
+
+    let exprs = infer.exprs.iter().fold(SideTable::new(), |mut exprs, (e, t)| {
+        exprs.insert(e, infer.apply(t));
+        exprs
+    });
+
+    (
+        Types {
+            exprs,
+            procedures,
+            variables,
+        },
+        infer.errors,
+    )
+}
+
+/// `module` with the types [inferred](infer) for it listed above it, as
+/// comments; what `--emit ast` prints.
+pub fn annotated(module: &Module) -> String {
+    let Lowered { arena, program } = Lowered::of(module);
+    let (types, _) = infer(&arena, &program);
+    format!("{}{}", types.listing(&arena), module)
+}
+
+/// Leaves the module's [`Types`] in the [`Analyses`].
+pub struct TypeCheck;
+
+impl Pass for TypeCheck {
+    fn name(&self) -> &'static str {
+        "types"
+    }
+
+    fn dependencies(&self) -> &'static [&'static str] {
+        &["lower"]
+    }
+
+    fn run(&mut self, _: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        let Lowered { arena, program } = analyses.get::<Lowered>().expect("`lower` runs first");
+        let (types, errors) = infer(arena, program);
+        analyses.insert(types);
+        errors.iter().map(|e| Diagnostic::error(e, e.labels())).collect()
+    }
+}
+
+/// Leaves the module's [`Lowered`] program in the [`Analyses`], for the passes
+/// that work on ids.
+pub struct Lower;
+
+impl Pass for Lower {
+    fn name(&self) -> &'static str {
+        "lower"
+    }
+
+    fn run(&mut self, module: &Module, analyses: &mut Analyses) -> Vec<Diagnostic> {
+        analyses.insert(Lowered::of(module));
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::cado;
+
+    fn lowered(src: &str) -> Lowered {
+        Lowered::of(&cado("test.cado", src))
+    }
+
+    #[test]
+    fn types_are_keyed_by_node() {
+        let lowered = lowered("set l to [1, 2].\nset n to get 0 from l.");
+        let Lowered { arena, program } = &lowered;
+        let (types, errors) = infer(arena, program);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // Every expression gets a type, `get`s included.
+        assert_eq!(types.exprs.iter().count(), arena.len::<Expr>());
+        let get = match &arena[arena.statements(program)[1]] {
+            Statement::Expr(e) => match &arena[*e] {
+                Expr::Assign { to, .. } => *to,
+                other => panic!("expected a `set`, got {:?}", other),
+            },
+            other => panic!("expected an expression, got {:?}", other),
+        };
+        assert_eq!(types.exprs.get(get), Some(&Type::Num));
+
+        assert_eq!(types.listing(arena).to_string(), "// l: [number]\n// n: number\n");
+    }
+
+    #[test]
+    fn procedures_get_signatures() {
+        let lowered = lowered(
+            "procedure double takes x does x * 2.\n\
+             procedure same takes x does x.\n\
+             set n to do same using “one”.",
+        );
+        let Lowered { arena, program } = &lowered;
+        let (types, errors) = infer(arena, program);
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        // `same` works on anything, even though it's only called with a string.
+        assert_eq!(
+            types.listing(arena).to_string(),
+            "// double: (number) -> number\n// same: ('a) -> 'a\n// n: string\n",
+        );
+    }
+
+    #[test]
+    fn the_ast_is_annotated_with_types() {
+        let module = cado("test.cado", "procedure same takes x does x.\nset n to do same using 3.");
+        assert_eq!(
+            annotated(&module),
+            "// same: ('a) -> 'a\n// n: number\nfunction same (x) x;\nn = same(3);\n",
+        );
+    }
+
+    #[test]
+    fn operand_errors_point_at_both_operands() {
+        let src = "set x to \"a\" - 1.";
+        let lowered = lowered(src);
+        let (_, errors) = infer(&lowered.arena, &lowered.program);
+        assert_eq!(errors.len(), 1, "{:?}", errors);
+        assert_eq!(errors[0].to_string(), "can't subtract a number from a string");
+        match &errors[0] {
+            TypeError::Operands { lhs_span, rhs_span, .. } => {
+                let lhs = src.find("\"a\"").unwrap();
+                assert_eq!(lhs_span.inner, lhs..lhs + 3);
+                let rhs = src.find('1').unwrap();
+                assert_eq!(rhs_span.inner, rhs..rhs + 1);
+            }
+            other => panic!("expected an error about the operands, got {:?}", other),
+        }
+    }
+}
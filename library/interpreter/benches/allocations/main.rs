@@ -0,0 +1,138 @@
+//! Counts the allocations it takes to run large programs.
+//!
+//! Run with `cargo bench -p avocadocx-interpreter --bench allocations`.
+//!
+//! Each program is run twice: by the interpreter (on the arena) and by the
+//! interpreter as it was when it walked the tree (see [`tree`]).
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use std::time::{Duration, Instant};
+
+use abogado_parse::ast::Module;
+
+mod tree;
+
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static BYTES: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Relaxed);
+        BYTES.fetch_add(layout.size(), Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Relaxed);
+        BYTES.fetch_add(new_size, Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: Counting = Counting;
+
+struct Measurement {
+    allocations: usize,
+    bytes: usize,
+    time: Duration,
+}
+
+fn measure(f: impl FnOnce()) -> Measurement {
+    let (allocations, bytes) = (ALLOCATIONS.load(Relaxed), BYTES.load(Relaxed));
+    let start = Instant::now();
+    f();
+
+    Measurement {
+        allocations: ALLOCATIONS.load(Relaxed) - allocations,
+        bytes: BYTES.load(Relaxed) - bytes,
+        time: start.elapsed(),
+    }
+}
+
+fn parse(name: &str, source: String) -> Module {
+    let (tokens, source) = abogado_lex::lex_cado(source);
+    let tokens = tokens.expect("benchmarks should lex");
+
+    let (module, errors) = abogado_parse::parse(name, tokens, &source);
+    assert!(errors.is_empty(), "benchmarks should parse: {:?}", errors);
+    module
+}
+
+/// A counter that goes down to zero, doing some arithmetic on the way.
+fn countdown(n: usize) -> String {
+    format!(
+        "set total to 0.
+         set i to {}.
+         while i run (set total to total + i * 2 - 1. set i to i - 1)",
+        n
+    )
+}
+
+/// The same, but through a procedure (and an anonymous one).
+fn calls(n: usize) -> String {
+    format!(
+        "procedure step takes n does n - 1
+         set twice to a procedure taking x that does x * 2.
+         set i to {}.
+         while i run (set total to do twice using i. set i to do step using i)",
+        n
+    )
+}
+
+/// Lots of straight-line code, with one loop at the end that goes over it.
+fn wide(n: usize) -> String {
+    let mut source = String::new();
+    for i in 0..n {
+        source.push_str(&format!("set v{} to [{}, {} + 1, {} * 2].\n", i, i, i, i));
+    }
+    source.push_str(&format!("set i to {}.\nwhile i run (set i to i - 1)\n", n));
+    source
+}
+
+fn main() {
+    const N: usize = 10_000;
+    let programs: [(&str, String); 3] = [
+        ("countdown", countdown(N)),
+        ("calls", calls(N)),
+        ("wide", wide(N)),
+    ];
+
+    println!(
+        "{:<10} {:>14} {:>14} {:>10}   {:>14} {:>14} {:>10}",
+        "program", "allocations", "bytes", "time", "tree allocs", "tree bytes", "tree time"
+    );
+    for (name, source) in programs.iter() {
+        let program = parse(name, source.clone());
+
+        // Both get their own copy of the program before measuring starts.
+        let body = program.body.clone();
+        let mut left = None;
+        let tree = measure(|| left = tree::run_program(body, "i"));
+        assert!(
+            matches!(left, Some(tree::Value::Num(n)) if n == 0.0),
+            "{} should count `i` down to 0",
+            name
+        );
+
+        let run = measure(|| avocadocx_interpreter::run_program(program).unwrap());
+
+        println!(
+            "{:<10} {:>14} {:>14} {:>10.2?}   {:>14} {:>14} {:>10.2?}",
+            name,
+            run.allocations,
+            run.bytes,
+            run.time,
+            tree.allocations,
+            tree.bytes,
+            tree.time,
+        );
+    }
+}
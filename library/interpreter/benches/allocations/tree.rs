@@ -0,0 +1,208 @@
+//! The interpreter as it was before it ran on the arena, cut down to what the
+//! benchmarks use.
+//!
+//! It walks the parser's tree and takes expressions by value, so every turn
+//! of a loop clones the loop's condition and body and every call clones the
+//! procedure's body. That's what the arena gets rid of; this is here so that
+//! the benchmarks can measure the difference instead of guessing at it.
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use abogado_lex::{spanned::S, token::Op::*};
+use abogado_parse::ast::{
+    Assign, BinOp, Block, Call, Expr, Ident, Lambda, List, Procedure, Statement, While,
+};
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Num(f64),
+    List(Vec<Value>),
+    Procedure(Rc<Proc>),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        match self {
+            Value::Num(f) => !(*f).eq(&0.0),
+            Value::List(l) => !l.is_empty(),
+            Value::Procedure(_) => true,
+        }
+    }
+}
+
+type Key = (Option<String>, Ident);
+
+fn key(name: &S<Ident>) -> Key {
+    (name.style.font().map(str::to_string), name.inner.clone())
+}
+
+#[derive(Default, Debug)]
+struct Namespace {
+    inner: HashMap<Key, Value>,
+    child: Option<Box<Namespace>>,
+}
+
+impl Namespace {
+    fn resolve(&self, k: &Key) -> Option<Value> {
+        self.child
+            .as_ref()
+            .and_then(|c| c.resolve(k))
+            .or_else(|| self.inner.get(k).cloned())
+    }
+
+    fn assign(&mut self, k: Key, val: Value) {
+        let existing = self.child.as_ref().map(|c| c.contains(&k)).unwrap_or(false);
+        match self.child {
+            Some(ref mut c) if existing || !self.inner.contains_key(&k) => c.assign(k, val),
+            _ => {
+                self.inner.insert(k, val);
+            }
+        }
+    }
+
+    fn contains(&self, k: &Key) -> bool {
+        self.inner.contains_key(k) || self.child.as_ref().map(|c| c.contains(k)).unwrap_or(false)
+    }
+
+    fn locals(&self) -> HashMap<Key, Value> {
+        fn collect(ns: &Namespace, out: &mut HashMap<Key, Value>) {
+            out.extend(ns.inner.iter().map(|(k, v)| (k.clone(), v.clone())));
+            if let Some(c) = &ns.child {
+                collect(c, out);
+            }
+        }
+
+        let mut locals = HashMap::new();
+        if let Some(c) = &self.child {
+            collect(c, &mut locals);
+        }
+        locals
+    }
+
+    fn push(&mut self, new: HashMap<Key, Value>) {
+        if let Some(ref mut c) = self.child {
+            c.push(new)
+        } else {
+            self.child = Some(Box::new(Namespace {
+                inner: new,
+                child: None,
+            }))
+        }
+    }
+
+    fn pop(&mut self) {
+        match self.child {
+            Some(ref mut c) if c.child.is_some() => c.pop(),
+            _ => self.child = None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Proc {
+    params: Vec<Key>,
+    body: S<Expr>,
+    captured: HashMap<Key, Value>,
+}
+
+#[derive(Default)]
+struct Context {
+    namespace: Namespace,
+    functions: HashMap<Key, Proc>,
+}
+
+fn run_expr(ctx: &mut Context, e: S<Expr>) -> Value {
+    match e.inner {
+        Expr::Num(f) => Value::Num(f),
+        Expr::List(List(l)) => Value::List(l.into_iter().map(|e| run_expr(ctx, e)).collect()),
+        Expr::Ident(i) => {
+            let k = (e.style.font().map(str::to_string), i);
+            match ctx.namespace.resolve(&k) {
+                Some(v) => v,
+                None => Value::Procedure(Rc::new(ctx.functions[&k].clone())),
+            }
+        }
+        Expr::Lambda(Lambda { args, body }) => Value::Procedure(Rc::new(Proc {
+            params: args.iter().map(key).collect(),
+            body: *body,
+            captured: ctx.namespace.locals(),
+        })),
+        Expr::Assign(Assign { name, to }) => {
+            let val = run_expr(ctx, *to);
+            ctx.namespace.assign(key(&name), val.clone());
+            val
+        }
+        Expr::Block(Block { body, end }) => {
+            for statement in body {
+                run_statement(ctx, statement);
+            }
+
+            match end {
+                Some(e) => run_expr(ctx, *e),
+                None => Value::Num(0.0),
+            }
+        }
+        Expr::BinOp(BinOp { lhs, op, rhs }) => {
+            match (run_expr(ctx, *lhs), op.inner, run_expr(ctx, *rhs)) {
+                (Value::Num(a), Add, Value::Num(b)) => Value::Num(a + b),
+                (Value::Num(a), Sub, Value::Num(b)) => Value::Num(a - b),
+                (Value::Num(a), Mul, Value::Num(b)) => Value::Num(a * b),
+                (Value::Num(a), Div, Value::Num(b)) => Value::Num(a / b),
+                (l, op, r) => unimplemented!("{:?} {} {:?}", l, op, r),
+            }
+        }
+        Expr::Call(Call { name, args }) => {
+            let Proc {
+                params,
+                body,
+                captured,
+            } = match ctx.namespace.resolve(&key(&name)) {
+                Some(Value::Procedure(p)) => (*p).clone(),
+                _ => ctx.functions[&key(&name)].clone(),
+            };
+            let args = args.into_iter().map(|a| run_expr(ctx, a)).collect::<Vec<_>>();
+            let mut bindings = captured;
+            bindings.extend(params.into_iter().zip(args.into_iter()));
+            ctx.namespace.push(bindings);
+            let retval = run_expr(ctx, body);
+            ctx.namespace.pop();
+
+            retval
+        }
+        e => unimplemented!("the benchmarks don't use {:?}", e),
+    }
+}
+
+fn run_statement(ctx: &mut Context, s: S<Statement>) {
+    match s.inner {
+        Statement::Expr(e) => {
+            run_expr(ctx, e);
+        }
+        Statement::While(While { cond, body }) => {
+            while run_expr(ctx, (*cond).clone()).truthy() {
+                run_expr(ctx, (*body).clone());
+            }
+        }
+        Statement::Procedure(Procedure { name, args, body }) => {
+            let proc = Proc {
+                params: args.iter().map(key).collect(),
+                body: *body,
+                captured: HashMap::new(),
+            };
+            ctx.functions.insert(key(&name), proc);
+        }
+        s => unimplemented!("the benchmarks don't use {:?}", s),
+    }
+}
+
+/// Runs `program`, giving back the value of `name` at the end (to check that
+/// it does the same thing as the interpreter).
+pub fn run_program(program: Vec<S<Statement>>, name: &str) -> Option<Value> {
+    let mut ctx = Context::default();
+    for statement in program {
+        run_statement(&mut ctx, statement);
+    }
+
+    ctx.namespace.resolve(&(None, name.to_string()))
+}
@@ -9,17 +9,140 @@
 )]
 
 // use abogado_parse::ast::*;
-use abogado_lex::token::Op::*;
-use abogado_parse::ast::{
-    Assign, BinOp, Block, Call, Expr, For, Get, If, List, Procedure, Statement, UnOp, While, UnaryOperator,
-};
-use std::collections::HashMap;
+use abogado_lex::{spanned::S, token::Op::*, Op, Span};
+use abogado_parse::ast::arena::{Arena, Expr, ExprId, Fragment, Statement, StatementId};
+use abogado_parse::ast::{self, Ident, Import, LogicOp, Pattern, UnaryOperator};
+use abogado_parse::interface::{DefinitionKind, Interface};
+use abogado_passes::privacy;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::rc::Rc;
+use thiserror::Error;
+
+fn operands(op: &Op, lhs: &str, rhs: &str) -> String {
+    match op {
+        Add => format!("can't add {} to {}", rhs, lhs),
+        Sub => format!("can't subtract {} from {}", rhs, lhs),
+        Mul => format!("can't multiply {} by {}", lhs, rhs),
+        Div => format!("can't divide {} by {}", lhs, rhs),
+        _ => format!("can't compare {} and {}", lhs, rhs),
+    }
+}
+
+#[derive(Debug, Error, Clone, PartialEq)]
+pub enum RuntimeError {
+    #[error("`{name}` is private to its border and can't be used outside of it")]
+    Private {
+        name: Ident,
+        span: Span,
+        border: Span,
+    },
+    #[error("`give back` can only be used inside a procedure")]
+    ReturnOutsideProcedure { span: Span },
+    #[error("`{what}` can only be used inside a loop")]
+    OutsideLoop { what: &'static str, span: Span },
+    #[error("nothing in this `when` matched; add an `otherwise`")]
+    NoMatch { span: Span },
+    #[error("`{name}` isn't defined")]
+    Undefined { name: Ident, span: Span },
+    #[error("`{name}` is {found}, not a procedure")]
+    NotProcedure {
+        name: Ident,
+        found: &'static str,
+        span: Span,
+    },
+    #[error("there's nothing at position {index} in {container} of {len}")]
+    OutOfBounds {
+        index: f64,
+        len: usize,
+        /// `"a list"` or `"a string"`.
+        container: &'static str,
+        span: Span,
+    },
+    #[error("the ledger doesn't have anything under “{key}”")]
+    MissingKey { key: String, span: Span },
+    #[error("{container} are indexed by {expected}, not by {found}")]
+    BadKey {
+        container: &'static str,
+        expected: &'static str,
+        found: &'static str,
+        span: Span,
+    },
+    #[error("{}", operands(.op, .lhs, .rhs))]
+    Operands {
+        op: Op,
+        lhs: &'static str,
+        rhs: &'static str,
+        span: Span,
+    },
+    #[error("can't negate {found}")]
+    Negate { found: &'static str, span: Span },
+    #[error("can't loop over {found}")]
+    Loop { found: &'static str, span: Span },
+    #[error("{found} doesn't have items to get or set")]
+    NotIndexable { found: &'static str, span: Span },
+    #[error("`{module}` wasn't loaded")]
+    UnknownModule { module: String, span: Span },
+    #[error("`{module}` doesn't have a procedure named `{name}`")]
+    Unresolved {
+        name: Ident,
+        module: String,
+        span: Span,
+    },
+}
+
+/// Why evaluation stopped early: either an error or control flow that an
+/// enclosing loop or procedure call should catch.
+enum Unwind {
+    Return(Value, Span),
+    Break(Span),
+    Continue(Span),
+    Error(RuntimeError),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(e: RuntimeError) -> Self {
+        Unwind::Error(e)
+    }
+}
+
+impl Unwind {
+    /// For when nothing is left to catch control flow.
+    fn into_error(self) -> RuntimeError {
+        match self {
+            Unwind::Return(_, span) => RuntimeError::ReturnOutsideProcedure { span },
+            Unwind::Break(span) => RuntimeError::OutsideLoop { what: "stop", span },
+            Unwind::Continue(span) => RuntimeError::OutsideLoop {
+                what: "skip ahead",
+                span,
+            },
+            Unwind::Error(e) => e,
+        }
+    }
+}
+
+/// What a loop does after running its body once.
+enum Flow {
+    Next,
+    Stop,
+}
+
+/// Runs a loop body, catching `stop` and `skip ahead`.
+fn run_body(ctx: &mut Context<'_>, body: ExprId) -> Result<Flow, Unwind> {
+    match run_expr(ctx, body) {
+        Ok(_) | Err(Unwind::Continue(_)) => Ok(Flow::Next),
+        Err(Unwind::Break(_)) => Ok(Flow::Stop),
+        Err(e) => Err(e),
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
 enum Value {
     Num(f64),
     String(String),
     List(Vec<Value>),
+    Record(BTreeMap<String, Value>),
+    Procedure(Rc<Proc>),
 }
 
 impl Value {
@@ -28,206 +151,1214 @@ impl Value {
             Value::Num(f) => (*f).eq(&0.0),
             Value::String(s) => s.is_empty(),
             Value::List(l) => l.is_empty() || l.iter().all(|v| v.falsey()),
+            Value::Record(r) => r.is_empty(),
+            Value::Procedure(_) => false,
         }
     }
     pub fn truthy(&self) -> bool {
         !self.falsey()
     }
+
+    /// What kind of value this is, for error messages.
+    fn describe(&self) -> &'static str {
+        match self {
+            Value::Num(_) => "a number",
+            Value::String(_) => "a string",
+            Value::List(_) => "a list",
+            Value::Record(_) => "a ledger",
+            Value::Procedure(_) => "a procedure",
+        }
+    }
+}
+
+/// How values are shown to users (i.e. by `emit` and in interpolated strings).
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Num(n) => write!(f, "{}", n),
+            Value::String(s) => write!(f, "{}", s),
+            Value::List(l) => {
+                write!(f, "[")?;
+                for (i, v) in l.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    // Quote strings so that the list's items are clear.
+                    match v {
+                        Value::String(s) => write!(f, "“{}”", s)?,
+                        v => write!(f, "{}", v)?,
+                    }
+                }
+                write!(f, "]")
+            }
+            Value::Record(r) => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in r.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+
+                    match v {
+                        Value::String(s) => write!(f, "{}: “{}”", k, s)?,
+                        v => write!(f, "{}: {}", k, v)?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::Procedure(p) if p.params.is_empty() => write!(f, "a procedure"),
+            Value::Procedure(p) => {
+                let params = p.params.iter().map(|(_, n)| n.as_str()).collect::<Vec<_>>();
+                write!(f, "a procedure taking {}", params.join(" and "))
+            }
+        }
+    }
+}
+
+/// Bindings are namespaced by the font their name is written in; `None` is
+/// for names without a font (i.e. from `.cado` files, like `std`).
+type Key = (Option<String>, Ident);
+
+fn key(name: &S<Ident>) -> Key {
+    (name.style.font().map(str::to_string), name.inner.clone())
 }
 
 #[derive(Default, Debug)]
 struct Namespace {
-    inner: HashMap<String, Value>,
+    inner: HashMap<Key, Value>,
     child: Option<Box<Namespace>>,
 }
 
 impl Namespace {
-    pub fn resolve(&self, s: &str) -> Option<Value> {
+    /// Names can see bindings in their own font and bindings that have no
+    /// font.
+    fn get(&self, (font, name): &Key) -> Option<&Value> {
+        self.inner
+            .get(&(font.clone(), name.clone()))
+            .or_else(|| self.inner.get(&(None, name.clone())))
+    }
+
+    pub fn resolve(&self, k: &Key) -> Option<Value> {
         self.child
             .as_ref()
-            .map(|c| c.resolve(s))
+            .map(|c| c.resolve(k))
             .flatten()
-            .or_else(|| self.inner.get(s).cloned())
+            .or_else(|| self.get(k).cloned())
+    }
+
+    /// Updates the innermost existing binding for `k` or, if there isn't one,
+    /// makes a new binding in the innermost scope.
+    pub fn assign(&mut self, k: Key, val: Value) {
+        let existing = self.child.as_ref().map(|c| c.contains(&k)).unwrap_or(false);
+        match self.child {
+            Some(ref mut c) if existing || !self.inner.contains_key(&k) => c.assign(k, val),
+            _ => {
+                self.inner.insert(k, val);
+            }
+        }
+    }
+
+    fn contains(&self, k: &Key) -> bool {
+        self.inner.contains_key(k) || self.child.as_ref().map(|c| c.contains(k)).unwrap_or(false)
     }
 
-    pub fn push(&mut self, new: HashMap<String, Value>) {
-        // if let Some(mut c) = &self.child {
-        //     c.push(new)
-        // } else {
-        //     self.child = Some(Box::new(Namespace {
-        //         inner: new,
-        //         child: None,
-        //     }))
-        // }
+    /// Every binding except the globals; what closures capture.
+    fn locals(&self) -> HashMap<Key, Value> {
+        fn collect(ns: &Namespace, out: &mut HashMap<Key, Value>) {
+            out.extend(ns.inner.iter().map(|(k, v)| (k.clone(), v.clone())));
+            if let Some(c) = &ns.child {
+                collect(c, out);
+            }
+        }
+
+        let mut locals = HashMap::new();
+        if let Some(c) = &self.child {
+            collect(c, &mut locals);
+        }
+        locals
+    }
+
+    pub fn push(&mut self, new: HashMap<Key, Value>) {
+        if let Some(ref mut c) = self.child {
+            c.push(new)
+        } else {
+            self.child = Some(Box::new(Namespace {
+                inner: new,
+                child: None,
+            }))
+        }
     }
 
     pub fn pop(&mut self) {
-        // if let Some(mut c) = &self.child {
-        //     c.pop()
-        // } else {
-        //     self.child = None
-        // }
+        match self.child {
+            Some(ref mut c) if c.child.is_some() => c.pop(),
+            _ => self.child = None,
+        }
     }
 }
 
-#[derive(Default)]
-struct Context {
+#[derive(Clone, Debug)]
+struct Proc {
+    params: Vec<Key>,
+    body: ExprId,
+    /// The module the procedure is defined in; `None` is the main program.
+    module: Option<String>,
+    /// The local variables where an anonymous procedure was made; named
+    /// procedures are top-level so they don't capture anything. A call sees
+    /// these, its parameters and the globals, but not the caller's locals.
+    captured: HashMap<Key, Value>,
+}
+
+/// Procedures are only equal to themselves.
+impl PartialEq for Proc {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+/// Every module's procedures: its own and the ones it imports.
+type Procedures = HashMap<Option<String>, HashMap<Key, Proc>>;
+
+struct Context<'a> {
+    /// Every module's code; evaluation only ever borrows from it.
+    arena: &'a Arena,
+
     namespace: Namespace,
-    functions: HashMap<String, (Vec<String>, Expr)>,
+    functions: Procedures,
+    /// The module whose code is running; `None` is the main program.
+    module: Option<String>,
+    /// Imported modules, keyed by the source their imports point to.
+    modules: HashMap<String, Vec<StatementId>>,
+    /// What each imported module exports.
+    interfaces: HashMap<String, Interface>,
+
+    /// Every module's bordered regions; see [`privacy`].
+    regions: HashMap<Option<String>, Vec<Span>>,
+    /// For every module, the names that are private to one of its regions.
+    private: HashMap<Option<String>, HashMap<Key, Span>>,
+    /// The top-level statement that's running.
+    statement: Option<Span>,
 }
 
-fn run_expr(ctx: &mut Context, e: Expr) -> Result<Value, ()> {
-    let res = match e {
-        Expr::Num(f) => Value::Num(f),
-        Expr::String(s) => Value::String(s),
-        Expr::List(List(l)) => Value::List(
-            l.into_iter()
-                .map(|e| run_expr(ctx, e.inner.clone()).expect("todo"))
-                .collect(),
+impl<'a> Context<'a> {
+    fn new(arena: &'a Arena) -> Self {
+        Context {
+            arena,
+            namespace: Namespace::default(),
+            functions: Procedures::new(),
+            module: None,
+            modules: HashMap::new(),
+            interfaces: HashMap::new(),
+            regions: HashMap::new(),
+            private: HashMap::new(),
+            statement: None,
+        }
+    }
+
+    /// Makes `name` private (or public) according to its border; this mirrors
+    /// the [`privacy`] pass so that it's enforced even if passes are skipped.
+    fn define(&mut self, name: &S<Ident>) {
+        let statement = self.statement.clone().unwrap_or_else(|| name.span.clone());
+        let regions = self.regions.get(&self.module).map(Vec::as_slice).unwrap_or_default();
+        let region = privacy::region_of(regions, &name.style, &name.span, &statement);
+        let private = self.private.entry(self.module.clone()).or_default();
+        match region {
+            Some(region) => {
+                private.insert(key(name), region);
+            }
+            None => {
+                private.remove(&key(name));
+            }
+        }
+    }
+
+    /// Spans are only compared within a module: names are checked against the
+    /// borders in the module whose code is running.
+    fn check_access(&self, (font, name): &Key, span: &Span) -> Result<(), RuntimeError> {
+        let private = match self.private.get(&self.module) {
+            Some(private) => private,
+            None => return Ok(()),
+        };
+        let border = private
+            .get(&(font.clone(), name.clone()))
+            .or_else(|| private.get(&(None, name.clone())));
+
+        match border {
+            Some(border) if !border.contains(span) => Err(RuntimeError::Private {
+                name: name.clone(),
+                span: span.clone(),
+                border: border.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    fn function(&self, (font, name): &Key) -> Option<&Proc> {
+        let functions = self.functions.get(&self.module)?;
+        functions
+            .get(&(font.clone(), name.clone()))
+            .or_else(|| functions.get(&(None, name.clone())))
+    }
+}
+
+/// Adds the procedures of the module whose code is running to the context,
+/// hoisting them out of sections.
+fn hoist(ctx: &mut Context<'_>, program: &[StatementId]) {
+    let arena = ctx.arena;
+    for &s in program {
+        match &arena[s] {
+            Statement::Procedure { name, args, body } => {
+                let proc = Proc {
+                    params: args.iter().map(key).collect(),
+                    body: *body,
+                    module: ctx.module.clone(),
+                    captured: HashMap::new(),
+                };
+                ctx.statement = Some(arena.span(s).clone());
+                ctx.define(name);
+                ctx.functions
+                    .entry(ctx.module.clone())
+                    .or_default()
+                    .insert(key(name), proc);
+            }
+            Statement::Section { body, .. } => hoist(ctx, body),
+            _ => {}
+        }
+    }
+}
+
+/// Makes the procedures `import` names visible in `module`.
+///
+/// This only looks at the procedures the imported module defines itself, so
+/// it doesn't matter what order modules (even ones that import each other)
+/// are linked in. Procedures inside a border can't be imported; that mirrors
+/// the `exports` pass.
+fn import(
+    procedures: &mut Procedures,
+    interfaces: &HashMap<String, Interface>,
+    module: &Option<String>,
+    Import { names, source }: &Import,
+) -> Result<(), RuntimeError> {
+    let interface = interfaces
+        .get(&source.inner)
+        .ok_or_else(|| RuntimeError::UnknownModule {
+            module: source.inner.clone(),
+            span: source.span.clone(),
+        })?;
+    let theirs = procedures.get(&Some(source.inner.clone()));
+    let lookup = |name: &Ident| {
+        theirs
+            .and_then(|t| t.iter().find(|((_, n), _)| n == name))
+            .map(|(k, p)| (k.clone(), p.clone()))
+    };
+
+    let imported = match names {
+        Some(names) => names
+            .iter()
+            .map(|n| {
+                let border = interface.definition(&n.inner).and_then(|d| d.border.clone());
+                match (lookup(&n.inner), border) {
+                    (_, Some(border)) => Err(RuntimeError::Private {
+                        name: n.inner.clone(),
+                        span: n.span.clone(),
+                        border,
+                    }),
+                    (Some((_, proc)), None) => Ok((key(n), proc)),
+                    (None, None) => Err(RuntimeError::Unresolved {
+                        name: n.inner.clone(),
+                        module: source.inner.clone(),
+                        span: n.span.clone(),
+                    }),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => interface
+            .exports()
+            .filter(|d| d.kind == DefinitionKind::Procedure)
+            .filter_map(|d| lookup(&d.name.inner))
+            .collect(),
+    };
+
+    procedures.entry(module.clone()).or_default().extend(imported);
+    Ok(())
+}
+
+/// Resolves the imports in every imported module.
+fn link(ctx: &mut Context<'_>) -> Result<(), RuntimeError> {
+    fn imports<'a>(arena: &'a Arena, program: &[StatementId], out: &mut Vec<&'a Import>) {
+        for &s in program {
+            match &arena[s] {
+                Statement::Import(i) => out.push(i),
+                Statement::Section { body, .. } => imports(arena, body, out),
+                _ => {}
+            }
+        }
+    }
+
+    let modules = ctx.modules.clone();
+    for (name, program) in &modules {
+        ctx.module = Some(name.clone());
+        hoist(ctx, program);
+    }
+    ctx.module = None;
+    ctx.statement = None;
+
+    for (name, program) in &ctx.modules {
+        let mut found = vec![];
+        imports(ctx.arena, program, &mut found);
+        for i in found {
+            import(&mut ctx.functions, &ctx.interfaces, &Some(name.clone()), i)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The bindings a pattern makes if it matches `val`.
+fn matches(pattern: &Pattern, val: &Value) -> Option<HashMap<Key, Value>> {
+    let mut bindings = HashMap::new();
+    let matched = match (pattern, val) {
+        (Pattern::Num(n), Value::Num(v)) => n == v,
+        (Pattern::String(s), Value::String(v)) => s == v,
+        (Pattern::EmptyList, Value::List(l)) => l.is_empty(),
+        (Pattern::ListStartingWith { head, rest }, Value::List(l)) => match l.split_first() {
+            Some((first, others)) => {
+                bindings.insert(key(head), first.clone());
+                if let Some(rest) = rest {
+                    bindings.insert(key(rest), Value::List(others.to_vec()));
+                }
+                true
+            }
+            None => false,
+        },
+        (Pattern::Bind(name), v) => {
+            bindings.insert(key(name), v.clone());
+            true
+        }
+        (Pattern::Anything, _) => true,
+        _ => false,
+    };
+
+    if matched {
+        Some(bindings)
+    } else {
+        None
+    }
+}
+
+/// Checks a position in a list (or a string, counting characters) against its
+/// length; positions start at 0.
+fn position(
+    index: f64,
+    len: usize,
+    container: &'static str,
+    span: &Span,
+) -> Result<usize, RuntimeError> {
+    if index >= 0.0 && index.fract() == 0.0 && (index as usize) < len {
+        Ok(index as usize)
+    } else {
+        Err(RuntimeError::OutOfBounds {
+            index,
+            len,
+            container,
+            span: span.clone(),
+        })
+    }
+}
+
+/// The error for indexing `container` with `key`, which isn't the right kind
+/// of value.
+fn bad_key(container: &Value, key: &Value, span: &Span) -> RuntimeError {
+    let (container, expected) = match container {
+        Value::Record(_) => ("ledgers", "names or strings"),
+        Value::String(_) => ("strings", "numbers"),
+        _ => ("lists", "numbers"),
+    };
+
+    RuntimeError::BadKey {
+        container,
+        expected,
+        found: key.describe(),
+        span: span.clone(),
+    }
+}
+
+/// Evaluates the index of a `get` or the key of a `set ... in`: a bare name
+/// that isn't a variable is a ledger key.
+fn run_key(ctx: &mut Context<'_>, e: ExprId) -> Result<Value, Unwind> {
+    if let Expr::Ident(i) = &ctx.arena[e] {
+        let k = (ctx.arena.style(e).font().map(str::to_string), i.clone());
+        if ctx.namespace.resolve(&k).is_none() {
+            return Ok(Value::String(i.clone()));
+        }
+    }
+
+    run_expr(ctx, e)
+}
+
+fn run_expr(ctx: &mut Context<'_>, e: ExprId) -> Result<Value, Unwind> {
+    let arena = ctx.arena;
+    let res = match &arena[e] {
+        Expr::Num(f) => Value::Num(*f),
+        Expr::String(s) => Value::String(s.clone()),
+        Expr::List(l) => Value::List(
+            l.iter()
+                .map(|&e| run_expr(ctx, e))
+                .collect::<Result<_, _>>()?,
         ),
-        Expr::Ident(i) => ctx
-            .namespace
-            .resolve(&i)
-            .expect("todo, err for unknown var"),
-        Expr::Assign(Assign { name, to }) => {
-            let val = run_expr(ctx, to.inner)?;
-            ctx.namespace.inner.insert(name.inner, val.clone());
+        Expr::Ident(i) => {
+            let k = (arena.style(e).font().map(str::to_string), i.clone());
+            ctx.check_access(&k, arena.span(e))?;
+            match ctx.namespace.resolve(&k) {
+                Some(v) => v,
+                // Procedures are values too.
+                None => match ctx.function(&k) {
+                    Some(p) => Value::Procedure(Rc::new(p.clone())),
+                    None => {
+                        let span = arena.span(e).clone();
+                        return Err(RuntimeError::Undefined { name: i.clone(), span }.into());
+                    }
+                },
+            }
+        }
+        Expr::Lambda { args, body } => Value::Procedure(Rc::new(Proc {
+            params: args.iter().map(key).collect(),
+            body: *body,
+            module: ctx.module.clone(),
+            captured: ctx.namespace.locals(),
+        })),
+        Expr::Assign { name, to } => {
+            let val = run_expr(ctx, *to)?;
+            ctx.define(name);
+            ctx.namespace.assign(key(name), val.clone());
             val
         }
-        Expr::Print(e) => {
-            let val = run_expr(ctx, (*e.inner).clone())?;
-            println!("{:?}", val);
+        Expr::Ledger(entries) => Value::Record(
+            entries
+                .iter()
+                .map(|(k, v)| Ok((k.inner.clone(), run_expr(ctx, *v)?)))
+                .collect::<Result<_, Unwind>>()?,
+        ),
+        Expr::Update { key: k, target, to } => {
+            let key_span = arena.span(*k);
+            let k = run_key(ctx, *k)?;
+            let val = run_expr(ctx, *to)?;
+            ctx.check_access(&key(target), &target.span)?;
+            let current = ctx.namespace.resolve(&key(target)).ok_or_else(|| {
+                RuntimeError::Undefined {
+                    name: target.inner.clone(),
+                    span: target.span.clone(),
+                }
+            })?;
+            let updated = match (current, k) {
+                (Value::Record(mut r), Value::String(k)) => {
+                    r.insert(k, val.clone());
+                    Value::Record(r)
+                }
+                (Value::List(mut l), Value::Num(n)) => {
+                    let at = position(n, l.len(), "a list", key_span)?;
+                    l[at] = val.clone();
+                    Value::List(l)
+                }
+                (c @ Value::Record(_), k) | (c @ Value::List(_), k) => {
+                    return Err(bad_key(&c, &k, key_span).into());
+                }
+                (other, _) => {
+                    let span = target.span.clone();
+                    return Err(RuntimeError::NotIndexable { found: other.describe(), span }.into());
+                }
+            };
+            ctx.namespace.assign(key(target), updated);
             val
         }
-        Expr::Block(Block { body, end }) => todo!(),
-        Expr::If(If {
+        Expr::Print(p) => {
+            let val = run_expr(ctx, *p)?;
+            println!("{}", val);
+            val
+        }
+        Expr::Interpolated(fragments) => {
+            let mut string = String::new();
+            for f in fragments {
+                match f {
+                    Fragment::Text(t) => string.push_str(&t.inner),
+                    Fragment::Expr(e) => string.push_str(&run_expr(ctx, *e)?.to_string()),
+                }
+            }
+
+            Value::String(string)
+        }
+        Expr::Block { body, end } => {
+            for &statement in body {
+                run_statement(ctx, statement)?;
+            }
+
+            match end {
+                Some(e) => run_expr(ctx, *e)?,
+                None => Value::Num(0.0),
+            }
+        }
+        Expr::If {
             cond,
             then,
             otherwise,
-        }) => {
-            if run_expr(ctx, cond.inner)?.truthy() {
-                run_expr(ctx, then.inner)?
+        } => {
+            if run_expr(ctx, *cond)?.truthy() {
+                run_expr(ctx, *then)?
             } else {
                 if let Some(o) = otherwise {
-                    run_expr(ctx, o.inner)?
+                    run_expr(ctx, *o)?
                 } else {
                     Value::Num(0.0)
                 }
             }
         }
-        Expr::UnOp(UnOp { op, expr }) => {
-            let val = run_expr(ctx, expr.inner)?;
-            Value::Num(match op.inner {
-                UnaryOperator::Neg => {
-                    if let Value::Num(n) = val {
-                        -n
-                    } else {
-                        todo!("gotto index with ints")
-                    }
+        Expr::When {
+            subject,
+            arms,
+            otherwise,
+        } => {
+            let subject = run_expr(ctx, *subject)?;
+
+            let mut picked = None;
+            for arm in arms {
+                let bindings = match matches(&arm.pattern.inner, &subject) {
+                    Some(b) => b,
+                    None => continue,
+                };
+
+                // What a pattern binds is only visible in its arm.
+                ctx.namespace.push(bindings);
+                let res = match arm.guard {
+                    Some(g) => match run_expr(ctx, g) {
+                        Ok(v) if v.falsey() => Ok(None),
+                        Ok(_) => run_expr(ctx, arm.body).map(Some),
+                        Err(e) => Err(e),
+                    },
+                    None => run_expr(ctx, arm.body).map(Some),
+                };
+                ctx.namespace.pop();
+
+                if let Some(v) = res? {
+                    picked = Some(v);
+                    break;
+                }
+            }
+
+            match (picked, otherwise) {
+                (Some(v), _) => v,
+                (None, Some(o)) => run_expr(ctx, *o)?,
+                (None, None) => {
+                    let span = arena.span(e).clone();
+                    return Err(RuntimeError::NoMatch { span }.into());
+                }
+            }
+        }
+        Expr::UnOp { op, expr } => {
+            let val = run_expr(ctx, *expr)?;
+            Value::Num(match (op.inner, val) {
+                (UnaryOperator::Neg, Value::Num(n)) => -n,
+                (UnaryOperator::Neg, other) => {
+                    let span = arena.span(e).clone();
+                    return Err(RuntimeError::Negate { found: other.describe(), span }.into());
                 }
-                UnaryOperator::Not => (!val.truthy()) as i32 as f64,
+                (UnaryOperator::Not, val) => (!val.truthy()) as i32 as f64,
             })
         }
-        Expr::BinOp(BinOp { lhs, op, rhs }) => {
-            let lhs = run_expr(ctx, lhs.inner)?;
-            let rhs = run_expr(ctx, rhs.inner)?;
-            match op.inner {
-                Add => match (lhs, rhs) {
-                    (Value::Num(a), Value::Num(b)) => Value::Num(a + b),
-                    (Value::List(mut l), b) => {
-                        l.push(b);
-                        Value::List(l)
-                    }
-                    _ => todo!("invalid combo"),
-                },
-                Sub => match (lhs, rhs) {
-                    (Value::Num(a), Value::Num(b)) => Value::Num(a - b),
-                    _ => todo!("invalid combo"),
-                },
-                Mul => match (lhs, rhs) {
-                    (Value::Num(a), Value::Num(b)) => Value::Num(a * b),
-                    _ => todo!("invalid combo"),
-                },
-                Div => match (lhs, rhs) {
-                    (Value::Num(a), Value::Num(b)) => Value::Num(a / b),
-                    _ => todo!("invalid combo"),
-                },
-                _ => todo!("other operator permutations"),
+        Expr::BinOp { lhs, op, rhs } => {
+            let lhs = run_expr(ctx, *lhs)?;
+            let rhs = run_expr(ctx, *rhs)?;
+            let mismatch = |lhs: &Value, rhs: &Value| -> Unwind {
+                RuntimeError::Operands {
+                    op: op.inner,
+                    lhs: lhs.describe(),
+                    rhs: rhs.describe(),
+                    span: arena.span(e).clone(),
+                }
+                .into()
+            };
+
+            match (op.inner, lhs, rhs) {
+                (Add, Value::Num(a), Value::Num(b)) => Value::Num(a + b),
+                (Add, Value::List(mut l), b) => {
+                    l.push(b);
+                    Value::List(l)
+                }
+                (Sub, Value::Num(a), Value::Num(b)) => Value::Num(a - b),
+                (Mul, Value::Num(a), Value::Num(b)) => Value::Num(a * b),
+                (Div, Value::Num(a), Value::Num(b)) => Value::Num(a / b),
+                (Add | Sub | Mul | Div, lhs, rhs) => return Err(mismatch(&lhs, &rhs)),
+                // Anything can be checked for equality; only numbers and
+                // strings have an order.
+                (Eq, lhs, rhs) => Value::Num((lhs == rhs) as i32 as f64),
+                (Lt | Gt, lhs, rhs) => {
+                    let ordering = match (&lhs, &rhs) {
+                        (Value::Num(a), Value::Num(b)) => a.partial_cmp(b),
+                        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                        _ => return Err(mismatch(&lhs, &rhs)),
+                    };
+                    let wanted = match op.inner {
+                        Lt => std::cmp::Ordering::Less,
+                        _ => std::cmp::Ordering::Greater,
+                    };
+                    Value::Num((ordering == Some(wanted)) as i32 as f64)
+                }
             }
         }
-        Expr::Call(Call { name, args }) => {
-            let (params, body) = ctx
-                .functions
-                .get(&name.inner)
-                .unwrap_or_else(|| todo!("unknown func"));
-            let body = body.clone();
-            let params = params.clone();
+        Expr::Logic { lhs, op, rhs } => {
+            let lhs = run_expr(ctx, *lhs)?.truthy();
+            let res = match op.inner {
+                LogicOp::And => lhs && run_expr(ctx, *rhs)?.truthy(),
+                LogicOp::Or => lhs || run_expr(ctx, *rhs)?.truthy(),
+                LogicOp::Xor => lhs != run_expr(ctx, *rhs)?.truthy(),
+            };
+
+            Value::Num(res as i32 as f64)
+        }
+        Expr::Call { name, args } => {
+            ctx.check_access(&key(name), &name.span)?;
+            // Variables that hold procedures shadow named procedures.
+            let Proc {
+                params,
+                body,
+                module,
+                captured,
+            } = match ctx.namespace.resolve(&key(name)) {
+                Some(Value::Procedure(p)) => (*p).clone(),
+                Some(v) => {
+                    return Err(RuntimeError::NotProcedure {
+                        name: name.inner.clone(),
+                        found: v.describe(),
+                        span: name.span.clone(),
+                    }
+                    .into())
+                }
+                None => ctx.function(&key(name)).cloned().ok_or_else(|| {
+                    RuntimeError::Undefined {
+                        name: name.inner.clone(),
+                        span: name.span.clone(),
+                    }
+                })?,
+            };
             let args = args
-                .into_iter()
-                .map(|a| run_expr(ctx, a.inner).unwrap_or_else(|_| todo!("rip")));
-            let bindings = params.into_iter().zip(args.into_iter()).collect();
+                .iter()
+                .map(|&a| run_expr(ctx, a))
+                .collect::<Result<Vec<_>, _>>()?;
+            let mut bindings = captured;
+            bindings.extend(params.into_iter().zip(args.into_iter()));
+            // The body only sees the globals and its own bindings, never the
+            // caller's locals.
+            let scopes = ctx.namespace.child.take();
             ctx.namespace.push(bindings);
-            let retval = run_expr(ctx, body)?;
-            ctx.namespace.pop();
-            retval
-        }
-        Expr::Get(Get { index, from }) => {
-            let from = run_expr(ctx, from.inner)?;
-            let index = run_expr(ctx, index.inner)?;
-            match from {
-                Value::List(l) => {
-                    if let Value::Num(n) = index {
-                        l.get(n as usize)
-                            .unwrap_or_else(|| todo!("out of bounds"))
-                            .clone()
-                    }
-                    // TODO: oob error
-                    else {
-                        todo!("gotto index with ints")
+            // Calls in the body resolve in the module the procedure is from.
+            let caller = std::mem::replace(&mut ctx.module, module);
+            let retval = run_expr(ctx, body);
+            ctx.module = caller;
+            ctx.namespace.child = scopes;
+
+            // `stop` and `skip ahead` don't reach loops outside the procedure.
+            match retval {
+                Ok(v) | Err(Unwind::Return(v, _)) => v,
+                Err(e) => return Err(e.into_error().into()),
+            }
+        }
+        Expr::Get { index, from } => {
+            let (index_span, from_span) = (arena.span(*index), arena.span(*from));
+            let from = run_expr(ctx, *from)?;
+            let index = run_key(ctx, *index)?;
+            match (from, index) {
+                (Value::Record(r), Value::String(k)) => match r.get(&k) {
+                    Some(v) => v.clone(),
+                    None => {
+                        let span = index_span.clone();
+                        return Err(RuntimeError::MissingKey { key: k, span }.into());
                     }
+                },
+                (Value::List(l), Value::Num(n)) => {
+                    l[position(n, l.len(), "a list", index_span)?].clone()
+                }
+                // A string's items are its characters, each a string of its own.
+                (Value::String(s), Value::Num(n)) => {
+                    let at = position(n, s.chars().count(), "a string", index_span)?;
+                    Value::String(s.chars().nth(at).into_iter().collect())
+                }
+                (c @ Value::Record(_), k) | (c @ Value::List(_), k) | (c @ Value::String(_), k) => {
+                    return Err(bad_key(&c, &k, index_span).into());
+                }
+                (other, _) => {
+                    let span = from_span.clone();
+                    return Err(RuntimeError::NotIndexable { found: other.describe(), span }.into());
                 }
-                Value::String(_s) => todo!("return substring of single char"),
-                _ => todo!("return error for non lists"),
             }
         }
     };
     Ok(res)
 }
 
-fn run_statement(ctx: &mut Context, s: Statement) -> Result<(), ()> {
-    match s {
+fn run_statement(ctx: &mut Context<'_>, s: StatementId) -> Result<(), Unwind> {
+    let arena = ctx.arena;
+    match &arena[s] {
         Statement::Expr(e) => {
-            run_expr(ctx, e.inner)?;
+            run_expr(ctx, *e)?;
         }
-        Statement::While(While { cond, body }) => {
-            while run_expr(ctx, cond.inner.clone())?.falsey() {
-                run_expr(ctx, body.inner.clone())?;
+        Statement::While { cond, body } => {
+            while run_expr(ctx, *cond)?.truthy() {
+                if let Flow::Stop = run_body(ctx, *body)? {
+                    break;
+                }
+            }
+        }
+        Statement::Until { body, cond } => loop {
+            if let Flow::Stop = run_body(ctx, *body)? {
+                break;
+            }
+
+            if run_expr(ctx, *cond)?.truthy() {
+                break;
             }
+        },
+        Statement::Procedure { name, args, body } => {
+            let proc = Proc {
+                params: args.iter().map(key).collect(),
+                body: *body,
+                module: ctx.module.clone(),
+                captured: HashMap::new(),
+            };
+            ctx.define(name);
+            ctx.functions
+                .entry(ctx.module.clone())
+                .or_default()
+                .insert(key(name), proc);
         }
-        Statement::Procedure(Procedure { name, args, body }) => {
-            let args = args.into_iter().map(|a| a.inner).collect::<Vec<_>>();
-            ctx.functions.insert(name.inner, (args, body.inner));
+        Statement::Import(i) => {
+            import(&mut ctx.functions, &ctx.interfaces, &ctx.module, i)?;
         }
-        Statement::For(For { name, list, body }) => {
-            let list = run_expr(ctx, list.inner)?;
-            match list {
-                Value::List(l) => {
-                    todo!("bind name and execute body with updated namespace")
+        Statement::For { name, list, body } => {
+            let items = match run_expr(ctx, *list)? {
+                Value::List(l) => l,
+                Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+                Value::Record(r) => r.into_iter().map(|(k, _)| Value::String(k)).collect(),
+                other => {
+                    let span = arena.span(*list).clone();
+                    return Err(RuntimeError::Loop { found: other.describe(), span }.into());
                 }
-                Value::String(_s) => todo!("iterate over substring of single char"),
-                _ => todo!("return error for non lists"),
             };
+
+            for item in items {
+                ctx.namespace
+                    .push(std::iter::once((key(name), item)).collect());
+                let flow = run_body(ctx, *body);
+                ctx.namespace.pop();
+
+                if let Flow::Stop = flow? {
+                    break;
+                }
+            }
+        }
+        Statement::Section { body, .. } => {
+            // What's in a section is still at the top level.
+            for &statement in body {
+                ctx.statement = Some(arena.span(statement).clone());
+                run_statement(ctx, statement)?;
+            }
         }
+        Statement::Return { value } => {
+            let val = run_expr(ctx, *value)?;
+            return Err(Unwind::Return(val, arena.span(s).clone()));
+        }
+        Statement::Break => return Err(Unwind::Break(arena.span(s).clone())),
+        Statement::Continue => return Err(Unwind::Continue(arena.span(s).clone())),
     };
     Ok(())
 }
 
-pub fn run_program(program: Vec<Statement>) -> Result<(), ()> {
-    let mut context = Context::default();
+pub fn run_program(program: ast::Module) -> Result<(), RuntimeError> {
+    run_modules(program, HashMap::new())
+}
+
+/// Runs `program` with the modules it (transitively) imports, keyed by the
+/// sources in their `import` statements.
+///
+/// Only procedures are imported; the rest of an imported module doesn't run.
+pub fn run_modules(
+    program: ast::Module,
+    modules: HashMap<String, ast::Module>,
+) -> Result<(), RuntimeError> {
+    let regions = modules
+        .iter()
+        .map(|(source, m)| (Some(source.clone()), privacy::regions(m)))
+        .chain(Some((None, privacy::regions(&program))))
+        .collect();
+    let interfaces = modules
+        .iter()
+        .map(|(source, m)| (source.clone(), m.interface()))
+        .collect();
+
+    // Everything is lowered up front; running never clones the program.
+    let mut arena = Arena::new();
+    let program = arena.lower(program.body);
+    let modules = modules
+        .into_iter()
+        .map(|(source, m)| (source, arena.lower(m.body)))
+        .collect();
+
+    let mut context = Context {
+        modules,
+        interfaces,
+        regions,
+        ..Context::new(&arena)
+    };
+    link(&mut context)?;
 
     for statement in program {
-        run_statement(&mut context, statement)?;
+        context.statement = Some(arena.span(statement).clone());
+        run_statement(&mut context, statement).map_err(Unwind::into_error)?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use abogado_passes::test_utils::{bordered, cado, docx, underlined};
+    use docx_rs::RunProperty;
+
+    fn parse(src: &str) -> ast::Module {
+        cado("test.cado", src)
+    }
+
+    /// Runs `src` (which can't import anything) and gives back what the
+    /// variable `name` holds at the end.
+    fn value(src: &str, name: &str) -> Value {
+        let mut arena = Arena::new();
+        let program = arena.lower(parse(src).body);
+        let mut ctx = Context::new(&arena);
+        for statement in program {
+            ctx.statement = Some(arena.span(statement).clone());
+            if let Err(e) = run_statement(&mut ctx, statement) {
+                panic!("{}", e.into_error());
+            }
+        }
+
+        ctx.namespace.resolve(&(None, name.to_string())).unwrap()
+    }
+
+    const SECTIONS: &str = "\
+set greeting to \"hi\".
+§ Setup
+set x to 1.
+§§ Helpers
+procedure bump takes n does n + 1.
+§ Main
+set y to do bump using x.
+§ Later
+do missing using y.
+";
+
+    #[test]
+    fn runs_a_section_with_its_prelude() {
+        let module = parse(SECTIONS);
+        assert!(matches!(
+            run_program(module.clone()),
+            Err(RuntimeError::Undefined { name, .. }) if name == "missing"
+        ));
+
+        // `Main` needs `x` and `bump` from the sections before it; `Later`
+        // doesn't run.
+        let main = ast::entry_point(&module, "Main").unwrap();
+        assert_eq!(run_program(main), Ok(()));
+
+        let helpers = ast::entry_point(&module, "Setup/Helpers").unwrap();
+        assert_eq!(run_program(helpers), Ok(()));
+    }
+
+    #[test]
+    fn bordered_procedures_cant_be_imported() {
+        let exported = underlined();
+        let private = RunProperty {
+            text_border: bordered().text_border,
+            ..exported.clone()
+        };
+        let lib = docx(
+            "lib.docx",
+            &[
+                &[
+                    ("procedure ", bordered()),
+                    ("hidden", private),
+                    (" takes does 1.", bordered()),
+                ],
+                &[
+                    ("procedure ", RunProperty::new()),
+                    ("shown", exported),
+                    (" takes does 2.", RunProperty::new()),
+                ],
+            ],
+        );
+        let border = lib.interface().definition("hidden").unwrap().border.clone().unwrap();
+        let modules = || vec![("lib.docx".to_string(), lib.clone())].into_iter().collect();
+
+        let by_name = parse("import hidden from \"lib.docx\".");
+        assert!(matches!(
+            run_modules(by_name, modules()),
+            Err(RuntimeError::Private { name, border: b, .. }) if name == "hidden" && b == border
+        ));
+
+        // A whole-module import doesn't bring it in.
+        let whole = parse("import \"lib.docx\".\ndo shown using.\ndo hidden using.");
+        assert!(matches!(
+            run_modules(whole, modules()),
+            Err(RuntimeError::Undefined { name, .. }) if name == "hidden"
+        ));
+    }
+
+    fn error(src: &str) -> RuntimeError {
+        run_program(parse(src)).unwrap_err()
+    }
+
+    #[test]
+    fn bad_lookups_are_errors() {
+        let src = "set prices to a ledger where apples is 3.\nget pears from prices.";
+        let at = src.find("pears").unwrap();
+        match error(src) {
+            RuntimeError::MissingKey { key, span } => {
+                assert_eq!(key, "pears");
+                assert_eq!(span, Span::from(at..at + 5));
+            }
+            other => panic!("expected a missing key, got {:?}", other),
+        }
+        assert!(matches!(
+            error("set l to [1, 2].\nget 2 from l."),
+            RuntimeError::OutOfBounds { len: 2, .. }
+        ));
+        assert!(matches!(
+            error("set l to [1, 2].\nget -1 from l."),
+            RuntimeError::OutOfBounds { len: 2, .. }
+        ));
+        assert!(matches!(
+            error("set l to [1, 2].\nget 0.5 from l."),
+            RuntimeError::OutOfBounds { len: 2, .. }
+        ));
+        assert!(matches!(
+            error("set l to [1, 2].\nget “one” from l."),
+            RuntimeError::BadKey { container: "lists", found: "a string", .. }
+        ));
+        assert!(matches!(
+            error("set prices to an empty ledger.\nget 1 from prices."),
+            RuntimeError::BadKey { container: "ledgers", found: "a number", .. }
+        ));
+        assert!(matches!(
+            error("get 1 from 5."),
+            RuntimeError::NotIndexable { found: "a number", .. }
+        ));
+
+        assert_eq!(run_program(parse("set l to [1, 2].\nemit get 1 from l!")), Ok(()));
+    }
+
+    #[test]
+    fn strings_are_indexed_by_character() {
+        // Looking the character up in an empty ledger shows what it is.
+        let src = "set s to “héllo”.\nset r to an empty ledger.\nget (get 1 from s) from r.";
+        assert!(matches!(
+            error(src),
+            RuntimeError::MissingKey { key, .. } if key == "é"
+        ));
+
+        assert!(matches!(
+            error("set s to “hi”.\nget 2 from s."),
+            RuntimeError::OutOfBounds { len: 2, container: "a string", .. }
+        ));
+        assert!(matches!(
+            error("set s to “hi”.\nget “h” from s."),
+            RuntimeError::BadKey { container: "strings", found: "a string", .. }
+        ));
+
+        assert_eq!(run_program(parse("set s to “hi”.\nemit get 0 from s!")), Ok(()));
+    }
+
+    #[test]
+    fn until_loops_run_until_a_comparison_holds() {
+        let src = "set x to 0.\nkeep doing (set x to x + 1) until x > 10.";
+        assert_eq!(value(src, "x"), Value::Num(11.0));
+
+        let src = "set x to 5.\nkeep doing (set x to x - 1) until x = 0.";
+        assert_eq!(value(src, "x"), Value::Num(0.0));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(value("set b to 1 < 2.", "b"), Value::Num(1.0));
+        assert_eq!(value("set b to 2 < 1.", "b"), Value::Num(0.0));
+        assert_eq!(value("set b to “pear” > “apple”.", "b"), Value::Num(1.0));
+        assert_eq!(value("set b to “a” = “a”.", "b"), Value::Num(1.0));
+        assert_eq!(value("set b to [1, 2] = [1, 2].", "b"), Value::Num(1.0));
+        assert_eq!(value("set b to “1” = 1.", "b"), Value::Num(0.0));
+
+        let err = error("set b to “1” < 2.");
+        assert!(matches!(
+            err,
+            RuntimeError::Operands { op: Lt, lhs: "a string", rhs: "a number", .. }
+        ));
+        assert_eq!(err.to_string(), "can't compare a string and a number");
+    }
+
+    #[test]
+    fn interpolated_strings_show_their_holes() {
+        assert_eq!(
+            value("set x to 1.\nset s to “x is {x + 1}, {{x}} isn't”.", "s"),
+            Value::String("x is 2, {x} isn't".to_string()),
+        );
+        assert_eq!(
+            value("set l to [“a”, 2].\nset s to “{l} and {“}”}”.", "s"),
+            Value::String("[“a”, 2] and }".to_string()),
+        );
+    }
+
+    #[test]
+    fn guards_pick_between_arms() {
+        let sign = |n: &str| {
+            let src = format!(
+                "set n to {}.\nset s to when n is 0 then “zero”, \
+                 is m where m < 0 then “negative”, otherwise “positive”.",
+                n
+            );
+            value(&src, "s")
+        };
+
+        assert_eq!(sign("0 - 3"), Value::String("negative".into()));
+        assert_eq!(sign("0"), Value::String("zero".into()));
+        assert_eq!(sign("3"), Value::String("positive".into()));
+    }
+
+    #[test]
+    fn bad_operands_are_errors() {
+        let err = error("set x to “a” - 1.");
+        assert!(matches!(
+            err,
+            RuntimeError::Operands { op: Sub, lhs: "a string", rhs: "a number", .. }
+        ));
+        assert_eq!(err.to_string(), "can't subtract a number from a string");
+
+        assert!(matches!(
+            error("set x to 1 + “a”."),
+            RuntimeError::Operands { op: Add, lhs: "a number", rhs: "a string", .. }
+        ));
+        assert!(matches!(
+            error("set x to [1] * 2."),
+            RuntimeError::Operands { op: Mul, lhs: "a list", .. }
+        ));
+        assert!(matches!(
+            error("set x to 1 / an empty ledger."),
+            RuntimeError::Operands { op: Div, rhs: "a ledger", .. }
+        ));
+        assert!(matches!(
+            error("set x to -“a”."),
+            RuntimeError::Negate { found: "a string", .. }
+        ));
+
+        // Adding to a list still works.
+        let list = Value::List(vec![Value::Num(1.0), Value::Num(2.0)]);
+        assert_eq!(value("set l to [1] + 2.", "l"), list);
+    }
+
+    #[test]
+    fn looping_over_a_number_is_an_error() {
+        let src = "run emit x! for x in 5.";
+        let at = src.find('5').unwrap();
+        match error(src) {
+            RuntimeError::Loop { found, span } => {
+                assert_eq!(found, "a number");
+                assert_eq!(span, Span::from(at..at + 1));
+            }
+            other => panic!("expected a loop error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn give_back_leaves_nested_blocks_and_loops() {
+        let src = "procedure firstbig takes l does (\n\
+                   run (is x > 2 ? (give back x)) for x in l.\n\
+                   0\n\
+                   ).\n\
+                   set big to do firstbig using [1, 5, 3].\n\
+                   set none to do firstbig using [1, 2].";
+        assert_eq!(value(src, "big"), Value::Num(5.0));
+        assert_eq!(value(src, "none"), Value::Num(0.0));
+    }
+
+    #[test]
+    fn stop_and_skip_ahead_leave_nested_blocks() {
+        let src = "set total to 0.\n\
+                   run (is x > 2 ? (stop). set total to total + x) for x in [1, 2, 3, 4].";
+        assert_eq!(value(src, "total"), Value::Num(3.0));
+
+        let src = "set total to 0.\n\
+                   run (is x = 2 ? (skip ahead). set total to total + x) for x in [1, 2, 3].";
+        assert_eq!(value(src, "total"), Value::Num(4.0));
+
+        // Only the innermost loop stops.
+        let src = "set total to 0.\n\
+                   run (run (is y > 1 ? (stop). set total to total + 1) for y in [1, 2, 3]) \
+                   for x in [1, 2].";
+        assert_eq!(value(src, "total"), Value::Num(2.0));
+    }
+
+    #[test]
+    fn stop_doesnt_leave_a_procedure() {
+        let src = "procedure halt takes does (stop).\nrun (do halt using) for x in [1].";
+        assert!(matches!(error(src), RuntimeError::OutsideLoop { what: "stop", .. }));
+    }
+
+    #[test]
+    fn bad_updates_are_errors() {
+        assert!(matches!(
+            error("set l to [1, 2].\nset 5 in l to 3."),
+            RuntimeError::OutOfBounds { len: 2, .. }
+        ));
+        assert!(matches!(
+            error("set prices to an empty ledger.\nset [1] in prices to 3."),
+            RuntimeError::BadKey { container: "ledgers", found: "a list", .. }
+        ));
+        assert!(matches!(
+            error("set n to 1.\nset 0 in n to 3."),
+            RuntimeError::NotIndexable { found: "a number", .. }
+        ));
+
+        // Ledgers get new keys when they're set.
+        let src = "set prices to an empty ledger.\nset pears in prices to 3.\n\
+                   emit get pears from prices!";
+        assert_eq!(run_program(parse(src)), Ok(()));
+    }
+
+    #[test]
+    fn procedures_dont_see_the_callers_locals() {
+        assert!(matches!(
+            error("procedure peek takes does x.\nrun (do peek using) for x in [1]"),
+            RuntimeError::Undefined { name, .. } if name == "x"
+        ));
+        assert!(matches!(
+            error("set f to a procedure that does z.\nrun (do f using) for z in [1]"),
+            RuntimeError::Undefined { name, .. } if name == "z"
+        ));
+
+        // `clobber`'s `y` is its own, so the loop's `y` is still 1.
+        let src = "procedure clobber takes does (set y to 5).\n\
+                   set total to 0.\n\
+                   run (set y to 1. do clobber using. set total to y) for i in [1]\n\
+                   when total is 1 then 0, otherwise do fail using.";
+        assert_eq!(run_program(parse(src)), Ok(()));
+
+        // Captured values are still there.
+        let src = "set g to 0.\nrun (set g to a procedure that does i) for i in [1]\n\
+                   set r to do g using.\n\
+                   when r is 1 then 0, otherwise do fail using.";
+        assert_eq!(run_program(parse(src)), Ok(()));
+    }
+
+    #[test]
+    fn calling_a_non_procedure_is_an_error() {
+        assert!(matches!(
+            error("set x to 1.\ndo x using."),
+            RuntimeError::NotProcedure { name, found: "a number", .. } if name == "x"
+        ));
+    }
+}